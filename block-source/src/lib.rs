@@ -0,0 +1,126 @@
+//! Abstraction over the source of the NEAR Lake data.
+//!
+//! By default the data is read from the NEAR Lake S3 bucket with `near_lake_framework`.
+//! For the offline development and testing the same data can be read from a local directory
+//! following the NEAR Lake bucket layout:
+//!
+//! ```text
+//! <path>/000000123456/block.json
+//! <path>/000000123456/shard_0.json
+//! <path>/000000123456/shard_1.json
+//! ...
+//! ```
+//!
+//! Example of usage:
+//! ```ignore
+//! let config = block_source::BlockSourceConfig::Local {
+//!     path: std::path::PathBuf::from("./lake-data"),
+//!     start_block_height: 123456,
+//! };
+//! let (handle, stream) = block_source::streamer(config);
+//! ```
+mod local;
+
+pub use local::LocalBlockFetcher;
+
+/// The directory name of the block in the NEAR Lake layout (block height zero-padded to 12 digits)
+pub fn block_dir_name(block_height: near_indexer_primitives::types::BlockHeight) -> String {
+    format!("{:0>12}", block_height)
+}
+
+pub enum BlockSourceConfig {
+    /// Read the data from the NEAR Lake S3 bucket
+    Lake(near_lake_framework::LakeConfig),
+    /// Read the data from the local directory following the NEAR Lake layout
+    Local {
+        path: std::path::PathBuf,
+        start_block_height: near_indexer_primitives::types::BlockHeight,
+    },
+}
+
+/// Starts streaming the blocks from the configured source.
+/// Mirrors `near_lake_framework::streamer`: returns the handle of the task reading the data
+/// and the receiver of the `StreamerMessage`s.
+pub fn streamer(
+    config: BlockSourceConfig,
+) -> (
+    tokio::task::JoinHandle<anyhow::Result<()>>,
+    tokio::sync::mpsc::Receiver<near_indexer_primitives::StreamerMessage>,
+) {
+    match config {
+        BlockSourceConfig::Lake(lake_config) => near_lake_framework::streamer(lake_config),
+        BlockSourceConfig::Local {
+            path,
+            start_block_height,
+        } => local::streamer(path, start_block_height),
+    }
+}
+
+/// Fetches the separate pieces of the block data on demand
+#[async_trait::async_trait]
+pub trait BlockFetcher: Send + Sync {
+    async fn fetch_block(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+    ) -> anyhow::Result<near_indexer_primitives::views::BlockView>;
+
+    async fn fetch_shard(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+        shard_id: near_indexer_primitives::types::ShardId,
+    ) -> anyhow::Result<near_indexer_primitives::IndexerShard>;
+}
+
+pub struct S3BlockFetcher {
+    s3_client: near_lake_framework::s3_fetchers::LakeS3Client,
+    s3_bucket_name: String,
+}
+
+impl S3BlockFetcher {
+    pub fn new(s3_config: aws_sdk_s3::Config, s3_bucket_name: String) -> Self {
+        Self {
+            s3_client: near_lake_framework::s3_fetchers::LakeS3Client::new(
+                aws_sdk_s3::Client::from_conf(s3_config),
+            ),
+            s3_bucket_name,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BlockFetcher for S3BlockFetcher {
+    async fn fetch_block(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+    ) -> anyhow::Result<near_indexer_primitives::views::BlockView> {
+        near_lake_framework::s3_fetchers::fetch_block_or_retry(
+            &self.s3_client,
+            &self.s3_bucket_name,
+            block_height,
+        )
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to fetch block {} from S3: {}", block_height, err))
+    }
+
+    async fn fetch_shard(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+        shard_id: near_indexer_primitives::types::ShardId,
+    ) -> anyhow::Result<near_indexer_primitives::IndexerShard> {
+        near_lake_framework::s3_fetchers::fetch_shard_or_retry(
+            &self.s3_client,
+            &self.s3_bucket_name,
+            block_height,
+            shard_id,
+        )
+        .await
+        .map_err(|err| {
+            anyhow::anyhow!(
+                "Failed to fetch shard {} of block {} from S3: {}",
+                shard_id,
+                block_height,
+                err
+            )
+        })
+    }
+}
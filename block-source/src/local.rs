@@ -0,0 +1,234 @@
+use crate::block_dir_name;
+
+/// How often to look for the new blocks once all the available blocks are streamed
+/// and to retry the blocks which are not completely written yet
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// The same default as `near_lake_framework` uses for the blocks preload pool
+const CHANNEL_SIZE: usize = 100;
+/// Number of the heights after the last streamed block checked for the new blocks on every poll,
+/// so the directory with all the blocks is not listed every time
+const PROBE_WINDOW: u64 = 100;
+/// How often the directory is listed to find the blocks after a gap longer than `PROBE_WINDOW`
+const LIST_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Max number of the block heights taken from one listing of the directory
+const LIST_BATCH_SIZE: usize = 1000;
+
+pub(crate) fn streamer(
+    path: std::path::PathBuf,
+    start_block_height: near_indexer_primitives::types::BlockHeight,
+) -> (
+    tokio::task::JoinHandle<anyhow::Result<()>>,
+    tokio::sync::mpsc::Receiver<near_indexer_primitives::StreamerMessage>,
+) {
+    let (sender, receiver) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
+    let handle = tokio::spawn(start(
+        LocalBlockFetcher::new(path),
+        start_block_height,
+        sender,
+    ));
+    (handle, receiver)
+}
+
+/// Streams the blocks in order and keeps polling the directory for the new blocks
+/// the same way `near_lake_framework` follows the S3 bucket
+async fn start(
+    fetcher: LocalBlockFetcher,
+    start_block_height: near_indexer_primitives::types::BlockHeight,
+    sender: tokio::sync::mpsc::Sender<near_indexer_primitives::StreamerMessage>,
+) -> anyhow::Result<()> {
+    tracing::info!(
+        "Streaming blocks from {} starting from {}",
+        fetcher.path.display(),
+        start_block_height
+    );
+    let mut next_block_height = start_block_height;
+    let mut listed_at: Option<std::time::Instant> = None;
+    loop {
+        let mut block_heights = fetcher
+            .probe_block_heights(next_block_height, PROBE_WINDOW)
+            .await;
+        if block_heights.is_empty()
+            && listed_at.map_or(true, |listed_at| listed_at.elapsed() >= LIST_INTERVAL)
+        {
+            block_heights = fetcher
+                .list_block_heights(next_block_height, LIST_BATCH_SIZE)
+                .await?;
+            listed_at = Some(std::time::Instant::now());
+        }
+        // The next blocks are looked for right away unless there are no new blocks
+        // or the block is not complete yet
+        let mut poll_later = block_heights.is_empty();
+        for block_height in block_heights {
+            // The block directory can be listed while its files are still being written,
+            // such a block is read again on the next poll
+            let streamer_message = match fetcher.fetch_streamer_message(block_height).await {
+                Ok(streamer_message) => streamer_message,
+                Err(err) => {
+                    tracing::warn!(
+                        "Block {} is not complete yet, retrying: {:?}",
+                        block_height,
+                        err
+                    );
+                    poll_later = true;
+                    break;
+                }
+            };
+            if sender.send(streamer_message).await.is_err() {
+                // The receiver is dropped, nobody is interested in the blocks anymore
+                return Ok(());
+            }
+            next_block_height = block_height + 1;
+        }
+        if poll_later {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Reads the NEAR Lake data from the local directory
+pub struct LocalBlockFetcher {
+    path: std::path::PathBuf,
+}
+
+impl LocalBlockFetcher {
+    pub fn new(path: std::path::PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Returns the heights of the blocks available in the directory among the `window` heights
+    /// starting from the given block height
+    async fn probe_block_heights(
+        &self,
+        start_block_height: near_indexer_primitives::types::BlockHeight,
+        window: u64,
+    ) -> Vec<near_indexer_primitives::types::BlockHeight> {
+        let mut block_heights = vec![];
+        for block_height in start_block_height..start_block_height.saturating_add(window) {
+            if tokio::fs::metadata(self.path.join(block_dir_name(block_height)))
+                .await
+                .is_ok()
+            {
+                block_heights.push(block_height);
+            }
+        }
+        block_heights
+    }
+
+    /// Returns the sorted heights of at most `limit` lowest blocks available in the directory
+    /// starting from the given block height.
+    /// The directory entries come unordered, so only the lowest `limit` heights are kept
+    /// while listing instead of sorting all the blocks on disk
+    async fn list_block_heights(
+        &self,
+        start_block_height: near_indexer_primitives::types::BlockHeight,
+        limit: usize,
+    ) -> anyhow::Result<Vec<near_indexer_primitives::types::BlockHeight>> {
+        let mut block_heights = std::collections::BinaryHeap::with_capacity(limit + 1);
+        let mut entries = tokio::fs::read_dir(&self.path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(block_height) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u64>().ok())
+            {
+                if block_height >= start_block_height {
+                    block_heights.push(block_height);
+                    if block_heights.len() > limit {
+                        block_heights.pop();
+                    }
+                }
+            }
+        }
+        Ok(block_heights.into_sorted_vec())
+    }
+
+    async fn fetch_streamer_message(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+    ) -> anyhow::Result<near_indexer_primitives::StreamerMessage> {
+        let block = self.read_block(block_height).await?;
+        let mut shards = vec![];
+        for shard_id in 0..block.chunks.len() as u64 {
+            shards.push(self.read_shard(block_height, shard_id).await?);
+        }
+        Ok(near_indexer_primitives::StreamerMessage { block, shards })
+    }
+
+    async fn read_block(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+    ) -> anyhow::Result<near_indexer_primitives::views::BlockView> {
+        let path = self
+            .path
+            .join(block_dir_name(block_height))
+            .join("block.json");
+        let content = tokio::fs::read(&path)
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to read {}: {}", path.display(), err))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    async fn read_shard(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+        shard_id: near_indexer_primitives::types::ShardId,
+    ) -> anyhow::Result<near_indexer_primitives::IndexerShard> {
+        let path = self
+            .path
+            .join(block_dir_name(block_height))
+            .join(format!("shard_{}.json", shard_id));
+        let content = tokio::fs::read(&path)
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to read {}: {}", path.display(), err))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::BlockFetcher for LocalBlockFetcher {
+    async fn fetch_block(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+    ) -> anyhow::Result<near_indexer_primitives::views::BlockView> {
+        self.read_block(block_height).await
+    }
+
+    async fn fetch_shard(
+        &self,
+        block_height: near_indexer_primitives::types::BlockHeight,
+        shard_id: near_indexer_primitives::types::ShardId,
+    ) -> anyhow::Result<near_indexer_primitives::IndexerShard> {
+        self.read_shard(block_height, shard_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn block_heights_are_looked_up_from_the_start() {
+        let path =
+            std::env::temp_dir().join(format!("read-rpc-local-blocks-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        for block_height in [7, 3, 12, 5, 9, 1] {
+            std::fs::create_dir_all(path.join(block_dir_name(block_height))).unwrap();
+        }
+        std::fs::write(path.join("README"), b"").unwrap();
+        let fetcher = LocalBlockFetcher::new(path.clone());
+
+        assert_eq!(
+            fetcher.list_block_heights(4, 3).await.unwrap(),
+            vec![5, 7, 9]
+        );
+        assert_eq!(
+            fetcher.list_block_heights(8, 10).await.unwrap(),
+            vec![9, 12]
+        );
+        assert!(fetcher.list_block_heights(13, 10).await.unwrap().is_empty());
+
+        assert_eq!(fetcher.probe_block_heights(4, 4).await, vec![5, 7]);
+        assert_eq!(fetcher.probe_block_heights(10, 2).await, Vec::<u64>::new());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
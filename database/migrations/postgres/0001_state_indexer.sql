@@ -0,0 +1,74 @@
+-- The tables mirror the `state_indexer` keyspace of ScyllaDB.
+-- The historical values are looked up with
+-- `WHERE ... AND block_height <= $N ORDER BY block_height DESC LIMIT 1`,
+-- so the primary keys end with the `block_height` to serve it with the index scan.
+-- `data_value` is NULL when the value was deleted at that block.
+CREATE SCHEMA IF NOT EXISTS state_indexer;
+
+CREATE TABLE IF NOT EXISTS state_indexer.state_changes_data (
+    account_id TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    data_key TEXT NOT NULL,
+    data_value BYTEA,
+    PRIMARY KEY (account_id, data_key, block_height)
+);
+
+CREATE TABLE IF NOT EXISTS state_indexer.state_changes_access_key (
+    account_id TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    data_key TEXT NOT NULL,
+    data_value BYTEA,
+    PRIMARY KEY (account_id, data_key, block_height)
+);
+
+CREATE TABLE IF NOT EXISTS state_indexer.state_changes_contract (
+    account_id TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    data_value BYTEA,
+    PRIMARY KEY (account_id, block_height)
+);
+
+CREATE TABLE IF NOT EXISTS state_indexer.state_changes_account (
+    account_id TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    data_value BYTEA,
+    PRIMARY KEY (account_id, block_height)
+);
+
+CREATE TABLE IF NOT EXISTS state_indexer.blocks (
+    block_hash TEXT PRIMARY KEY,
+    block_height BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS state_indexer.chunks (
+    chunk_hash TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    shard_id BIGINT NOT NULL,
+    stored_at_block_height BIGINT NOT NULL,
+    PRIMARY KEY (chunk_hash, block_height)
+);
+
+CREATE INDEX IF NOT EXISTS chunks_block_height ON state_indexer.chunks (block_height);
+
+CREATE TABLE IF NOT EXISTS state_indexer.account_state (
+    account_id TEXT NOT NULL,
+    data_key TEXT NOT NULL,
+    PRIMARY KEY (account_id, data_key)
+);
+
+-- All the state changes of the block are written in a single statement,
+-- so there is no need to track the partially written blocks like in ScyllaDB
+CREATE TABLE IF NOT EXISTS state_indexer.state_changes_by_block (
+    block_height BIGINT PRIMARY KEY,
+    block_hash TEXT NOT NULL,
+    state_changes BYTEA[] NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS state_indexer.meta (
+    indexer_id TEXT PRIMARY KEY,
+    last_processed_block_height BIGINT NOT NULL
+);
@@ -0,0 +1,22 @@
+-- The tables mirror the `tx_indexer` keyspace of ScyllaDB
+CREATE SCHEMA IF NOT EXISTS tx_indexer;
+
+CREATE TABLE IF NOT EXISTS tx_indexer.transactions_details (
+    transaction_hash TEXT NOT NULL,
+    block_height BIGINT NOT NULL,
+    account_id TEXT NOT NULL,
+    transaction_details BYTEA NOT NULL,
+    PRIMARY KEY (transaction_hash, block_height)
+);
+
+CREATE TABLE IF NOT EXISTS tx_indexer.receipts_map (
+    receipt_id TEXT PRIMARY KEY,
+    block_height BIGINT NOT NULL,
+    parent_transaction_hash TEXT NOT NULL,
+    shard_id BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tx_indexer.meta (
+    indexer_id TEXT PRIMARY KEY,
+    last_processed_block_height BIGINT NOT NULL
+);
@@ -0,0 +1,162 @@
+// Selection of the accounts the state-indexer stores the state of.
+//
+// The app-specific deployments only care about a handful of contracts, so the state-indexer
+// can skip the state changes of the other accounts. Both the state-indexer and the rpc-server
+// flatten `AccountFilterArgs` into their options, so the same environment variables configure
+// which accounts are indexed and the rpc-server reports the accounts which are not
+// instead of responding as if they didn't exist. The changes of the blocks are incomplete
+// with the filter, so the rpc-server proxies the changes requests to near-rpc.
+
+#[derive(Debug, Clone)]
+pub enum AccountPattern {
+    /// `alice.near`
+    Exact(String),
+    /// `*.mycorp.near` matches all the sub-accounts of `mycorp.near` but not `mycorp.near` itself
+    Suffix(String),
+    /// `re:^app-[0-9]+\.near$`
+    Regex(regex::Regex),
+}
+
+impl std::str::FromStr for AccountPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(regex) = s.strip_prefix("re:") {
+            return Ok(Self::Regex(regex::Regex::new(regex)?));
+        }
+        if let Some(suffix) = s.strip_prefix('*') {
+            if !suffix.starts_with('.') {
+                anyhow::bail!("Expected `*.<account_id>`, got {}", s);
+            }
+            return Ok(Self::Suffix(suffix.to_string()));
+        }
+        if s.is_empty() {
+            anyhow::bail!("Empty account pattern");
+        }
+        Ok(Self::Exact(s.to_string()))
+    }
+}
+
+impl AccountPattern {
+    pub fn matches(&self, account_id: &str) -> bool {
+        match self {
+            Self::Exact(exact) => account_id == exact,
+            Self::Suffix(suffix) => account_id.ends_with(suffix.as_str()),
+            Self::Regex(regex) => regex.is_match(account_id),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AccountFilterArgs {
+    /// Index only the state of the accounts matching any of the comma-separated patterns,
+    /// all the accounts are indexed if empty.
+    /// The pattern is an account id (`alice.near`), all the sub-accounts (`*.mycorp.near`)
+    /// or a regular expression without commas (`re:^app-[0-9]+\.near$`)
+    #[clap(long, env, value_delimiter = ',')]
+    pub index_accounts: Vec<AccountPattern>,
+    /// Skip the state of the accounts matching any of the comma-separated patterns,
+    /// even if they match `index_accounts`
+    #[clap(long, env, value_delimiter = ',')]
+    pub skip_accounts: Vec<AccountPattern>,
+}
+
+impl AccountFilterArgs {
+    /// Returns `false` if the state of the account is not indexed
+    pub fn is_indexed(&self, account_id: &str) -> bool {
+        if self
+            .skip_accounts
+            .iter()
+            .any(|pattern| pattern.matches(account_id))
+        {
+            return false;
+        }
+        self.index_accounts.is_empty()
+            || self
+                .index_accounts
+                .iter()
+                .any(|pattern| pattern.matches(account_id))
+    }
+
+    /// Returns `true` if all the accounts are indexed
+    pub fn is_empty(&self) -> bool {
+        self.index_accounts.is_empty() && self.skip_accounts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn account_pattern_is_parsed() {
+        assert!(matches!(
+            "alice.near".parse::<AccountPattern>().unwrap(),
+            AccountPattern::Exact(exact) if exact == "alice.near"
+        ));
+        assert!(matches!(
+            " *.mycorp.near ".parse::<AccountPattern>().unwrap(),
+            AccountPattern::Suffix(suffix) if suffix == ".mycorp.near"
+        ));
+        assert!(matches!(
+            r"re:^app-[0-9]+\.near$".parse::<AccountPattern>().unwrap(),
+            AccountPattern::Regex(_)
+        ));
+    }
+
+    #[test]
+    fn account_pattern_rejects_the_invalid_patterns() {
+        assert!("".parse::<AccountPattern>().is_err());
+        assert!("  ".parse::<AccountPattern>().is_err());
+        assert!("*mycorp.near".parse::<AccountPattern>().is_err());
+        assert!("re:app-(".parse::<AccountPattern>().is_err());
+    }
+
+    #[test]
+    fn exact_pattern_matches_only_the_account() {
+        let pattern: AccountPattern = "alice.near".parse().unwrap();
+        assert!(pattern.matches("alice.near"));
+        assert!(!pattern.matches("bob.alice.near"));
+        assert!(!pattern.matches("alice.nearx"));
+    }
+
+    #[test]
+    fn suffix_pattern_matches_only_the_sub_accounts() {
+        let pattern: AccountPattern = "*.mycorp.near".parse().unwrap();
+        assert!(pattern.matches("app.mycorp.near"));
+        assert!(pattern.matches("a.b.mycorp.near"));
+        assert!(!pattern.matches("mycorp.near"));
+        assert!(!pattern.matches("notmycorp.near"));
+    }
+
+    #[test]
+    fn regex_pattern_matches_the_accounts() {
+        let pattern: AccountPattern = r"re:^app-[0-9]+\.near$".parse().unwrap();
+        assert!(pattern.matches("app-42.near"));
+        assert!(!pattern.matches("app-x.near"));
+        assert!(!pattern.matches("my-app-42.near"));
+    }
+
+    #[test]
+    fn skipped_accounts_are_not_indexed_even_if_included() {
+        let filter = AccountFilterArgs {
+            index_accounts: vec!["*.mycorp.near".parse().unwrap()],
+            skip_accounts: vec!["spam.mycorp.near".parse().unwrap()],
+        };
+        assert!(filter.is_indexed("app.mycorp.near"));
+        assert!(!filter.is_indexed("spam.mycorp.near"));
+        assert!(!filter.is_indexed("alice.near"));
+        assert!(!filter.is_empty());
+    }
+
+    #[test]
+    fn all_the_accounts_are_indexed_without_the_filter() {
+        let filter = AccountFilterArgs {
+            index_accounts: vec![],
+            skip_accounts: vec![],
+        };
+        assert!(filter.is_indexed("alice.near"));
+        assert!(filter.is_empty());
+    }
+}
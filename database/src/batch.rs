@@ -0,0 +1,82 @@
+// Unlogged batches of the writes grouped by the partition.
+//
+// An unlogged batch of the writes to the same partition is applied by the replicas of the partition
+// as a single mutation, so grouping the writes by the partition saves the round-trips without
+// the overhead of the multi-partition (coordinator-side) batches.
+// The batch is limited by the number of the statements and by the size of the values,
+// ScyllaDB rejects the batches above `batch_size_fail_threshold_in_kb` (1 MiB by default).
+
+use scylla::frame::value::{SerializedValues, ValueList};
+use scylla::prepared_statement::PreparedStatement;
+
+/// Stays well below the default `batch_size_fail_threshold_in_kb` of ScyllaDB
+const MAX_BATCH_BYTES: usize = 256 * 1024;
+
+pub struct PartitionBatches {
+    max_batch_size: usize,
+    /// The writes by the partition they go to
+    partitions: std::collections::BTreeMap<String, Vec<(PreparedStatement, SerializedValues)>>,
+}
+
+impl PartitionBatches {
+    pub fn new(max_batch_size: usize) -> Self {
+        Self {
+            max_batch_size: max_batch_size.max(1),
+            partitions: std::collections::BTreeMap::new(),
+        }
+    }
+
+    /// `partition` identifies the table and the partition key the write goes to,
+    /// e.g. `account_state_versions/<account_id>`
+    pub fn append(
+        &mut self,
+        partition: String,
+        statement: &PreparedStatement,
+        values: impl ValueList,
+    ) -> anyhow::Result<()> {
+        self.partitions
+            .entry(partition)
+            .or_default()
+            .push((statement.clone(), values.serialized()?.into_owned()));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+
+    /// Splits the writes of every partition into the batches of at most `max_batch_size` statements
+    pub fn into_batches(self) -> Vec<(scylla::batch::Batch, Vec<SerializedValues>)> {
+        let mut batches = vec![];
+        for writes in self.partitions.into_values() {
+            let mut batch = new_batch();
+            let mut batch_values = vec![];
+            let mut batch_bytes = 0;
+            for (statement, values) in writes {
+                if !batch_values.is_empty()
+                    && (batch_values.len() >= self.max_batch_size
+                        || batch_bytes + values.size() > MAX_BATCH_BYTES)
+                {
+                    batches.push((
+                        std::mem::replace(&mut batch, new_batch()),
+                        std::mem::take(&mut batch_values),
+                    ));
+                    batch_bytes = 0;
+                }
+                batch_bytes += values.size();
+                batch.append_statement(statement);
+                batch_values.push(values);
+            }
+            if !batch_values.is_empty() {
+                batches.push((batch, batch_values));
+            }
+        }
+        batches
+    }
+}
+
+fn new_batch() -> scylla::batch::Batch {
+    let mut batch = scylla::batch::Batch::new(scylla::batch::BatchType::Unlogged);
+    batch.set_consistency(scylla::frame::types::Consistency::LocalQuorum);
+    batch
+}
@@ -0,0 +1,116 @@
+// Transparent compression of the large blobs stored in the tables.
+//
+// The indexers compress the contract code, the contract data values and the transaction details
+// with zstd if `scylla_blob_compression_level` is set, the readers decompress them.
+// The compressed value is the header byte followed by the zstd frame (starting with the zstd
+// magic number), the values starting otherwise are the uncompressed ones, so the rows written
+// before the compression was enabled (or with it disabled) are read as is.
+// The uncompressed value which happens to start the same way is compressed regardless of
+// the settings to keep the stored values unambiguous.
+// The compression is the setting of the `ScyllaSession` the indexer writes with.
+
+const COMPRESSED_HEADER: u8 = 0xFE;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+#[derive(Debug, Clone, Default)]
+pub struct BlobCompression {
+    /// zstd compression level, `None` disables the compression
+    pub level: Option<i32>,
+    /// The blobs smaller than this are stored uncompressed
+    pub min_size: usize,
+}
+
+impl BlobCompression {
+    /// Returns the value to store, `kind` is the label of the compression metrics
+    /// (e.g. `contract_code`)
+    pub fn compress(&self, kind: &str, value: &[u8]) -> Vec<u8> {
+        let level = match self.level {
+            Some(level) if value.len() >= self.min_size => level,
+            _ if is_compressed(value) => zstd::DEFAULT_COMPRESSION_LEVEL,
+            _ => return value.to_vec(),
+        };
+        let mut stored = vec![COMPRESSED_HEADER];
+        zstd::stream::copy_encode(value, &mut stored, level)
+            .expect("Failed to zstd-compress the value");
+        // The incompressible values are stored as is unless they have to be escaped
+        if stored.len() >= value.len() && !is_compressed(value) {
+            stored = value.to_vec();
+        }
+        crate::metrics::BLOB_COMPRESSION_INPUT_BYTES_TOTAL
+            .with_label_values(&[kind])
+            .inc_by(value.len() as u64);
+        crate::metrics::BLOB_COMPRESSION_OUTPUT_BYTES_TOTAL
+            .with_label_values(&[kind])
+            .inc_by(stored.len() as u64);
+        stored
+    }
+}
+
+fn is_compressed(value: &[u8]) -> bool {
+    value.first() == Some(&COMPRESSED_HEADER) && value.get(1..5) == Some(&ZSTD_MAGIC[..])
+}
+
+/// Returns the stored value decompressed, the uncompressed values are returned as is
+pub fn decompress(value: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    if !is_compressed(&value) {
+        return Ok(value);
+    }
+    Ok(zstd::stream::decode_all(&value[1..])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compression(level: Option<i32>) -> BlobCompression {
+        BlobCompression {
+            level,
+            min_size: 16,
+        }
+    }
+
+    #[test]
+    fn compressed_value_round_trips() {
+        let value = b"contract data ".repeat(100);
+        let stored = compression(Some(3)).compress("test", &value);
+        assert!(is_compressed(&stored));
+        assert!(stored.len() < value.len());
+        assert_eq!(decompress(stored).unwrap(), value);
+    }
+
+    #[test]
+    fn small_value_is_stored_as_is() {
+        let value = b"short".to_vec();
+        let stored = compression(Some(3)).compress("test", &value);
+        assert_eq!(stored, value);
+        assert_eq!(decompress(stored).unwrap(), value);
+    }
+
+    #[test]
+    fn value_is_stored_as_is_with_the_compression_disabled() {
+        let value = b"contract data ".repeat(100);
+        let stored = compression(None).compress("test", &value);
+        assert_eq!(stored, value);
+        assert_eq!(decompress(stored).unwrap(), value);
+    }
+
+    #[test]
+    fn value_looking_compressed_is_escaped() {
+        let mut value = vec![COMPRESSED_HEADER];
+        value.extend_from_slice(&ZSTD_MAGIC);
+        value.extend_from_slice(b"raw");
+        for level in [None, Some(3)] {
+            let stored = compression(level).compress("test", &value);
+            assert_ne!(stored, value);
+            assert!(is_compressed(&stored));
+            assert_eq!(decompress(stored).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn uncompressed_value_is_read_as_is() {
+        let value = vec![COMPRESSED_HEADER, 0x00, 0x01];
+        assert_eq!(decompress(value.clone()).unwrap(), value);
+        assert_eq!(decompress(vec![]).unwrap(), Vec::<u8>::new());
+    }
+}
@@ -0,0 +1,623 @@
+// Embedded storage backed by RocksDB for the single-node deployments
+// (e.g. Calimero private shards with a few hundred accounts) where running
+// a ScyllaDB cluster is an overkill.
+//
+// Every indexer owns its own database directory (RocksDB allows only one process
+// to open the database for writing). The rpc-server opens the databases of the indexers
+// as a secondary instance and catches up with the writes periodically:
+//
+// <rocksdb_path>/state_indexer   written by the state-indexer
+// <rocksdb_path>/tx_indexer      written by the tx-indexer
+//
+// The column families mirror the ScyllaDB tables. The keys are built from the length-prefixed
+// parts, the versioned keys are followed by the big-endian block height, so the versions
+// of the same key are sorted by the block height and the latest version at the given block
+// can be found with a single reverse seek.
+//
+// The RocksDB calls are blocking, so the indexers and the rpc-server make every call
+// through `RocksDBManager::run_blocking`, which runs it on the blocking thread pool of tokio
+// (`spawn_blocking`) to keep the async worker threads free.
+
+use crate::VersionedRecord;
+
+pub const STATE_INDEXER_DIR: &str = "state_indexer";
+pub const TX_INDEXER_DIR: &str = "tx_indexer";
+
+pub const STATE_INDEXER_COLUMN_FAMILIES: [&str; 10] = [
+    STATE_CHANGES_DATA,
+    STATE_CHANGES_ACCESS_KEY,
+    STATE_CHANGES_CONTRACT,
+    STATE_CHANGES_ACCOUNT,
+    ACCOUNT_STATE,
+    BLOCKS,
+    CHUNKS,
+    CHUNKS_BY_BLOCK,
+    STATE_CHANGES_BY_BLOCK,
+    META,
+];
+
+pub const TX_INDEXER_COLUMN_FAMILIES: [&str; 3] = [TRANSACTIONS_DETAILS, RECEIPTS_MAP, META];
+
+const STATE_CHANGES_DATA: &str = "state_changes_data";
+const STATE_CHANGES_ACCESS_KEY: &str = "state_changes_access_key";
+const STATE_CHANGES_CONTRACT: &str = "state_changes_contract";
+const STATE_CHANGES_ACCOUNT: &str = "state_changes_account";
+const ACCOUNT_STATE: &str = "account_state";
+const BLOCKS: &str = "blocks";
+const CHUNKS: &str = "chunks";
+const CHUNKS_BY_BLOCK: &str = "chunks_by_block";
+const STATE_CHANGES_BY_BLOCK: &str = "state_changes_by_block";
+const META: &str = "meta";
+const TRANSACTIONS_DETAILS: &str = "transactions_details";
+const RECEIPTS_MAP: &str = "receipts_map";
+
+/// Cheap to clone, the clones share the same database.
+/// The calls are blocking, the async code should go through `run_blocking`
+#[derive(Clone)]
+pub struct RocksDBManager {
+    db: std::sync::Arc<rocksdb::DB>,
+}
+
+impl RocksDBManager {
+    /// Opens the database for reading and writing, creates it if missing.
+    /// Only one process can open the database this way
+    pub fn open(path: &std::path::Path, column_families: &[&str]) -> anyhow::Result<Self> {
+        let mut options = rocksdb::Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = rocksdb::DB::open_cf(&options, path, column_families)?;
+        Ok(Self {
+            db: std::sync::Arc::new(db),
+        })
+    }
+
+    /// Opens the database written by another process for reading.
+    /// `secondary_path` is used to store the info logs of the secondary instance,
+    /// it has to be unique for every process opening the database this way
+    pub fn open_secondary(
+        primary_path: &std::path::Path,
+        secondary_path: &std::path::Path,
+        column_families: &[&str],
+    ) -> anyhow::Result<Self> {
+        let mut options = rocksdb::Options::default();
+        // Secondary instance keeps all the files opened
+        options.set_max_open_files(-1);
+        let db = rocksdb::DB::open_cf_as_secondary(
+            &options,
+            primary_path,
+            secondary_path,
+            column_families,
+        )?;
+        Ok(Self {
+            db: std::sync::Arc::new(db),
+        })
+    }
+
+    /// Runs the blocking calls on the blocking thread pool of tokio
+    /// to not stall the async worker threads
+    pub async fn run_blocking<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&Self) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db_manager = self.clone();
+        tokio::task::spawn_blocking(move || f(&db_manager)).await?
+    }
+
+    /// Makes the writes of the primary instance visible to the secondary instance
+    pub fn catch_up_with_primary(&self) -> anyhow::Result<()> {
+        Ok(self.db.try_catch_up_with_primary()?)
+    }
+
+    fn cf(&self, name: &str) -> anyhow::Result<&rocksdb::ColumnFamily> {
+        self.db
+            .cf_handle(name)
+            .ok_or_else(|| anyhow::anyhow!("Column family {} not found", name))
+    }
+
+    /// Returns the latest record stored under the given key parts at or before the given block height
+    fn get_latest(
+        &self,
+        cf_name: &str,
+        key_parts: &[&[u8]],
+        block_height: u64,
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        let prefix = key(key_parts);
+        let seek_key = versioned_key(key_parts, block_height);
+        let mut iter = self.db.iterator_cf(
+            self.cf(cf_name)?,
+            rocksdb::IteratorMode::From(&seek_key, rocksdb::Direction::Reverse),
+        );
+        match iter.next() {
+            Some(item) => {
+                let (found_key, value) = item?;
+                if found_key.len() != seek_key.len() || !found_key.starts_with(&prefix) {
+                    return Ok(None);
+                }
+                let mut height = [0u8; 8];
+                height.copy_from_slice(&found_key[prefix.len()..]);
+                Ok(Some(decode_versioned_value(
+                    u64::from_be_bytes(height),
+                    &value,
+                )?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the rest of the keys starting with the given key parts followed by the raw prefix
+    fn get_keys_by_prefix(
+        &self,
+        cf_name: &str,
+        key_parts: &[&[u8]],
+        raw_prefix: &[u8],
+    ) -> anyhow::Result<Vec<Vec<u8>>> {
+        let parts_prefix = key(key_parts);
+        let mut prefix = parts_prefix.clone();
+        prefix.extend_from_slice(raw_prefix);
+        let mut keys = vec![];
+        for item in self.db.iterator_cf(
+            self.cf(cf_name)?,
+            rocksdb::IteratorMode::From(&prefix, rocksdb::Direction::Forward),
+        ) {
+            let (found_key, _) = item?;
+            if !found_key.starts_with(&prefix) {
+                break;
+            }
+            keys.push(found_key[parts_prefix.len()..].to_vec());
+        }
+        Ok(keys)
+    }
+
+    fn put_versioned(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        cf_name: &str,
+        key_parts: &[&[u8]],
+        block_height: u64,
+        block_hash: &str,
+        data: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        batch.put_cf(
+            self.cf(cf_name)?,
+            versioned_key(key_parts, block_height),
+            encode_versioned_value(block_hash, data),
+        );
+        Ok(())
+    }
+
+    fn write_versioned(
+        &self,
+        cf_name: &str,
+        key_parts: &[&[u8]],
+        block_height: u64,
+        block_hash: &str,
+        data: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        self.put_versioned(
+            &mut batch,
+            cf_name,
+            key_parts,
+            block_height,
+            block_hash,
+            data,
+        )?;
+        Ok(self.db.write(batch)?)
+    }
+
+    pub fn update_meta(&self, indexer_id: &str, block_height: u64) -> anyhow::Result<()> {
+        Ok(self
+            .db
+            .put_cf(self.cf(META)?, indexer_id, block_height.to_be_bytes())?)
+    }
+
+    pub fn get_last_processed_block_height(&self, indexer_id: &str) -> anyhow::Result<Option<u64>> {
+        self.db
+            .get_cf(self.cf(META)?, indexer_id)?
+            .map(|value| decode_u64(&value))
+            .transpose()
+    }
+}
+
+// state_indexer
+impl RocksDBManager {
+    /// Stores the state value of the account, `None` value means the key was deleted
+    pub fn put_state_value(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        data_key: &[u8],
+        data_value: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        self.put_versioned(
+            &mut batch,
+            STATE_CHANGES_DATA,
+            &[account_id.as_bytes(), data_key],
+            block_height,
+            block_hash,
+            data_value,
+        )?;
+        // The state key is followed by nothing to allow the prefix search by the state key
+        let mut account_state_key = key(&[account_id.as_bytes()]);
+        account_state_key.extend_from_slice(data_key);
+        batch.put_cf(self.cf(ACCOUNT_STATE)?, account_state_key, b"");
+        Ok(self.db.write(batch)?)
+    }
+
+    pub fn get_state_value(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        data_key: &[u8],
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest(
+            STATE_CHANGES_DATA,
+            &[account_id.as_bytes(), data_key],
+            block_height,
+        )
+    }
+
+    /// Returns the state keys of the account starting with the given prefix
+    /// which have a value at the given block height.
+    /// `account_state` keeps all the keys the account ever had, the keys are checked against
+    /// their versions since the versions of the key are not stored next to each other
+    pub fn get_state_keys_by_prefix(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        prefix: &[u8],
+    ) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut state_keys = vec![];
+        for data_key in self.get_keys_by_prefix(ACCOUNT_STATE, &[account_id.as_bytes()], prefix)? {
+            if let Some(VersionedRecord { data: Some(_), .. }) =
+                self.get_state_value(account_id, block_height, &data_key)?
+            {
+                state_keys.push(data_key);
+            }
+        }
+        Ok(state_keys)
+    }
+
+    /// Stores the access key of the account, `None` value means the access key was deleted
+    pub fn put_access_key(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        public_key: &[u8],
+        access_key: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        self.write_versioned(
+            STATE_CHANGES_ACCESS_KEY,
+            &[account_id.as_bytes(), public_key],
+            block_height,
+            block_hash,
+            access_key,
+        )
+    }
+
+    pub fn get_access_key(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        public_key: &[u8],
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest(
+            STATE_CHANGES_ACCESS_KEY,
+            &[account_id.as_bytes(), public_key],
+            block_height,
+        )
+    }
+
+    /// Returns the active access keys of the account at the given block height
+    /// as pairs of the serialized public key and the serialized access key
+    pub fn get_access_keys(
+        &self,
+        account_id: &str,
+        block_height: u64,
+    ) -> anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut public_keys = vec![];
+        for versioned_key in
+            self.get_keys_by_prefix(STATE_CHANGES_ACCESS_KEY, &[account_id.as_bytes()], &[])?
+        {
+            // The rest of the key is the length-prefixed public key followed by the block height
+            let public_key = decode_key_part(&versioned_key)?.to_vec();
+            if public_keys.last() != Some(&public_key) {
+                public_keys.push(public_key);
+            }
+        }
+        let mut access_keys = vec![];
+        for public_key in public_keys {
+            if let Some(VersionedRecord {
+                data: Some(access_key),
+                ..
+            }) = self.get_access_key(account_id, block_height, &public_key)?
+            {
+                access_keys.push((public_key, access_key));
+            }
+        }
+        Ok(access_keys)
+    }
+
+    /// Stores the contract code of the account, `None` value means the contract was deleted
+    pub fn put_contract_code(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        code: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        self.write_versioned(
+            STATE_CHANGES_CONTRACT,
+            &[account_id.as_bytes()],
+            block_height,
+            block_hash,
+            code,
+        )
+    }
+
+    pub fn get_contract_code(
+        &self,
+        account_id: &str,
+        block_height: u64,
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest(
+            STATE_CHANGES_CONTRACT,
+            &[account_id.as_bytes()],
+            block_height,
+        )
+    }
+
+    /// Stores the account, `None` value means the account was deleted
+    pub fn put_account(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        account: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        self.write_versioned(
+            STATE_CHANGES_ACCOUNT,
+            &[account_id.as_bytes()],
+            block_height,
+            block_hash,
+            account,
+        )
+    }
+
+    pub fn get_account(
+        &self,
+        account_id: &str,
+        block_height: u64,
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest(
+            STATE_CHANGES_ACCOUNT,
+            &[account_id.as_bytes()],
+            block_height,
+        )
+    }
+
+    pub fn add_block(&self, block_hash: &str, block_height: u64) -> anyhow::Result<()> {
+        Ok(self
+            .db
+            .put_cf(self.cf(BLOCKS)?, block_hash, block_height.to_be_bytes())?)
+    }
+
+    pub fn get_block_height_by_hash(&self, block_hash: &str) -> anyhow::Result<Option<u64>> {
+        self.db
+            .get_cf(self.cf(BLOCKS)?, block_hash)?
+            .map(|value| decode_u64(&value))
+            .transpose()
+    }
+
+    /// Stores the chunks of the block as `(chunk_hash, shard_id, stored_at_block_height)`
+    pub fn add_chunks(
+        &self,
+        block_height: u64,
+        chunks: &[(String, u64, u64)],
+    ) -> anyhow::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for (chunk_hash, shard_id, stored_at_block_height) in chunks {
+            let mut value = stored_at_block_height.to_be_bytes().to_vec();
+            value.extend_from_slice(&shard_id.to_be_bytes());
+            batch.put_cf(self.cf(CHUNKS)?, chunk_hash, value);
+            let mut chunk_key = block_height.to_be_bytes().to_vec();
+            chunk_key.extend_from_slice(&shard_id.to_be_bytes());
+            batch.put_cf(
+                self.cf(CHUNKS_BY_BLOCK)?,
+                chunk_key,
+                stored_at_block_height.to_be_bytes(),
+            );
+        }
+        Ok(self.db.write(batch)?)
+    }
+
+    /// Returns the `(stored_at_block_height, shard_id)` of the chunk
+    pub fn get_chunk(&self, chunk_hash: &str) -> anyhow::Result<Option<(u64, u64)>> {
+        match self.db.get_cf(self.cf(CHUNKS)?, chunk_hash)? {
+            Some(value) if value.len() == 16 => {
+                Ok(Some((decode_u64(&value[..8])?, decode_u64(&value[8..])?)))
+            }
+            Some(_) => anyhow::bail!("Malformed chunk record of {}", chunk_hash),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the height of the block the chunk of the given block and shard is stored at
+    pub fn get_chunk_stored_at_block_height(
+        &self,
+        block_height: u64,
+        shard_id: u64,
+    ) -> anyhow::Result<Option<u64>> {
+        let mut chunk_key = block_height.to_be_bytes().to_vec();
+        chunk_key.extend_from_slice(&shard_id.to_be_bytes());
+        self.db
+            .get_cf(self.cf(CHUNKS_BY_BLOCK)?, chunk_key)?
+            .map(|value| decode_u64(&value))
+            .transpose()
+    }
+
+    /// Stores all the serialized state changes of the block at once
+    pub fn add_block_state_changes(
+        &self,
+        block_height: u64,
+        state_changes: &[Vec<u8>],
+    ) -> anyhow::Result<()> {
+        let parts: Vec<&[u8]> = state_changes.iter().map(Vec::as_slice).collect();
+        Ok(self.db.put_cf(
+            self.cf(STATE_CHANGES_BY_BLOCK)?,
+            block_height.to_be_bytes(),
+            key(&parts),
+        )?)
+    }
+
+    pub fn get_block_state_changes(
+        &self,
+        block_height: u64,
+    ) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        match self
+            .db
+            .get_cf(self.cf(STATE_CHANGES_BY_BLOCK)?, block_height.to_be_bytes())?
+        {
+            Some(value) => {
+                let mut state_changes = vec![];
+                let mut rest = value.as_slice();
+                while !rest.is_empty() {
+                    let state_change = decode_key_part(rest)?;
+                    rest = &rest[4 + state_change.len()..];
+                    state_changes.push(state_change.to_vec());
+                }
+                Ok(Some(state_changes))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+// tx_indexer
+impl RocksDBManager {
+    pub fn add_transaction(
+        &self,
+        transaction_hash: &str,
+        block_height: u64,
+        transaction_details: &[u8],
+    ) -> anyhow::Result<()> {
+        // Block hash is not tracked for the transactions
+        self.write_versioned(
+            TRANSACTIONS_DETAILS,
+            &[transaction_hash.as_bytes()],
+            block_height,
+            "",
+            Some(transaction_details),
+        )
+    }
+
+    /// Returns the transaction details stored at the highest block height,
+    /// the same way ScyllaDB orders the hash collisions
+    pub fn get_transaction(&self, transaction_hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .get_latest(
+                TRANSACTIONS_DETAILS,
+                &[transaction_hash.as_bytes()],
+                u64::MAX,
+            )?
+            .and_then(|record| record.data))
+    }
+
+    pub fn add_receipt(
+        &self,
+        receipt_id: &str,
+        parent_transaction_hash: &str,
+        block_height: u64,
+        shard_id: u64,
+    ) -> anyhow::Result<()> {
+        let mut value = key(&[parent_transaction_hash.as_bytes()]);
+        value.extend_from_slice(&block_height.to_be_bytes());
+        value.extend_from_slice(&shard_id.to_be_bytes());
+        Ok(self.db.put_cf(self.cf(RECEIPTS_MAP)?, receipt_id, value)?)
+    }
+
+    /// Returns the `(parent_transaction_hash, block_height, shard_id)` of the receipt
+    pub fn get_receipt(&self, receipt_id: &str) -> anyhow::Result<Option<(String, u64, u64)>> {
+        match self.db.get_cf(self.cf(RECEIPTS_MAP)?, receipt_id)? {
+            Some(value) => {
+                let parent_transaction_hash = decode_key_part(&value)?;
+                let rest = &value[4 + parent_transaction_hash.len()..];
+                if rest.len() != 16 {
+                    anyhow::bail!("Malformed receipt record of {}", receipt_id);
+                }
+                Ok(Some((
+                    String::from_utf8(parent_transaction_hash.to_vec())?,
+                    decode_u64(&rest[..8])?,
+                    decode_u64(&rest[8..])?,
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Builds the key of the length-prefixed parts, so the parts of different length never collide
+fn key(parts: &[&[u8]]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(parts.iter().map(|part| part.len() + 4).sum());
+    for part in parts {
+        key.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        key.extend_from_slice(part);
+    }
+    key
+}
+
+fn versioned_key(parts: &[&[u8]], block_height: u64) -> Vec<u8> {
+    let mut key = key(parts);
+    key.extend_from_slice(&block_height.to_be_bytes());
+    key
+}
+
+/// Returns the first length-prefixed part of the key
+fn decode_key_part(key: &[u8]) -> anyhow::Result<&[u8]> {
+    if key.len() < 4 {
+        anyhow::bail!("Malformed key: missing the length prefix");
+    }
+    let mut len = [0u8; 4];
+    len.copy_from_slice(&key[..4]);
+    let len = u32::from_be_bytes(len) as usize;
+    key.get(4..4 + len)
+        .ok_or_else(|| anyhow::anyhow!("Malformed key: the part is shorter than its length"))
+}
+
+fn decode_u64(value: &[u8]) -> anyhow::Result<u64> {
+    let bytes: [u8; 8] = value
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Malformed value: expected 8 bytes, got {}", value.len()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn encode_versioned_value(block_hash: &str, data: Option<&[u8]>) -> Vec<u8> {
+    let mut value = key(&[block_hash.as_bytes()]);
+    match data {
+        Some(data) => {
+            value.push(1);
+            value.extend_from_slice(data);
+        }
+        None => value.push(0),
+    }
+    value
+}
+
+fn decode_versioned_value(block_height: u64, value: &[u8]) -> anyhow::Result<VersionedRecord> {
+    let block_hash = decode_key_part(value)?;
+    let rest = &value[4 + block_hash.len()..];
+    let data = match rest.first() {
+        Some(1) => Some(rest[1..].to_vec()),
+        Some(0) => None,
+        _ => anyhow::bail!("Malformed versioned value at block {}", block_height),
+    };
+    Ok(VersionedRecord {
+        block_height,
+        block_hash: String::from_utf8(block_hash.to_vec())?,
+        data,
+    })
+}
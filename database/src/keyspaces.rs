@@ -0,0 +1,107 @@
+// The names and the replication of the ScyllaDB keyspaces.
+//
+// All the binaries flatten `ScyllaKeyspaces` into their options, so the same environment
+// variables configure the keyspaces of the indexers writing the data and of the rpc-server reading it.
+// Custom names allow to run several networks in one cluster, e.g. `mainnet_state_indexer` and
+// `testnet_state_indexer`.
+
+/// The replication of the keyspaces created by the binaries.
+/// Has no effect on the keyspaces that already exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replication {
+    /// `simple:<replication_factor>`, for the development and the single-DC clusters
+    SimpleStrategy(u32),
+    /// `network-topology:<dc>=<replication_factor>,<dc>=<replication_factor>,...`
+    NetworkTopologyStrategy(Vec<(String, u32)>),
+}
+
+impl std::str::FromStr for Replication {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(replication_factor) = s.strip_prefix("simple:") {
+            return Ok(Self::SimpleStrategy(replication_factor.parse()?));
+        }
+        if let Some(data_centers) = s.strip_prefix("network-topology:") {
+            let data_centers = data_centers
+                .split(',')
+                .map(|data_center| {
+                    let (name, replication_factor) =
+                        data_center.split_once('=').ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "Expected `<dc>=<replication_factor>`, got {}",
+                                data_center
+                            )
+                        })?;
+                    Ok((name.trim().to_string(), replication_factor.trim().parse()?))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            return Ok(Self::NetworkTopologyStrategy(data_centers));
+        }
+        anyhow::bail!(
+            "Unknown replication {}, expected `simple:<replication_factor>` \
+            or `network-topology:<dc>=<replication_factor>,...`",
+            s
+        )
+    }
+}
+
+impl Replication {
+    /// The replication map of the `CREATE KEYSPACE` statement
+    pub fn to_cql(&self) -> String {
+        match self {
+            Self::SimpleStrategy(replication_factor) => format!(
+                "{{'class': 'SimpleStrategy', 'replication_factor': {}}}",
+                replication_factor
+            ),
+            Self::NetworkTopologyStrategy(data_centers) => {
+                let data_centers: Vec<String> = data_centers
+                    .iter()
+                    .map(|(name, replication_factor)| format!("'{}': {}", name, replication_factor))
+                    .collect();
+                format!(
+                    "{{'class': 'NetworkTopologyStrategy', {}}}",
+                    data_centers.join(", ")
+                )
+            }
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScyllaKeyspaces {
+    /// ScyllaDB keyspace of the state-indexer
+    #[clap(long, default_value = "state_indexer", env)]
+    pub scylla_state_indexer_keyspace: String,
+    /// ScyllaDB keyspace of the tx-indexer
+    #[clap(long, default_value = "tx_indexer", env)]
+    pub scylla_tx_indexer_keyspace: String,
+    /// ScyllaDB keyspace of the transactions collected by the tx-indexer
+    #[clap(long, default_value = "tx_indexer_cache", env)]
+    pub scylla_tx_indexer_cache_keyspace: String,
+    /// Replication of the created keyspaces:
+    /// `simple:<replication_factor>` or `network-topology:<dc>=<replication_factor>,...`
+    /// Example: "network-topology:DC1=3,DC2=3"
+    #[clap(long, default_value = "simple:1", env)]
+    pub scylla_replication: Replication,
+}
+
+impl ScyllaKeyspaces {
+    pub async fn create_keyspace(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+    ) -> anyhow::Result<()> {
+        scylla_db_session
+            .query(
+                format!(
+                    "CREATE KEYSPACE IF NOT EXISTS {} WITH REPLICATION = {}",
+                    keyspace,
+                    self.scylla_replication.to_cql()
+                ),
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -1,16 +1,20 @@
 // Example:
 //
 // pub(crate) struct MyScyllaManager {
-//     scylla_session: std::sync::Arc<scylla::Session>,
-//     add_transaction: PreparedStatement,
+//     scylla_session: std::sync::Arc<database::session::ScyllaSession>,
+//     add_transaction: database::NamedStatement,
 // }
 //
 // impl ScyllaStorageManager for MyScyllaManager {
-//     async fn prepare(scylla_db_session: std::sync::Arc<scylla::Session>) -> anyhow::Result<Self> {
+//     async fn prepare(
+//         scylla_db_session: std::sync::Arc<database::session::ScyllaSession>,
+//         keyspaces: &database::keyspaces::ScyllaKeyspaces,
+//     ) -> anyhow::Result<Self> {
 //         Ok(Self {
 //             scylla_session: scylla_db_session.clone(),
-//             add_transaction: Self::prepare_query(
+//             add_transaction: Self::prepare_write_query(
 //                 &scylla_db_session,
+//                 "add_transaction",
 //                 "INSERT INTO transactions_details
 //                     (transaction_hash, block_height, account_id, transaction_details)
 //                     VALUES(?, ?, ?, ?)",
@@ -20,9 +24,21 @@
 //     }
 // }
 //
-// If you need migrations describe the tables in the `create_tables`
+// If you need migrations add the keyspace migrations to the `database/migrations/scylla`
+// and list them in the `migrations` (see the `migrations` module)
 //
-//     async fn create_tables(scylla_db_session: &scylla::Session) -> anyhow::Result<()> {
+//     fn migrations(
+//         keyspaces: &database::keyspaces::ScyllaKeyspaces,
+//     ) -> Vec<(String, &'static database::migrations::KeyspaceMigrations)> {
+//         vec![(keyspaces.scylla_tx_indexer_keyspace.clone(), &database::migrations::TX_INDEXER)]
+//     }
+//
+// The tables can also be described in the `create_tables`, they are not versioned though
+//
+//     async fn create_tables(
+//         scylla_db_session: &scylla::Session,
+//         keyspaces: &database::keyspaces::ScyllaKeyspaces,
+//     ) -> anyhow::Result<()> {
 //         scylla_db_session.query(
 //             "CREATE TABLE IF NOT EXISTS transactions_details (
 //                 transaction_hash varchar,
@@ -64,72 +80,121 @@
 //
 // Usage:
 // let scylla_db_client = MyScyllaManager::new(
-//         scylla_url,
-//         scylla_keyspace,
-//         scylla_user,
-//         scylla_password,
-//         Some(keepalive_interval),
+//         &database::session::ScyllaSessionOptions {
+//             scylla_url,
+//             scylla_user,
+//             scylla_password,
+//             scylla_preferred_dc,
+//             keepalive_interval: Some(keepalive_interval),
+//             max_retry,
+//             strict_mode,
+//             connection,
+//         },
+//         migrations_mode,
+//         &keyspaces,
 // ).await?,
 
+pub mod account_filter;
+pub mod batch;
+pub mod blob_compression;
+#[cfg(feature = "rocksdb")]
+pub mod embedded;
+pub mod keyspaces;
+pub mod metrics;
+pub mod migrations;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "scylla_db_tracing")]
+pub mod query_tracing;
+pub mod retry;
+pub mod session;
+pub mod token_range_scan;
+
 use scylla::prepared_statement::PreparedStatement;
 use scylla::retry_policy::{QueryInfo, RetryDecision};
 use scylla::transport::errors::QueryError;
 
+/// The prepared statement with the name identifying it in the metrics
+#[derive(Debug, Clone)]
+pub struct NamedStatement {
+    pub name: String,
+    pub statement: PreparedStatement,
+}
+
+impl std::ops::Deref for NamedStatement {
+    type Target = PreparedStatement;
+
+    fn deref(&self) -> &Self::Target {
+        &self.statement
+    }
+}
+
+/// The value of the key at some block height returned by the storages
+/// keeping the whole history of the key (the embedded and PostgreSQL ones).
+/// `data` is `None` if the key was deleted at that block
+#[derive(Debug, Clone)]
+pub struct VersionedRecord {
+    pub block_height: u64,
+    pub block_hash: String,
+    pub data: Option<Vec<u8>>,
+}
+
+/// The range `start <= data_key < end` of the hex-encoded state keys starting with the prefix,
+/// so the prefix lookups are the range scans of the keys instead of the `LIKE` scans.
+/// The lowercase hex encoding keeps the order of the bytes and every hex digit is below `g`,
+/// so every key starting with the prefix is below the prefix followed by `g`
+pub fn hex_prefix_range(prefix: &[u8]) -> (String, String) {
+    let start = hex::encode(prefix);
+    let end = format!("{}g", start);
+    (start, end)
+}
+
+/// Retries the failed query on the other nodes at most `max_retry` times.
+/// The retries with the backoff are made by `ScyllaStorageManager::execute_prepared_query`
 #[derive(Debug)]
 pub struct CustomDBRetryPolicy {
     max_retry: u8,
-    strict_mode: bool,
 }
 
 impl CustomDBRetryPolicy {
-    pub fn new(max_retry: u8, strict_mode: bool) -> CustomDBRetryPolicy {
-        CustomDBRetryPolicy {
-            max_retry,
-            strict_mode,
-        }
+    pub fn new(max_retry: u8) -> CustomDBRetryPolicy {
+        CustomDBRetryPolicy { max_retry }
     }
 }
 
 impl Default for CustomDBRetryPolicy {
-    /// By default using `strict_mode`
     fn default() -> CustomDBRetryPolicy {
-        CustomDBRetryPolicy::new(0, true)
+        CustomDBRetryPolicy::new(0)
     }
 }
 
 impl scylla::retry_policy::RetryPolicy for CustomDBRetryPolicy {
     /// Called for each new query, starts a session of deciding about retries
     fn new_session(&self) -> Box<dyn scylla::retry_policy::RetrySession> {
-        Box::new(CustomRetrySession::new(self.max_retry, self.strict_mode))
+        Box::new(CustomRetrySession::new(self.max_retry))
     }
 
     /// Used to clone this RetryPolicy
     fn clone_boxed(&self) -> Box<dyn scylla::retry_policy::RetryPolicy> {
-        Box::new(CustomDBRetryPolicy::new(self.max_retry, self.strict_mode))
+        Box::new(CustomDBRetryPolicy::new(self.max_retry))
     }
 }
 
 struct CustomRetrySession {
     max_retry: u8,
     max_retry_count: u8,
-    strict_mode: bool,
 }
 
 impl CustomRetrySession {
-    pub fn new(max_retry: u8, strict_mode: bool) -> CustomRetrySession {
+    pub fn new(max_retry: u8) -> CustomRetrySession {
         CustomRetrySession {
             max_retry,
             max_retry_count: 0,
-            strict_mode,
         }
     }
 
     /// Decide if we should retry the query
     pub fn should_retry(&mut self) -> bool {
-        // If `strict_mode` always retry
-        if self.strict_mode {
-            return true;
-        };
         // If `max_retry_count` is more than `max_retry` don't retry
         if self.max_retry_count > self.max_retry {
             return false;
@@ -138,12 +203,29 @@ impl CustomRetrySession {
         self.max_retry_count += 1;
         true
     }
+
+    fn retry(&mut self, error: &QueryError, decision: RetryDecision) -> RetryDecision {
+        let error_class = retry::retriable_error_class(error).unwrap_or("other");
+        if !self.should_retry() {
+            return RetryDecision::DontRetry;
+        }
+        metrics::SCYLLA_RETRIES_TOTAL
+            .with_label_values(&[error_class, "immediate"])
+            .inc();
+        if let Some(suppressed) = retry::RETRY_LOG_THROTTLE.check() {
+            tracing::warn!(
+                "ScyllaDB QueryError: {:?}. Retrying immediately ({} similar messages suppressed)",
+                error,
+                suppressed
+            );
+        }
+        decision
+    }
 }
 
 impl Default for CustomRetrySession {
-    /// By default using `strict_mode`
     fn default() -> CustomRetrySession {
-        CustomRetrySession::new(0, true)
+        CustomRetrySession::new(0)
     }
 }
 
@@ -153,34 +235,27 @@ impl scylla::retry_policy::RetrySession for CustomRetrySession {
         if let scylla::frame::types::LegacyConsistency::Serial(_) = query_info.consistency {
             return RetryDecision::DontRetry;
         };
-        tracing::warn!("ScyllaDB QueryError: {:?}", query_info.error);
         match query_info.error {
+            // Basic errors - there are some problems on this node.
+            // Retry on a different one if possible
             QueryError::IoError(_)
             | QueryError::DbError(scylla::transport::errors::DbError::Overloaded, _)
             | QueryError::DbError(scylla::transport::errors::DbError::ServerError, _)
             | QueryError::DbError(scylla::transport::errors::DbError::TruncateError, _) => {
-                tracing::warn!(
-                    "Basic errors - there are some problems on this node.
-                    Retry on a different one if possible"
-                );
-                RetryDecision::RetryNextNode(None)
+                self.retry(query_info.error, RetryDecision::RetryNextNode(None))
             }
+            // Unavailable - the current node believes that not enough nodes
+            // are alive to satisfy specified consistency requirements.
+            // Maybe this node has network problems - try a different one
             QueryError::DbError(scylla::transport::errors::DbError::Unavailable { .. }, _) => {
-                tracing::warn!(
-                    "Unavailable - the current node believes that not enough nodes
-                    are alive to satisfy specified consistency requirements.
-                    Maybe this node has network problems - try a different one.
-                    Perform at most one retry - it's unlikely that two nodes
-                    have network problems at the same time"
-                );
-                if self.should_retry() {
-                    tracing::warn!("Retrying on a different node");
-                    RetryDecision::RetryNextNode(None)
-                } else {
-                    tracing::warn!("Not retrying");
-                    RetryDecision::DontRetry
-                }
+                self.retry(query_info.error, RetryDecision::RetryNextNode(None))
             }
+            // ReadTimeout - coordinator didn't receive enough replies in time.
+            // Retry only if there were actually enough replies to satisfy consistency
+            // but they were all just checksums (data_present == false).
+            // This happens when the coordinator picked replicas that were overloaded/dying.
+            // Retried request should have some useful response because the node will detect
+            // that these replicas are dead
             QueryError::DbError(
                 scylla::transport::errors::DbError::ReadTimeout {
                     received,
@@ -190,49 +265,28 @@ impl scylla::retry_policy::RetrySession for CustomRetrySession {
                 },
                 _,
             ) => {
-                tracing::warn!(
-                    "ReadTimeout - coordinator didn't receive enough replies in time.
-                    Retry at most once and only if there were actually enough replies
-                    to satisfy consistency but they were all just checksums (data_present == false).
-                    This happens when the coordinator picked replicas that were overloaded/dying.
-                    Retried request should have some useful response because the node will detect
-                    that these replicas are dead."
-                );
-                if self.should_retry() && received >= required && !*data_present {
-                    tracing::warn!("Retrying on the same node");
-                    RetryDecision::RetrySameNode(None)
+                if received >= required && !*data_present {
+                    self.retry(query_info.error, RetryDecision::RetrySameNode(None))
                 } else {
-                    tracing::warn!("Not retrying");
                     RetryDecision::DontRetry
                 }
             }
+            // WriteTimeout - coordinator didn't receive enough replies in time.
+            // Coordinator probably didn't detect the nodes as dead.
+            // By the time we retry they should be detected as dead
             QueryError::DbError(scylla::transport::errors::DbError::WriteTimeout { .. }, _) => {
-                tracing::warn!(
-                    "WriteTimeout - coordinator didn't receive enough replies in time.
-                    Retry at most once and only for BatchLog write.
-                    Coordinator probably didn't detect the nodes as dead.
-                    By the time we retry they should be detected as dead."
-                );
-                if self.should_retry() {
-                    tracing::warn!("Retrying on the next node");
-                    RetryDecision::RetryNextNode(None)
-                } else {
-                    tracing::warn!("Not retrying");
-                    RetryDecision::DontRetry
-                }
+                self.retry(query_info.error, RetryDecision::RetryNextNode(None))
             }
+            // The node is still bootstrapping it can't execute the query, we should try another one
             QueryError::DbError(scylla::transport::errors::DbError::IsBootstrapping, _) => {
-                tracing::warn!("The node is still bootstrapping it can't execute the query, we should try another one");
-                RetryDecision::RetryNextNode(None)
+                self.retry(query_info.error, RetryDecision::RetryNextNode(None))
             }
+            // Connection to the contacted node is overloaded, try another one
             QueryError::UnableToAllocStreamId => {
-                tracing::warn!("Connection to the contacted node is overloaded, try another one");
-                RetryDecision::RetryNextNode(None)
-            }
-            _ => {
-                tracing::warn!("In all other cases propagate the error to the user. Don't retry");
-                RetryDecision::DontRetry
+                self.retry(query_info.error, RetryDecision::RetryNextNode(None))
             }
+            // In all other cases propagate the error to the user. Don't retry
+            _ => RetryDecision::DontRetry,
         }
     }
 
@@ -245,45 +299,56 @@ impl scylla::retry_policy::RetrySession for CustomRetrySession {
 #[async_trait::async_trait]
 pub trait ScyllaStorageManager {
     async fn new_from_session(
-        scylla_db_session: std::sync::Arc<scylla::Session>,
+        scylla_db_session: std::sync::Arc<session::ScyllaSession>,
+        migrations_mode: migrations::MigrationsMode,
+        keyspaces: &keyspaces::ScyllaKeyspaces,
     ) -> anyhow::Result<Box<Self>> {
         tracing::info!("Running migrations into the scylla database...");
-        Self::migrate(&scylla_db_session).await?;
-        Self::prepare(scylla_db_session).await
+        Self::migrate(&scylla_db_session, migrations_mode, keyspaces).await?;
+        Self::prepare(scylla_db_session, keyspaces).await
     }
 
     async fn new(
-        scylla_url: &str,
-        scylla_user: Option<&str>,
-        scylla_password: Option<&str>,
-        scylla_preferred_dc: Option<&str>,
-        keepalive_interval: Option<u64>,
-        max_retry: u8,
-        strict_mode: bool,
+        session_options: &session::ScyllaSessionOptions,
+        migrations_mode: migrations::MigrationsMode,
+        keyspaces: &keyspaces::ScyllaKeyspaces,
     ) -> anyhow::Result<Box<Self>> {
-        let scylla_db_session = std::sync::Arc::new(
-            Self::get_scylladb_session(
-                scylla_url,
-                scylla_user,
-                scylla_password,
-                scylla_preferred_dc,
-                keepalive_interval,
-                max_retry,
-                strict_mode,
-            )
-            .await?,
-        );
-        Self::new_from_session(scylla_db_session).await
+        let scylla_db_session =
+            std::sync::Arc::new(Self::get_scylladb_session(session_options).await?);
+        Self::new_from_session(scylla_db_session, migrations_mode, keyspaces).await
+    }
+
+    /// Creates the keyspaces and runs the versioned migrations of the `migrations` in the given mode.
+    /// The keyspaces and the unversioned tables are created in the `Apply` mode only
+    async fn migrate(
+        scylla_db_session: &scylla::Session,
+        migrations_mode: migrations::MigrationsMode,
+        keyspaces: &keyspaces::ScyllaKeyspaces,
+    ) -> anyhow::Result<()> {
+        if migrations_mode == migrations::MigrationsMode::Apply {
+            Self::create_keyspace(scylla_db_session, keyspaces).await?;
+            Self::create_tables(scylla_db_session, keyspaces).await?;
+        }
+        for (keyspace, keyspace_migrations) in Self::migrations(keyspaces) {
+            keyspace_migrations
+                .run(scylla_db_session, &keyspace, migrations_mode)
+                .await?;
+        }
+        Ok(())
     }
 
-    async fn migrate(scylla_db_session: &scylla::Session) -> anyhow::Result<()> {
-        Self::create_keyspace(scylla_db_session).await?;
-        Ok(Self::create_tables(scylla_db_session).await?)
+    // The versioned migrations of the keyspaces the manager works with by the keyspace name
+    // Example:
+    //     vec![(keyspaces.scylla_tx_indexer_keyspace.clone(), &database::migrations::TX_INDEXER)]
+    fn migrations(
+        _keyspaces: &keyspaces::ScyllaKeyspaces,
+    ) -> Vec<(String, &'static migrations::KeyspaceMigrations)> {
+        vec![]
     }
 
     // Create tables
     // Example:
-    //         scylla_db_session.use_keyspace(`scylla_keyspace`, false).await?;
+    //         scylla_db_session.use_keyspace(&keyspaces.scylla_tx_indexer_keyspace, false).await?;
     //         scylla_db_session.query(
     //             "CREATE TABLE IF NOT EXISTS transactions_details (
     //                 transaction_hash varchar,
@@ -296,28 +361,34 @@ pub trait ScyllaStorageManager {
     //             &[],
     //         ).await?;
     //     Ok(())
-    async fn create_tables(_scylla_db_session: &scylla::Session) -> anyhow::Result<()> {
+    async fn create_tables(
+        _scylla_db_session: &scylla::Session,
+        _keyspaces: &keyspaces::ScyllaKeyspaces,
+    ) -> anyhow::Result<()> {
         tracing::info!("Please describe the tables in the `create_tables`, if needed.");
         Ok(())
     }
 
-    // Create keyspace
-    // Example:
-    //      scylla_db_session.query(
-    //          "CREATE KEYSPACE IF NOT EXISTS `scylla_keyspace` WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 1}",
-    //          &[]
-    //      ).await?;
-    // Ok(())
-    async fn create_keyspace(_scylla_db_session: &scylla::Session) -> anyhow::Result<()> {
-        tracing::info!("Please create  keyspace in the `create_keyspace`, if needed.");
+    // Create the keyspaces of the `migrations` with the configured replication
+    async fn create_keyspace(
+        scylla_db_session: &scylla::Session,
+        keyspaces: &keyspaces::ScyllaKeyspaces,
+    ) -> anyhow::Result<()> {
+        for (keyspace, _) in Self::migrations(keyspaces) {
+            keyspaces
+                .create_keyspace(scylla_db_session, &keyspace)
+                .await?;
+        }
         Ok(())
     }
 
+    /// `name` identifies the statement in the metrics
     async fn prepare_query(
-        scylla_db_session: &std::sync::Arc<scylla::Session>,
+        scylla_db_session: &std::sync::Arc<session::ScyllaSession>,
+        name: &str,
         mut query: scylla::statement::query::Query,
         consistency: Option<scylla::frame::types::Consistency>,
-    ) -> anyhow::Result<PreparedStatement> {
+    ) -> anyhow::Result<NamedStatement> {
         if let Some(consistency) = consistency {
             query.set_consistency(consistency);
         } else {
@@ -325,30 +396,24 @@ pub trait ScyllaStorageManager {
             query.set_consistency(scylla::frame::types::Consistency::LocalQuorum);
         }
 
-        #[cfg(not(feature = "scylla_db_tracing"))]
-        {
-            let prepared = scylla_db_session.prepare(query).await?;
-            Ok(prepared)
-        }
-
-        #[cfg(feature = "scylla_db_tracing")]
-        {
-            let mut prepared = scylla_db_session.prepare(query).await?;
-            prepared.set_tracing(true);
-            Ok(prepared)
-        }
+        Ok(NamedStatement {
+            name: name.to_string(),
+            statement: scylla_db_session.prepare(query).await?,
+        })
     }
 
     /// Wrapper to prepare read queries
     /// Just a simpler way to prepare a query with `Consistency::LocalQuorum`
     /// we use it as a default consistency for read queries
     async fn prepare_read_query(
-        scylla_db_session: &std::sync::Arc<scylla::Session>,
+        scylla_db_session: &std::sync::Arc<session::ScyllaSession>,
+        name: &str,
         query_text: &str,
-    ) -> anyhow::Result<PreparedStatement> {
+    ) -> anyhow::Result<NamedStatement> {
         let query = scylla::statement::query::Query::new(query_text);
         Self::prepare_query(
             scylla_db_session,
+            name,
             query,
             Some(scylla::frame::types::Consistency::LocalQuorum),
         )
@@ -359,12 +424,14 @@ pub trait ScyllaStorageManager {
     /// Just a simpler way to prepare a query with `Consistency::LocalQuorum`
     /// we use it as a default consistency for write queries
     async fn prepare_write_query(
-        scylla_db_session: &std::sync::Arc<scylla::Session>,
+        scylla_db_session: &std::sync::Arc<session::ScyllaSession>,
+        name: &str,
         query_text: &str,
-    ) -> anyhow::Result<PreparedStatement> {
+    ) -> anyhow::Result<NamedStatement> {
         let query = scylla::statement::query::Query::new(query_text);
         Self::prepare_query(
             scylla_db_session,
+            name,
             query,
             Some(scylla::frame::types::Consistency::LocalQuorum),
         )
@@ -372,54 +439,86 @@ pub trait ScyllaStorageManager {
     }
 
     async fn get_scylladb_session(
-        scylla_url: &str,
-        scylla_user: Option<&str>,
-        scylla_password: Option<&str>,
-        scylla_preferred_dc: Option<&str>,
-        keepalive_interval: Option<u64>,
-        max_retry: u8,
-        strict_mode: bool,
-    ) -> anyhow::Result<scylla::Session> {
+        session_options: &session::ScyllaSessionOptions,
+    ) -> anyhow::Result<session::ScyllaSession> {
         let mut load_balancing_policy_builder =
             scylla::transport::load_balancing::DefaultPolicy::builder();
 
-        if let Some(scylla_preferred_dc) = scylla_preferred_dc {
+        if let Some(scylla_preferred_dc) = &session_options.scylla_preferred_dc {
             load_balancing_policy_builder =
                 load_balancing_policy_builder.prefer_datacenter(scylla_preferred_dc.to_string());
         }
 
+        let retry_backoff = retry::RetryBackoff {
+            initial_delay: std::time::Duration::from_millis(
+                session_options.connection.scylla_retry_initial_delay,
+            ),
+            max_delay: std::time::Duration::from_millis(
+                session_options.connection.scylla_retry_max_delay,
+            ),
+            budget: if session_options.strict_mode {
+                None
+            } else {
+                Some(std::time::Duration::from_millis(
+                    session_options.connection.scylla_retry_budget,
+                ))
+            },
+        };
+        let blob_compression = blob_compression::BlobCompression {
+            level: session_options.connection.scylla_blob_compression_level,
+            min_size: session_options.connection.scylla_blob_compression_min_size,
+        };
+
         let scylla_execution_profile_handle = scylla::transport::ExecutionProfile::builder()
-            .retry_policy(Box::new(CustomDBRetryPolicy::new(max_retry, strict_mode)))
+            .retry_policy(Box::new(CustomDBRetryPolicy::new(
+                session_options.max_retry,
+            )))
             .load_balancing_policy(load_balancing_policy_builder.build())
             .build()
             .into_handle();
 
+        let connection = &session_options.connection;
         let mut session: scylla::SessionBuilder = scylla::SessionBuilder::new()
-            .known_node(scylla_url)
-            .default_execution_profile_handle(scylla_execution_profile_handle);
-
-        if let Some(keepalive) = keepalive_interval {
+            .known_nodes(&session_options.contact_points())
+            .default_execution_profile_handle(scylla_execution_profile_handle)
+            .connection_timeout(std::time::Duration::from_secs(
+                connection.scylla_connection_timeout,
+            ))
+            .compression(connection.scylla_compression.map(Into::into))
+            .ssl_context(connection.ssl_context()?);
+
+        if let Some(pool_size) = connection.scylla_pool_size_per_host {
+            session = session.pool_size(scylla::transport::session::PoolSize::PerHost(pool_size));
+        }
+        if let Some(keepalive) = session_options.keepalive_interval {
             session = session.keepalive_interval(std::time::Duration::from_secs(keepalive));
         }
-        if let Some(user) = scylla_user {
-            if let Some(password) = scylla_password {
+        if let Some(user) = &session_options.scylla_user {
+            if let Some(password) = &session_options.scylla_password {
                 session = session.user(user, password);
             }
         }
 
-        Ok(session.build().await?)
+        let scylla_session =
+            session::ScyllaSession::new(session.build().await?, retry_backoff, blob_compression);
+        #[cfg(feature = "scylla_db_tracing")]
+        let scylla_session =
+            scylla_session.with_tracing_sample_rate(connection.scylla_tracing_sample_rate);
+        Ok(scylla_session)
     }
 
     // Prepare manager and queries
     async fn prepare(
-        scylla_db_session: std::sync::Arc<scylla::Session>,
+        scylla_db_session: std::sync::Arc<session::ScyllaSession>,
+        keyspaces: &keyspaces::ScyllaKeyspaces,
     ) -> anyhow::Result<Box<Self>>;
     // Example:
     // {
     //     Ok(Self {
     //         scylla_session: scylla_db_session.clone(),
-    //         add_transaction: Self::prepare_query(
+    //         add_transaction: Self::prepare_write_query(
     //             &scylla_db_session,
+    //             "add_transaction",
     //             "INSERT INTO transactions_details
     //                 (transaction_hash, block_height, account_id, transaction_details)
     //                 VALUES(?, ?, ?, ?)",
@@ -429,92 +528,110 @@ pub trait ScyllaStorageManager {
     // }
 
     async fn execute_prepared_query(
-        scylla_session: &scylla::Session,
-        query: &PreparedStatement,
+        scylla_session: &session::ScyllaSession,
+        query: &NamedStatement,
         values: impl scylla::frame::value::ValueList + std::marker::Send,
     ) -> anyhow::Result<scylla::QueryResult> {
-        let result = scylla_session.execute(query, values).await?;
+        let statement_name = query.name.as_str();
 
+        let query = std::borrow::Cow::Borrowed(&query.statement);
+        // The tracing is enabled on a copy of the statement for the sampled queries only
         #[cfg(feature = "scylla_db_tracing")]
-        if query.get_tracing() {
-            let tracing_id: Option<uuid::Uuid> = result.tracing_id;
-            if let Some(id) = tracing_id {
-                // Query tracing info from system_traces.sessions and system_traces.events
-                let tracing_info: scylla::tracing::TracingInfo =
-                    scylla_session.get_tracing_info(&id).await?;
-                Self::log_tracing_info(tracing_info).await;
-            }
-        }
-
-        Ok(result)
-    }
-
-    // For now we show all scylla tracing_info datails
-    // In future we can left only needed
-    #[cfg(feature = "scylla_db_tracing")]
-    async fn log_tracing_info(tracing_info: scylla::tracing::TracingInfo) {
-        let mut tracing_info_table = prettytable::table!(["Parameter", "Info"]);
-
-        if let Some(client) = tracing_info.client {
-            tracing_info_table.add_row(prettytable::row!["Client", client]);
-        }
-
-        if let Some(cmd) = tracing_info.command {
-            tracing_info_table.add_row(prettytable::row!["Command", cmd]);
-        }
-
-        if let Some(coord) = tracing_info.coordinator {
-            tracing_info_table.add_row(prettytable::row!["Coordinator", coord]);
-        }
-
-        if let Some(drn) = tracing_info.duration {
-            tracing_info_table.add_row(prettytable::row!["Duration", drn]);
-        }
+        let query = if query_tracing::sampled(scylla_session.tracing_sample_rate) {
+            let mut traced_query = query.into_owned();
+            traced_query.set_tracing(true);
+            std::borrow::Cow::Owned(traced_query)
+        } else {
+            query
+        };
 
-        if let Some(params) = tracing_info.parameters {
-            let mut params_table = prettytable::table!(["Param", "Value"]);
-            for (param, value) in params.iter() {
-                params_table.add_row(prettytable::row![param, value]);
+        let latency_timer = metrics::SCYLLA_QUERY_LATENCY_SECONDS
+            .with_label_values(&[statement_name])
+            .start_timer();
+        let result = match retry::execute_with_backoff(scylla_session, &query, values).await {
+            Ok(result) => result,
+            Err(error) => {
+                metrics::SCYLLA_QUERY_ERRORS_TOTAL
+                    .with_label_values(&[statement_name, retry::error_class(&error)])
+                    .inc();
+                return Err(error.into());
             }
-            tracing_info_table.add_row(prettytable::row!["Parameters", params_table]);
-        }
-
-        if let Some(req) = tracing_info.request {
-            tracing_info_table.add_row(prettytable::row!["Request", req]);
+        };
+        latency_timer.observe_duration();
+        if let Some(rows) = &result.rows {
+            metrics::SCYLLA_QUERY_ROWS_TOTAL
+                .with_label_values(&[statement_name])
+                .inc_by(rows.len() as u64);
         }
 
-        if let Some(start_at) = tracing_info.started_at {
-            tracing_info_table.add_row(prettytable::row!["Started at", start_at]);
+        #[cfg(feature = "scylla_db_tracing")]
+        if let Some(tracing_id) = result.tracing_id {
+            // Query tracing info from system_traces.sessions and system_traces.events
+            match scylla_session.get_tracing_info(&tracing_id).await {
+                Ok(tracing_info) => Self::log_tracing_info(statement_name, tracing_info).await,
+                Err(err) => tracing::warn!("Failed to get the ScyllaDB tracing info: {:?}", err),
+            }
         }
 
-        let mut events_table =
-            prettytable::table!(["Activity", "Thread", "Source", "Source elapsed"]);
-        for event in tracing_info.events.iter() {
-            let mut event_row = vec![];
-            let none_val = prettytable::Cell::new(&String::from("Unknown"));
-            match &event.activity {
-                Some(act) => event_row.push(prettytable::Cell::new(act)),
-                None => event_row.push(none_val.clone()),
-            }
+        Ok(result)
+    }
 
-            match &event.thread {
-                Some(thr) => event_row.push(prettytable::Cell::new(thr)),
-                None => event_row.push(none_val.clone()),
+    /// Executes the batch built with `batch::PartitionBatches` with the same retries as the prepared queries
+    async fn execute_batch(
+        scylla_session: &session::ScyllaSession,
+        batch: &scylla::batch::Batch,
+        values: Vec<scylla::frame::value::SerializedValues>,
+    ) -> anyhow::Result<scylla::QueryResult> {
+        metrics::SCYLLA_BATCH_SIZE.observe(values.len() as f64);
+        let latency_timer = metrics::SCYLLA_QUERY_LATENCY_SECONDS
+            .with_label_values(&["batch"])
+            .start_timer();
+        let result = match retry::with_backoff(&scylla_session.retry_backoff, || {
+            scylla_session.batch(batch, &values)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(error) => {
+                metrics::SCYLLA_QUERY_ERRORS_TOTAL
+                    .with_label_values(&["batch", retry::error_class(&error)])
+                    .inc();
+                return Err(error.into());
             }
+        };
+        latency_timer.observe_duration();
+        Ok(result)
+    }
 
-            match &event.source {
-                Some(src) => event_row.push(prettytable::Cell::new(&src.to_string())),
-                None => event_row.push(none_val.clone()),
-            }
+    // Emits the tracing info as the `scylla_trace` span of the current span
+    #[cfg(feature = "scylla_db_tracing")]
+    async fn log_tracing_info(statement_name: &str, tracing_info: scylla::tracing::TracingInfo) {
+        query_tracing::record(statement_name, &tracing_info);
+    }
+}
 
-            match &event.source_elapsed {
-                Some(src_el) => event_row.push(prettytable::Cell::new(&src_el.to_string())),
-                None => event_row.push(none_val),
-            }
-            events_table.add_row(prettytable::Row::new(event_row));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_prefix_range_covers_the_keys_with_the_prefix() {
+        let (start, end) = hex_prefix_range(b"ab");
+        assert_eq!((start.as_str(), end.as_str()), ("6162", "6162g"));
+        for key in [&b"ab"[..], b"ab\x00", b"ab\xff\xff", b"abc"] {
+            let key = hex::encode(key);
+            assert!(start <= key && key < end, "{} is out of the range", key);
         }
-        tracing_info_table.add_row(prettytable::row!["Events", events_table]);
+        for key in [&b"a"[..], b"aa\xff", b"ac", b"b"] {
+            let key = hex::encode(key);
+            assert!(!(start <= key && key < end), "{} is in the range", key);
+        }
+    }
 
-        tracing_info_table.printstd();
+    #[test]
+    fn hex_prefix_range_of_the_empty_prefix_covers_every_key() {
+        let (start, end) = hex_prefix_range(b"");
+        assert_eq!((start.as_str(), end.as_str()), ("", "g"));
+        assert!(hex::encode([0xffu8; 32]) < end);
     }
 }
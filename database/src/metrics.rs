@@ -0,0 +1,104 @@
+use prometheus::{Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts};
+
+type Result<T, E> = std::result::Result<T, E>;
+
+// The metrics are registered in the default registry,
+// so they are exported by the metrics server of every binary
+
+fn try_create_int_counter(name: &str, help: &str) -> Result<IntCounter, prometheus::Error> {
+    let opts = Opts::new(name, help);
+    let counter = IntCounter::with_opts(opts)?;
+    prometheus::register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+fn try_create_int_counter_vec(
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> Result<IntCounterVec, prometheus::Error> {
+    let opts = Opts::new(name, help);
+    let counter = IntCounterVec::new(opts, labels)?;
+    prometheus::register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+fn try_create_histogram(
+    name: &str,
+    help: &str,
+    buckets: Vec<f64>,
+) -> Result<Histogram, prometheus::Error> {
+    let opts = HistogramOpts::new(name, help).buckets(buckets);
+    let histogram = Histogram::with_opts(opts)?;
+    prometheus::register(Box::new(histogram.clone()))?;
+    Ok(histogram)
+}
+
+fn try_create_histogram_vec(
+    name: &str,
+    help: &str,
+    labels: &[&str],
+    buckets: Vec<f64>,
+) -> Result<HistogramVec, prometheus::Error> {
+    let opts = HistogramOpts::new(name, help).buckets(buckets);
+    let histogram = HistogramVec::new(opts, labels)?;
+    prometheus::register(Box::new(histogram.clone()))?;
+    Ok(histogram)
+}
+
+lazy_static::lazy_static! {
+    /// `kind` is `immediate` for the retries on the next node made by the driver
+    /// and `backoff` for the retries made after the backoff delay
+    pub static ref SCYLLA_RETRIES_TOTAL: IntCounterVec = try_create_int_counter_vec(
+        "scylla_retries_total",
+        "Total number of the retried ScyllaDB queries by the error class",
+        &["error", "kind"]
+    )
+    .unwrap();
+    pub static ref SCYLLA_RETRY_BUDGET_EXCEEDED_TOTAL: IntCounter = try_create_int_counter(
+        "scylla_retry_budget_exceeded_total",
+        "Total number of the ScyllaDB queries failed after exceeding the retry time budget"
+    )
+    .unwrap();
+    /// Including the retries, `statement` is the name the statement was prepared with
+    pub static ref SCYLLA_QUERY_LATENCY_SECONDS: HistogramVec = try_create_histogram_vec(
+        "scylla_query_latency_seconds",
+        "Latency of the ScyllaDB prepared statements",
+        &["statement"],
+        vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0],
+    )
+    .unwrap();
+    pub static ref SCYLLA_QUERY_ROWS_TOTAL: IntCounterVec = try_create_int_counter_vec(
+        "scylla_query_rows_total",
+        "Total number of the rows returned by the ScyllaDB prepared statements",
+        &["statement"]
+    )
+    .unwrap();
+    /// Counted once per failed call after all the retries
+    pub static ref SCYLLA_QUERY_ERRORS_TOTAL: IntCounterVec = try_create_int_counter_vec(
+        "scylla_query_errors_total",
+        "Total number of the failed ScyllaDB prepared statements by the error class",
+        &["statement", "error"]
+    )
+    .unwrap();
+    pub static ref SCYLLA_BATCH_SIZE: Histogram = try_create_histogram(
+        "scylla_batch_size",
+        "Number of the statements in the ScyllaDB batches",
+        vec![1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0],
+    )
+    .unwrap();
+    /// The compression ratio of the blobs of the `kind` is the ratio of the input
+    /// and the output rates, the blobs below the min size are not counted
+    pub static ref BLOB_COMPRESSION_INPUT_BYTES_TOTAL: IntCounterVec = try_create_int_counter_vec(
+        "blob_compression_input_bytes_total",
+        "Total size of the blobs before the compression by the kind of the blob",
+        &["kind"]
+    )
+    .unwrap();
+    pub static ref BLOB_COMPRESSION_OUTPUT_BYTES_TOTAL: IntCounterVec = try_create_int_counter_vec(
+        "blob_compression_output_bytes_total",
+        "Total size of the stored blobs after the compression by the kind of the blob",
+        &["kind"]
+    )
+    .unwrap();
+}
@@ -0,0 +1,610 @@
+// Versioned migrations of the ScyllaDB keyspaces.
+//
+// Every keyspace has its own ordered list of migrations numbered from 1 without gaps,
+// the keyspaces are named by `ScyllaKeyspaces`.
+// The migrations live in `database/migrations/scylla/<keyspace>` as
+// `<version>_<description>.up.cql` and, if the migration can be reverted, `<version>_<description>.down.cql`.
+// The statements in the files are separated by `;`, the lines starting with `--` are comments.
+//
+// The applied versions are recorded in the `schema_migrations` table of the keyspace.
+// On startup every binary compares the recorded versions with the migrations it is built with
+// and refuses to run against the schema it doesn't know (older or newer one).
+//
+// The migrations are applied and reverted under the lock taken with a lightweight transaction
+// in the `schema_migrations_lock` table, so the binaries started at the same time don't apply
+// the same migration twice. The statements are idempotent (`IF NOT EXISTS`, the columns that
+// already exist are not added), so the migration interrupted halfway is applied again on the next start.
+
+use scylla::IntoTypedRows;
+
+/// The lock expires if the binary holding it dies before releasing it
+const LOCK_TTL_SECONDS: u32 = 600;
+const LOCK_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: &'static str,
+    /// `None` if the migration can't be reverted
+    pub down: Option<&'static str>,
+}
+
+/// The migrations of the keyspace, the name of the keyspace is configured with `ScyllaKeyspaces`
+pub struct KeyspaceMigrations {
+    pub migrations: &'static [Migration],
+}
+
+pub const STATE_INDEXER: KeyspaceMigrations = KeyspaceMigrations {
+    migrations: &[
+        Migration {
+            version: 1,
+            description: "create_tables",
+            up: include_str!("../migrations/scylla/state_indexer/0001_create_tables.up.cql"),
+            down: Some(include_str!(
+                "../migrations/scylla/state_indexer/0001_create_tables.down.cql"
+            )),
+        },
+        Migration {
+            version: 2,
+            description: "account_access_keys",
+            up: include_str!("../migrations/scylla/state_indexer/0002_account_access_keys.up.cql"),
+            down: Some(include_str!(
+                "../migrations/scylla/state_indexer/0002_account_access_keys.down.cql"
+            )),
+        },
+        Migration {
+            version: 3,
+            description: "account_state_versions",
+            up: include_str!(
+                "../migrations/scylla/state_indexer/0003_account_state_versions.up.cql"
+            ),
+            down: Some(include_str!(
+                "../migrations/scylla/state_indexer/0003_account_state_versions.down.cql"
+            )),
+        },
+        Migration {
+            version: 4,
+            description: "contract_code_by_hash",
+            up: include_str!(
+                "../migrations/scylla/state_indexer/0004_contract_code_by_hash.up.cql"
+            ),
+            down: None,
+        },
+    ],
+};
+
+pub const TX_INDEXER: KeyspaceMigrations = KeyspaceMigrations {
+    migrations: &[Migration {
+        version: 1,
+        description: "create_tables",
+        up: include_str!("../migrations/scylla/tx_indexer/0001_create_tables.up.cql"),
+        down: Some(include_str!(
+            "../migrations/scylla/tx_indexer/0001_create_tables.down.cql"
+        )),
+    }],
+};
+
+pub const TX_INDEXER_CACHE: KeyspaceMigrations = KeyspaceMigrations {
+    migrations: &[Migration {
+        version: 1,
+        description: "create_tables",
+        up: include_str!("../migrations/scylla/tx_indexer_cache/0001_create_tables.up.cql"),
+        down: Some(include_str!(
+            "../migrations/scylla/tx_indexer_cache/0001_create_tables.down.cql"
+        )),
+    }],
+};
+
+/// The `meta` row recorded once the keys of the legacy `account_state` index are copied into
+/// `account_state_versions` (the migration 3 of the state-indexer keyspace), until then the readers
+/// list the keys of both indexes
+pub const STATE_KEYS_BACKFILL_MARKER: &str = "account_state_versions_backfill";
+
+/// What to do with the schema on startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationsMode {
+    /// Apply the pending migrations
+    Apply,
+    /// Log the statements of the pending migrations without applying them
+    DryRun,
+    /// Only check the schema is the one the binary is built with
+    Check,
+    /// Revert the applied migrations down to the given version (`rollback:<version>`)
+    Rollback(u32),
+}
+
+impl std::str::FromStr for MigrationsMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "apply" => Ok(Self::Apply),
+            "dry-run" => Ok(Self::DryRun),
+            "check" => Ok(Self::Check),
+            _ => match s.strip_prefix("rollback:") {
+                Some(version) => Ok(Self::Rollback(version.parse()?)),
+                None => anyhow::bail!(
+                    "Unknown migrations mode {}, expected one of: apply, dry-run, check, rollback:<version>",
+                    s
+                ),
+            },
+        }
+    }
+}
+
+impl KeyspaceMigrations {
+    fn latest_version(&self) -> u32 {
+        self.migrations
+            .last()
+            .map(|migration| migration.version)
+            .unwrap_or_default()
+    }
+
+    /// The migrations have to be numbered from 1 without gaps
+    fn validate(&self, keyspace: &str) -> anyhow::Result<()> {
+        for (index, migration) in self.migrations.iter().enumerate() {
+            if migration.version as usize != index + 1 {
+                anyhow::bail!(
+                    "Migration {}_{} of the keyspace {} is out of order, expected version {}",
+                    migration.version,
+                    migration.description,
+                    keyspace,
+                    index + 1
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings the schema of the keyspace in line with the migrations according to the mode.
+    /// In the `apply` and `check` modes fails if the schema is not the one the binary is built with afterwards,
+    /// the dry run and the rollback are expected to leave another schema, so it is not checked
+    pub async fn run(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+        mode: MigrationsMode,
+    ) -> anyhow::Result<()> {
+        self.validate(keyspace)?;
+        match mode {
+            MigrationsMode::Apply => {
+                self.create_migrations_table(scylla_db_session, keyspace)
+                    .await?;
+                let owner = self.lock(scylla_db_session, keyspace).await?;
+                let result = self.apply(scylla_db_session, keyspace, false).await;
+                self.unlock(scylla_db_session, keyspace, &owner).await?;
+                result?;
+                self.check(scylla_db_session, keyspace).await
+            }
+            MigrationsMode::DryRun => {
+                self.apply(scylla_db_session, keyspace, true).await?;
+                tracing::warn!(
+                    "Dry run: the schema of the keyspace {} is not checked",
+                    keyspace
+                );
+                Ok(())
+            }
+            MigrationsMode::Check => self.check(scylla_db_session, keyspace).await,
+            MigrationsMode::Rollback(version) => {
+                self.create_migrations_table(scylla_db_session, keyspace)
+                    .await?;
+                let owner = self.lock(scylla_db_session, keyspace).await?;
+                let result = self.rollback(scylla_db_session, keyspace, version).await;
+                self.unlock(scylla_db_session, keyspace, &owner).await?;
+                result?;
+                tracing::warn!(
+                    "The keyspace {} is rolled back to the version {}, the schema is not checked",
+                    keyspace,
+                    version
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Fails if the applied migrations differ from the ones the binary is built with
+    pub async fn check(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+    ) -> anyhow::Result<()> {
+        let applied_versions = self.applied_versions(scylla_db_session, keyspace).await?;
+        let expected_versions: Vec<u32> = (1..=self.latest_version()).collect();
+        if applied_versions != expected_versions {
+            anyhow::bail!(
+                "The schema of the keyspace {} is incompatible: applied migrations {:?}, expected {:?}. \
+                Run the binary with the `apply` migrations mode or use the binary built for this schema",
+                keyspace,
+                applied_versions,
+                expected_versions
+            );
+        }
+        tracing::info!(
+            "The schema of the keyspace {} is at version {}",
+            keyspace,
+            self.latest_version()
+        );
+        Ok(())
+    }
+
+    async fn apply(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let applied_versions = self.applied_versions(scylla_db_session, keyspace).await?;
+        if let Some(unknown_version) = applied_versions
+            .iter()
+            .find(|version| **version > self.latest_version())
+        {
+            anyhow::bail!(
+                "The keyspace {} has the migration {} applied which is unknown to this binary",
+                keyspace,
+                unknown_version
+            );
+        }
+
+        for migration in self
+            .migrations
+            .iter()
+            .filter(|migration| !applied_versions.contains(&migration.version))
+        {
+            if dry_run {
+                tracing::info!(
+                    "Dry run: the migration {}_{} of the keyspace {} is pending:\n{}",
+                    migration.version,
+                    migration.description,
+                    keyspace,
+                    statements(migration.up).join(";\n")
+                );
+                continue;
+            }
+            tracing::info!(
+                "Applying the migration {}_{} to the keyspace {}...",
+                migration.version,
+                migration.description,
+                keyspace
+            );
+            scylla_db_session.use_keyspace(keyspace, false).await?;
+            for statement in statements(migration.up) {
+                if let Some((table, column)) = added_column(&statement) {
+                    if self
+                        .column_exists(scylla_db_session, keyspace, table, column)
+                        .await?
+                    {
+                        tracing::info!("The column {}.{} already exists, skipping", table, column);
+                        continue;
+                    }
+                }
+                scylla_db_session.query(statement.as_str(), &[]).await?;
+            }
+            scylla_db_session
+                .query(
+                    format!(
+                        "INSERT INTO {}.schema_migrations (version, description, applied_at) VALUES (?, ?, ?)",
+                        keyspace
+                    ),
+                    (
+                        i32::try_from(migration.version)?,
+                        migration.description,
+                        now_millis()?,
+                    ),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Reverts the applied migrations newer than `version` starting from the latest one
+    async fn rollback(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+        version: u32,
+    ) -> anyhow::Result<()> {
+        let applied_versions = self.applied_versions(scylla_db_session, keyspace).await?;
+        for migration in self
+            .migrations
+            .iter()
+            .rev()
+            .filter(|migration| migration.version > version)
+            .filter(|migration| applied_versions.contains(&migration.version))
+        {
+            let down = migration.down.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The migration {}_{} of the keyspace {} can't be reverted",
+                    migration.version,
+                    migration.description,
+                    keyspace
+                )
+            })?;
+            tracing::info!(
+                "Reverting the migration {}_{} of the keyspace {}...",
+                migration.version,
+                migration.description,
+                keyspace
+            );
+            scylla_db_session.use_keyspace(keyspace, false).await?;
+            for statement in statements(down) {
+                scylla_db_session.query(statement, &[]).await?;
+            }
+            scylla_db_session
+                .query(
+                    format!(
+                        "DELETE FROM {}.schema_migrations WHERE version = ?",
+                        keyspace
+                    ),
+                    (i32::try_from(migration.version)?,),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn create_migrations_table(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+    ) -> anyhow::Result<()> {
+        scylla_db_session
+            .query(
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}.schema_migrations (
+                        version int PRIMARY KEY,
+                        description varchar,
+                        applied_at bigint
+                    )",
+                    keyspace
+                ),
+                &[],
+            )
+            .await?;
+        scylla_db_session
+            .query(
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}.schema_migrations_lock (
+                        id int PRIMARY KEY,
+                        owner varchar,
+                        acquired_at bigint
+                    )",
+                    keyspace
+                ),
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Waits until the migrations lock of the keyspace is taken, returns the owner to release it with
+    async fn lock(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+    ) -> anyhow::Result<String> {
+        let owner = format!("{}-{:016x}", std::process::id(), rand::random::<u64>());
+        loop {
+            let result = scylla_db_session
+                .query(
+                    format!(
+                        "INSERT INTO {}.schema_migrations_lock (id, owner, acquired_at) VALUES (0, ?, ?) IF NOT EXISTS USING TTL {}",
+                        keyspace, LOCK_TTL_SECONDS
+                    ),
+                    (owner.as_str(), now_millis()?),
+                )
+                .await?;
+            let applied = result
+                .first_row()?
+                .columns
+                .first()
+                .and_then(|value| value.as_ref())
+                .and_then(|value| value.as_boolean())
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "The migrations lock response of the keyspace {} has no [applied] column",
+                        keyspace
+                    )
+                })?;
+            if applied {
+                return Ok(owner);
+            }
+            tracing::info!(
+                "The migrations of the keyspace {} are run by another process, waiting for the lock...",
+                keyspace
+            );
+            tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+        }
+    }
+
+    async fn unlock(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+        owner: &str,
+    ) -> anyhow::Result<()> {
+        scylla_db_session
+            .query(
+                format!(
+                    "DELETE FROM {}.schema_migrations_lock WHERE id = 0 IF owner = ?",
+                    keyspace
+                ),
+                (owner,),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn column_exists(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+        table: &str,
+        column: &str,
+    ) -> anyhow::Result<bool> {
+        Ok(!scylla_db_session
+            .query(
+                "SELECT column_name FROM system_schema.columns WHERE keyspace_name = ? AND table_name = ? AND column_name = ?",
+                (keyspace, table, column),
+            )
+            .await?
+            .rows
+            .unwrap_or_default()
+            .is_empty())
+    }
+
+    /// Returns the applied versions in ascending order,
+    /// empty if the migrations were never applied to the keyspace
+    async fn applied_versions(
+        &self,
+        scylla_db_session: &scylla::Session,
+        keyspace: &str,
+    ) -> anyhow::Result<Vec<u32>> {
+        let migrations_table = scylla_db_session
+            .query(
+                "SELECT table_name FROM system_schema.tables WHERE keyspace_name = ? AND table_name = 'schema_migrations'",
+                (keyspace,),
+            )
+            .await?
+            .rows
+            .unwrap_or_default();
+        if migrations_table.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut versions = vec![];
+        if let Some(rows) = scylla_db_session
+            .query(
+                format!("SELECT version FROM {}.schema_migrations", keyspace),
+                &[],
+            )
+            .await?
+            .rows
+        {
+            for row in rows.into_typed::<(i32,)>() {
+                let (version,) = row?;
+                versions.push(u32::try_from(version)?);
+            }
+        }
+        versions.sort_unstable();
+        Ok(versions)
+    }
+}
+
+/// Splits the migration file into the statements skipping the comments
+fn statements(migration: &str) -> Vec<String> {
+    migration
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .split(';')
+        .map(|statement| statement.trim().to_string())
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// Returns the table and the column of the `ALTER TABLE <table> ADD <column> <type>` statement
+fn added_column(statement: &str) -> Option<(&str, &str)> {
+    let mut words = statement.split_whitespace();
+    match (
+        words.next(),
+        words.next(),
+        words.next(),
+        words.next(),
+        words.next(),
+    ) {
+        (Some(alter), Some(table_keyword), Some(table), Some(add), Some(column))
+            if alter.eq_ignore_ascii_case("ALTER")
+                && table_keyword.eq_ignore_ascii_case("TABLE")
+                && add.eq_ignore_ascii_case("ADD") =>
+        {
+            Some((table, column))
+        }
+        _ => None,
+    }
+}
+
+fn now_millis() -> anyhow::Result<i64> {
+    Ok(i64::try_from(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_skip_the_comments_and_the_empty_statements() {
+        let migration = "-- The comment; with a separator\nCREATE TABLE a (\n    id int PRIMARY KEY\n);\n\n  -- Another comment\nALTER TABLE a ADD b varchar;\n;\n";
+        assert_eq!(
+            statements(migration),
+            vec![
+                "CREATE TABLE a (\n    id int PRIMARY KEY\n)".to_string(),
+                "ALTER TABLE a ADD b varchar".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_of_the_built_in_migrations_are_not_empty() {
+        for keyspace_migrations in [STATE_INDEXER, TX_INDEXER, TX_INDEXER_CACHE] {
+            for migration in keyspace_migrations.migrations {
+                assert!(!statements(migration.up).is_empty());
+                if let Some(down) = migration.down {
+                    assert!(!statements(down).is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn added_column_is_parsed_from_the_alter_statement() {
+        assert_eq!(
+            added_column("ALTER TABLE state_changes_contract ADD code_hash varchar"),
+            Some(("state_changes_contract", "code_hash"))
+        );
+        assert_eq!(
+            added_column("CREATE TABLE IF NOT EXISTS a (id int PRIMARY KEY)"),
+            None
+        );
+        assert_eq!(added_column("ALTER TABLE a DROP b"), None);
+    }
+
+    #[test]
+    fn migrations_mode_is_parsed() {
+        assert_eq!(
+            "apply".parse::<MigrationsMode>().unwrap(),
+            MigrationsMode::Apply
+        );
+        assert_eq!(
+            "dry-run".parse::<MigrationsMode>().unwrap(),
+            MigrationsMode::DryRun
+        );
+        assert_eq!(
+            "check".parse::<MigrationsMode>().unwrap(),
+            MigrationsMode::Check
+        );
+        assert_eq!(
+            "rollback:3".parse::<MigrationsMode>().unwrap(),
+            MigrationsMode::Rollback(3)
+        );
+    }
+
+    #[test]
+    fn migrations_mode_rejects_the_unknown_values() {
+        assert!("Apply".parse::<MigrationsMode>().is_err());
+        assert!("rollback".parse::<MigrationsMode>().is_err());
+        assert!("rollback:latest".parse::<MigrationsMode>().is_err());
+        assert!("rollback:-1".parse::<MigrationsMode>().is_err());
+    }
+
+    #[test]
+    fn built_in_migrations_are_in_order() {
+        STATE_INDEXER.validate("state_indexer").unwrap();
+        TX_INDEXER.validate("tx_indexer").unwrap();
+        TX_INDEXER_CACHE.validate("tx_indexer_cache").unwrap();
+    }
+}
@@ -0,0 +1,618 @@
+// PostgreSQL storage for the deployments already operating Postgres
+// that would rather not run a ScyllaDB cluster for the read-rpc.
+//
+// The schemas `state_indexer` and `tx_indexer` mirror the ScyllaDB keyspaces with the same names.
+// The migrations live in `database/migrations/postgres` and are handled on connect according
+// to the same `MigrationsMode` as the ScyllaDB keyspaces, the applied versions are tracked
+// by sqlx in the `_sqlx_migrations` table. The PostgreSQL migrations can't be reverted.
+//
+// The block heights are stored as BIGINT, the keys are hex-encoded the same way
+// they are in ScyllaDB, so the data can be compared between the storages.
+
+use crate::migrations::MigrationsMode;
+use crate::VersionedRecord;
+
+pub const STATE_INDEXER_SCHEMA: &str = "state_indexer";
+pub const TX_INDEXER_SCHEMA: &str = "tx_indexer";
+
+pub struct PostgresDBManager {
+    pool: sqlx::PgPool,
+}
+
+impl PostgresDBManager {
+    /// Connects to the database and handles the migrations according to the mode
+    pub async fn connect(
+        database_url: &str,
+        max_connections: u32,
+        migrations_mode: MigrationsMode,
+    ) -> anyhow::Result<Self> {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(database_url)
+            .await?;
+        run_migrations(&pool, migrations_mode).await?;
+        Ok(Self { pool })
+    }
+
+    /// Returns the latest record of the versioned table at or before the given block height
+    async fn get_latest(
+        &self,
+        table: &str,
+        account_id: &str,
+        data_key: Option<&[u8]>,
+        block_height: u64,
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        let row: Option<(i64, String, Option<Vec<u8>>)> = match data_key {
+            Some(data_key) => {
+                sqlx::query_as(&format!(
+                    "SELECT block_height, block_hash, data_value FROM state_indexer.{table}
+                        WHERE account_id = $1 AND data_key = $2 AND block_height <= $3
+                        ORDER BY block_height DESC LIMIT 1"
+                ))
+                .bind(account_id)
+                .bind(hex::encode(data_key))
+                .bind(to_i64(block_height)?)
+                .fetch_optional(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query_as(&format!(
+                    "SELECT block_height, block_hash, data_value FROM state_indexer.{table}
+                        WHERE account_id = $1 AND block_height <= $2
+                        ORDER BY block_height DESC LIMIT 1"
+                ))
+                .bind(account_id)
+                .bind(to_i64(block_height)?)
+                .fetch_optional(&self.pool)
+                .await?
+            }
+        };
+        row.map(|(block_height, block_hash, data)| {
+            Ok(VersionedRecord {
+                block_height: to_u64(block_height)?,
+                block_hash,
+                data,
+            })
+        })
+        .transpose()
+    }
+
+    /// Stores the value of the versioned table without the `data_key`
+    async fn put_versioned(
+        &self,
+        table: &str,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        data: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(&format!(
+            "INSERT INTO state_indexer.{table} (account_id, block_height, block_hash, data_value)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (account_id, block_height)
+                DO UPDATE SET block_hash = EXCLUDED.block_hash, data_value = EXCLUDED.data_value"
+        ))
+        .bind(account_id)
+        .bind(to_i64(block_height)?)
+        .bind(block_hash)
+        .bind(data)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_meta(
+        &self,
+        schema: &str,
+        indexer_id: &str,
+        block_height: u64,
+    ) -> anyhow::Result<()> {
+        sqlx::query(&format!(
+            "INSERT INTO {schema}.meta (indexer_id, last_processed_block_height)
+                VALUES ($1, $2)
+                ON CONFLICT (indexer_id)
+                DO UPDATE SET last_processed_block_height = EXCLUDED.last_processed_block_height"
+        ))
+        .bind(indexer_id)
+        .bind(to_i64(block_height)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_last_processed_block_height(
+        &self,
+        schema: &str,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<u64>> {
+        let row: Option<(i64,)> = sqlx::query_as(&format!(
+            "SELECT last_processed_block_height FROM {schema}.meta WHERE indexer_id = $1"
+        ))
+        .bind(indexer_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|(block_height,)| to_u64(block_height)).transpose()
+    }
+}
+
+// state_indexer
+impl PostgresDBManager {
+    /// Stores the state value of the account, `None` value means the key was deleted
+    pub async fn put_state_value(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        data_key: &[u8],
+        data_value: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        let data_key = hex::encode(data_key);
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO state_indexer.state_changes_data
+                (account_id, block_height, block_hash, data_key, data_value)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (account_id, data_key, block_height)
+                DO UPDATE SET block_hash = EXCLUDED.block_hash, data_value = EXCLUDED.data_value",
+        )
+        .bind(account_id)
+        .bind(to_i64(block_height)?)
+        .bind(block_hash)
+        .bind(&data_key)
+        .bind(data_value)
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query(
+            "INSERT INTO state_indexer.account_state (account_id, data_key)
+                VALUES ($1, $2)
+                ON CONFLICT DO NOTHING",
+        )
+        .bind(account_id)
+        .bind(&data_key)
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_state_value(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        data_key: &[u8],
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest(
+            "state_changes_data",
+            account_id,
+            Some(data_key),
+            block_height,
+        )
+        .await
+    }
+
+    /// Returns the state keys of the account starting with the given prefix
+    /// which have a value at the given block height.
+    /// The latest version of every key at the block height is picked with `DISTINCT ON`
+    /// served by the primary key of `state_changes_data`
+    pub async fn get_state_keys_by_prefix(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        prefix: &[u8],
+    ) -> anyhow::Result<Vec<Vec<u8>>> {
+        let (start, end) = crate::hex_prefix_range(prefix);
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "SELECT data_key FROM (
+                SELECT DISTINCT ON (data_key) data_key, data_value
+                    FROM state_indexer.state_changes_data
+                    WHERE account_id = $1 AND data_key >= $2 AND data_key < $3
+                        AND block_height <= $4
+                    ORDER BY data_key, block_height DESC
+                ) latest
+                WHERE data_value IS NOT NULL",
+        )
+        .bind(account_id)
+        .bind(start)
+        .bind(end)
+        .bind(to_i64(block_height)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|(data_key,)| Ok(hex::decode(data_key)?))
+            .collect()
+    }
+
+    /// Stores the access key of the account, `None` value means the access key was deleted
+    pub async fn put_access_key(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        public_key: &[u8],
+        access_key: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO state_indexer.state_changes_access_key
+                (account_id, block_height, block_hash, data_key, data_value)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (account_id, data_key, block_height)
+                DO UPDATE SET block_hash = EXCLUDED.block_hash, data_value = EXCLUDED.data_value",
+        )
+        .bind(account_id)
+        .bind(to_i64(block_height)?)
+        .bind(block_hash)
+        .bind(hex::encode(public_key))
+        .bind(access_key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_access_key(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        public_key: &[u8],
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest(
+            "state_changes_access_key",
+            account_id,
+            Some(public_key),
+            block_height,
+        )
+        .await
+    }
+
+    /// Returns the active access keys of the account at the given block height
+    /// as pairs of the serialized public key and the serialized access key
+    pub async fn get_access_keys(
+        &self,
+        account_id: &str,
+        block_height: u64,
+    ) -> anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let rows: Vec<(String, Option<Vec<u8>>)> = sqlx::query_as(
+            "SELECT DISTINCT ON (data_key) data_key, data_value
+                FROM state_indexer.state_changes_access_key
+                WHERE account_id = $1 AND block_height <= $2
+                ORDER BY data_key, block_height DESC",
+        )
+        .bind(account_id)
+        .bind(to_i64(block_height)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .filter_map(|(public_key, access_key)| {
+                access_key.map(|access_key| Ok((hex::decode(public_key)?, access_key)))
+            })
+            .collect()
+    }
+
+    /// Stores the contract code of the account, `None` value means the contract was deleted
+    pub async fn put_contract_code(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        code: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        self.put_versioned(
+            "state_changes_contract",
+            account_id,
+            block_height,
+            block_hash,
+            code,
+        )
+        .await
+    }
+
+    pub async fn get_contract_code(
+        &self,
+        account_id: &str,
+        block_height: u64,
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest("state_changes_contract", account_id, None, block_height)
+            .await
+    }
+
+    /// Stores the account, `None` value means the account was deleted
+    pub async fn put_account(
+        &self,
+        account_id: &str,
+        block_height: u64,
+        block_hash: &str,
+        account: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        self.put_versioned(
+            "state_changes_account",
+            account_id,
+            block_height,
+            block_hash,
+            account,
+        )
+        .await
+    }
+
+    pub async fn get_account(
+        &self,
+        account_id: &str,
+        block_height: u64,
+    ) -> anyhow::Result<Option<VersionedRecord>> {
+        self.get_latest("state_changes_account", account_id, None, block_height)
+            .await
+    }
+
+    pub async fn add_block(&self, block_hash: &str, block_height: u64) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO state_indexer.blocks (block_hash, block_height)
+                VALUES ($1, $2)
+                ON CONFLICT (block_hash) DO UPDATE SET block_height = EXCLUDED.block_height",
+        )
+        .bind(block_hash)
+        .bind(to_i64(block_height)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_block_height_by_hash(&self, block_hash: &str) -> anyhow::Result<Option<u64>> {
+        let row: Option<(i64,)> =
+            sqlx::query_as("SELECT block_height FROM state_indexer.blocks WHERE block_hash = $1")
+                .bind(block_hash)
+                .fetch_optional(&self.pool)
+                .await?;
+        row.map(|(block_height,)| to_u64(block_height)).transpose()
+    }
+
+    /// Stores the chunks of the block as `(chunk_hash, shard_id, stored_at_block_height)`
+    pub async fn add_chunks(
+        &self,
+        block_height: u64,
+        chunks: &[(String, u64, u64)],
+    ) -> anyhow::Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        for (chunk_hash, shard_id, stored_at_block_height) in chunks {
+            sqlx::query(
+                "INSERT INTO state_indexer.chunks
+                    (chunk_hash, block_height, shard_id, stored_at_block_height)
+                    VALUES ($1, $2, $3, $4)
+                    ON CONFLICT (chunk_hash, block_height)
+                    DO UPDATE SET shard_id = EXCLUDED.shard_id,
+                        stored_at_block_height = EXCLUDED.stored_at_block_height",
+            )
+            .bind(chunk_hash)
+            .bind(to_i64(block_height)?)
+            .bind(to_i64(*shard_id)?)
+            .bind(to_i64(*stored_at_block_height)?)
+            .execute(&mut transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Returns the `(stored_at_block_height, shard_id)` of the chunk
+    pub async fn get_chunk(&self, chunk_hash: &str) -> anyhow::Result<Option<(u64, u64)>> {
+        let row: Option<(i64, i64)> = sqlx::query_as(
+            "SELECT stored_at_block_height, shard_id FROM state_indexer.chunks
+                WHERE chunk_hash = $1 LIMIT 1",
+        )
+        .bind(chunk_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|(stored_at_block_height, shard_id)| {
+            Ok((to_u64(stored_at_block_height)?, to_u64(shard_id)?))
+        })
+        .transpose()
+    }
+
+    /// Returns the height of the block the chunk of the given block and shard is stored at
+    pub async fn get_chunk_stored_at_block_height(
+        &self,
+        block_height: u64,
+        shard_id: u64,
+    ) -> anyhow::Result<Option<u64>> {
+        let row: Option<(i64,)> = sqlx::query_as(
+            "SELECT stored_at_block_height FROM state_indexer.chunks
+                WHERE block_height = $1 AND shard_id = $2 LIMIT 1",
+        )
+        .bind(to_i64(block_height)?)
+        .bind(to_i64(shard_id)?)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|(stored_at_block_height,)| to_u64(stored_at_block_height))
+            .transpose()
+    }
+
+    /// Stores all the serialized state changes of the block at once
+    pub async fn add_block_state_changes(
+        &self,
+        block_height: u64,
+        block_hash: &str,
+        state_changes: &[Vec<u8>],
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO state_indexer.state_changes_by_block
+                (block_height, block_hash, state_changes)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (block_height)
+                DO UPDATE SET block_hash = EXCLUDED.block_hash,
+                    state_changes = EXCLUDED.state_changes",
+        )
+        .bind(to_i64(block_height)?)
+        .bind(block_hash)
+        .bind(state_changes)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_block_state_changes(
+        &self,
+        block_height: u64,
+    ) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        let row: Option<(Vec<Vec<u8>>,)> = sqlx::query_as(
+            "SELECT state_changes FROM state_indexer.state_changes_by_block
+                WHERE block_height = $1",
+        )
+        .bind(to_i64(block_height)?)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|(state_changes,)| state_changes))
+    }
+}
+
+// tx_indexer
+impl PostgresDBManager {
+    pub async fn add_transaction(
+        &self,
+        transaction_hash: &str,
+        block_height: u64,
+        account_id: &str,
+        transaction_details: &[u8],
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO tx_indexer.transactions_details
+                (transaction_hash, block_height, account_id, transaction_details)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (transaction_hash, block_height)
+                DO UPDATE SET account_id = EXCLUDED.account_id,
+                    transaction_details = EXCLUDED.transaction_details",
+        )
+        .bind(transaction_hash)
+        .bind(to_i64(block_height)?)
+        .bind(account_id)
+        .bind(transaction_details)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the transaction details stored at the highest block height,
+    /// the same way ScyllaDB orders the hash collisions
+    pub async fn get_transaction(&self, transaction_hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let row: Option<(Vec<u8>,)> = sqlx::query_as(
+            "SELECT transaction_details FROM tx_indexer.transactions_details
+                WHERE transaction_hash = $1
+                ORDER BY block_height DESC LIMIT 1",
+        )
+        .bind(transaction_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|(transaction_details,)| transaction_details))
+    }
+
+    pub async fn add_receipt(
+        &self,
+        receipt_id: &str,
+        parent_transaction_hash: &str,
+        block_height: u64,
+        shard_id: u64,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO tx_indexer.receipts_map
+                (receipt_id, block_height, parent_transaction_hash, shard_id)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (receipt_id)
+                DO UPDATE SET block_height = EXCLUDED.block_height,
+                    parent_transaction_hash = EXCLUDED.parent_transaction_hash,
+                    shard_id = EXCLUDED.shard_id",
+        )
+        .bind(receipt_id)
+        .bind(to_i64(block_height)?)
+        .bind(parent_transaction_hash)
+        .bind(to_i64(shard_id)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the `(parent_transaction_hash, block_height, shard_id)` of the receipt
+    pub async fn get_receipt(
+        &self,
+        receipt_id: &str,
+    ) -> anyhow::Result<Option<(String, u64, u64)>> {
+        let row: Option<(String, i64, i64)> = sqlx::query_as(
+            "SELECT parent_transaction_hash, block_height, shard_id FROM tx_indexer.receipts_map
+                WHERE receipt_id = $1",
+        )
+        .bind(receipt_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|(parent_transaction_hash, block_height, shard_id)| {
+            Ok((
+                parent_transaction_hash,
+                to_u64(block_height)?,
+                to_u64(shard_id)?,
+            ))
+        })
+        .transpose()
+    }
+}
+
+/// Brings the schema in line with the migrations according to the mode.
+/// Only `check` fails on the pending migrations, `apply` fails on the unknown applied ones
+async fn run_migrations(pool: &sqlx::PgPool, mode: MigrationsMode) -> anyhow::Result<()> {
+    let migrator = sqlx::migrate!("./migrations/postgres");
+    match mode {
+        MigrationsMode::Apply => {
+            tracing::info!("Running migrations into the postgres database...");
+            migrator.run(pool).await?;
+        }
+        MigrationsMode::DryRun => {
+            let applied_versions = applied_versions(pool).await?;
+            for migration in migrator
+                .iter()
+                .filter(|migration| !applied_versions.contains(&migration.version))
+            {
+                tracing::info!(
+                    "Dry run: the migration {}_{} of the postgres database is pending:\n{}",
+                    migration.version,
+                    migration.description,
+                    migration.sql
+                );
+            }
+        }
+        MigrationsMode::Check => {
+            let applied_versions = applied_versions(pool).await?;
+            let expected_versions: Vec<i64> =
+                migrator.iter().map(|migration| migration.version).collect();
+            if applied_versions != expected_versions {
+                anyhow::bail!(
+                    "The schema of the postgres database is incompatible: applied migrations {:?}, expected {:?}. \
+                    Run the binary with the `apply` migrations mode or use the binary built for this schema",
+                    applied_versions,
+                    expected_versions
+                );
+            }
+        }
+        MigrationsMode::Rollback(_) => {
+            anyhow::bail!("The migrations of the postgres database can't be reverted")
+        }
+    }
+    Ok(())
+}
+
+/// Returns the successfully applied versions in ascending order,
+/// empty if the migrations were never applied to the database
+async fn applied_versions(pool: &sqlx::PgPool) -> anyhow::Result<Vec<i64>> {
+    let (migrations_table_exists,): (bool,) =
+        sqlx::query_as("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(pool)
+            .await?;
+    if !migrations_table_exists {
+        return Ok(vec![]);
+    }
+    let rows: Vec<(i64,)> =
+        sqlx::query_as("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows.into_iter().map(|(version,)| version).collect())
+}
+
+fn to_i64(value: u64) -> anyhow::Result<i64> {
+    i64::try_from(value).map_err(|_| anyhow::anyhow!("{} does not fit into BIGINT", value))
+}
+
+fn to_u64(value: i64) -> anyhow::Result<u64> {
+    u64::try_from(value).map_err(|_| anyhow::anyhow!("Unexpected negative value {}", value))
+}
@@ -0,0 +1,36 @@
+// Server-side tracing of the sampled ScyllaDB queries (`scylla_db_tracing` feature).
+//
+// The sampled query is executed with the tracing enabled, the tracing info is read back from
+// `system_traces` and emitted as the `scylla_trace` span with the events of the coordinator
+// and the replicas. The span is a child of the current span, so with the `tracing-instrumentation`
+// feature the traces of the queries show up in Jaeger under the request that made them.
+// The fraction of the traced queries is the `tracing_sample_rate` of the `ScyllaSession`.
+
+/// Decides if the query should be traced
+pub fn sampled(sample_rate: f64) -> bool {
+    rand::random::<f64>() < sample_rate
+}
+
+pub fn record(statement_name: &str, tracing_info: &scylla::tracing::TracingInfo) {
+    let span = tracing::debug_span!(
+        "scylla_trace",
+        statement = statement_name,
+        coordinator = ?tracing_info.coordinator,
+        duration_micros = ?tracing_info.duration,
+        command = ?tracing_info.command,
+        request = ?tracing_info.request,
+        client = ?tracing_info.client,
+        started_at = ?tracing_info.started_at,
+        parameters = ?tracing_info.parameters,
+    );
+    let _enter = span.enter();
+    for event in tracing_info.events.iter() {
+        tracing::debug!(
+            source = ?event.source,
+            source_elapsed_micros = ?event.source_elapsed,
+            thread = ?event.thread,
+            "{}",
+            event.activity.as_deref().unwrap_or("Unknown")
+        );
+    }
+}
@@ -0,0 +1,185 @@
+// Backoff between the retries of the failed queries.
+//
+// The retry policy of the driver decides synchronously and retries immediately,
+// so `CustomRetrySession` only switches to the next node a limited number of times.
+// When the driver gives up on the retriable error `execute_prepared_query` waits
+// with the exponential backoff and the full jitter and executes the query again
+// until the time budget of the query is exceeded. In `strict_mode` there is no budget
+// to ensure no data is missing, the query is retried with the backoff until it succeeds.
+// The backoff is the setting of the `ScyllaSession` the query is executed with.
+
+use scylla::transport::errors::{BadQuery, DbError, QueryError};
+
+#[derive(Debug, Clone)]
+pub struct RetryBackoff {
+    pub initial_delay: std::time::Duration,
+    pub max_delay: std::time::Duration,
+    /// `None` means retry until the query succeeds
+    pub budget: Option<std::time::Duration>,
+}
+
+impl Default for RetryBackoff {
+    /// By default using `strict_mode`
+    fn default() -> Self {
+        Self {
+            initial_delay: std::time::Duration::from_millis(100),
+            max_delay: std::time::Duration::from_secs(10),
+            budget: None,
+        }
+    }
+}
+
+impl RetryBackoff {
+    /// Full jitter: a random delay up to the exponentially growing cap
+    pub fn delay(&self, attempt: u32) -> std::time::Duration {
+        let cap = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        cap.mul_f64(rand::random::<f64>())
+    }
+
+    pub fn within_budget(&self, elapsed: std::time::Duration) -> bool {
+        self.budget.map_or(true, |budget| elapsed <= budget)
+    }
+}
+
+/// Executes the query with `with_backoff` of the session
+pub(crate) async fn execute_with_backoff(
+    scylla_session: &crate::session::ScyllaSession,
+    query: &scylla::prepared_statement::PreparedStatement,
+    values: impl scylla::frame::value::ValueList,
+) -> Result<scylla::QueryResult, QueryError> {
+    // Serialize once to execute the same values on every attempt
+    let values = values
+        .serialized()
+        .map_err(|error| QueryError::BadQuery(BadQuery::SerializeValuesError(error)))?
+        .into_owned();
+    with_backoff(&scylla_session.retry_backoff, || {
+        scylla_session.execute(query, &values)
+    })
+    .await
+}
+
+/// Runs the operation retrying the retriable errors with the backoff
+/// within its time budget
+pub(crate) async fn with_backoff<T, F, Fut>(
+    backoff: &RetryBackoff,
+    mut operation: F,
+) -> Result<T, QueryError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, QueryError>>,
+{
+    let started_at = std::time::Instant::now();
+    let mut attempt = 0;
+    loop {
+        let error = match operation().await {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+        let error_class = match retriable_error_class(&error) {
+            Some(error_class) => error_class,
+            None => return Err(error),
+        };
+        let delay = backoff.delay(attempt);
+        if !backoff.within_budget(started_at.elapsed() + delay) {
+            crate::metrics::SCYLLA_RETRY_BUDGET_EXCEEDED_TOTAL.inc();
+            tracing::error!(
+                "ScyllaDB query failed after {} retries in {:?}: {:?}",
+                attempt,
+                started_at.elapsed(),
+                error
+            );
+            return Err(error);
+        }
+        crate::metrics::SCYLLA_RETRIES_TOTAL
+            .with_label_values(&[error_class, "backoff"])
+            .inc();
+        if let Some(suppressed) = RETRY_LOG_THROTTLE.check() {
+            tracing::warn!(
+                "ScyllaDB QueryError: {:?}. Retrying in {:?} ({} similar messages suppressed)",
+                error,
+                delay,
+                suppressed
+            );
+        }
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Returns the class of the error used as the metrics label
+/// if the query may succeed when retried, `None` otherwise
+pub fn retriable_error_class(error: &QueryError) -> Option<&'static str> {
+    match error {
+        QueryError::IoError(_) => Some("io"),
+        QueryError::DbError(DbError::Overloaded, _) => Some("overloaded"),
+        QueryError::DbError(DbError::ServerError, _) => Some("server_error"),
+        QueryError::DbError(DbError::TruncateError, _) => Some("truncate_error"),
+        QueryError::DbError(DbError::Unavailable { .. }, _) => Some("unavailable"),
+        QueryError::DbError(DbError::ReadTimeout { .. }, _) => Some("read_timeout"),
+        QueryError::DbError(DbError::WriteTimeout { .. }, _) => Some("write_timeout"),
+        QueryError::DbError(DbError::IsBootstrapping, _) => Some("is_bootstrapping"),
+        QueryError::UnableToAllocStreamId => Some("unable_to_alloc_stream_id"),
+        QueryError::TimeoutError => Some("timeout"),
+        _ => None,
+    }
+}
+
+/// Returns the class of any error used as the metrics label
+pub fn error_class(error: &QueryError) -> &'static str {
+    if let Some(error_class) = retriable_error_class(error) {
+        return error_class;
+    }
+    match error {
+        QueryError::DbError(_, _) => "db_error",
+        QueryError::BadQuery(_) => "bad_query",
+        QueryError::ProtocolError(_) | QueryError::InvalidMessage(_) => "protocol_error",
+        _ => "other",
+    }
+}
+
+/// Lets through one message per interval and counts the suppressed ones,
+/// so a failing cluster doesn't flood the logs with a warning per attempt
+pub struct LogThrottle {
+    interval_millis: u64,
+    last_logged_at: std::sync::atomic::AtomicU64,
+    suppressed: std::sync::atomic::AtomicU64,
+}
+
+impl LogThrottle {
+    pub const fn new(interval: std::time::Duration) -> Self {
+        Self {
+            interval_millis: interval.as_millis() as u64,
+            last_logged_at: std::sync::atomic::AtomicU64::new(0),
+            suppressed: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of the messages suppressed since the last logged one
+    /// if the message should be logged now
+    pub fn check(&self) -> Option<u64> {
+        use std::sync::atomic::Ordering;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+        let last_logged_at = self.last_logged_at.load(Ordering::Relaxed);
+        if now.saturating_sub(last_logged_at) >= self.interval_millis
+            && self
+                .last_logged_at
+                .compare_exchange(last_logged_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            Some(self.suppressed.swap(0, Ordering::Relaxed))
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+pub(crate) static RETRY_LOG_THROTTLE: LogThrottle =
+    LogThrottle::new(std::time::Duration::from_secs(1));
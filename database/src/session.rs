@@ -0,0 +1,182 @@
+// The options of the ScyllaDB session.
+//
+// `ScyllaSessionOptions` is built by every binary from its own options, since the defaults
+// (e.g. `max_retry` and `strict_mode`) differ between the indexers and the rpc-server.
+// The production connection settings (TLS, timeouts, pooling and compression) are the same
+// for all of them, so the binaries flatten `ScyllaConnectionArgs` into their options.
+//
+// `ScyllaSession` is the driver session together with the settings of the queries made
+// through it, so the managers sharing the session share the settings and the sessions
+// created with different options in the same process don't affect each other.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScyllaCompression {
+    Lz4,
+    Snappy,
+}
+
+impl std::str::FromStr for ScyllaCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lz4" => Ok(Self::Lz4),
+            "snappy" => Ok(Self::Snappy),
+            _ => anyhow::bail!("Unknown compression {}, expected lz4 or snappy", s),
+        }
+    }
+}
+
+impl From<ScyllaCompression> for scylla::transport::Compression {
+    fn from(compression: ScyllaCompression) -> Self {
+        match compression {
+            ScyllaCompression::Lz4 => Self::Lz4,
+            ScyllaCompression::Snappy => Self::Snappy,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScyllaConnectionArgs {
+    /// Path to the CA certificate (PEM) to verify the ScyllaDB nodes with. Enables TLS
+    #[clap(long, env)]
+    pub scylla_tls_ca_cert: Option<std::path::PathBuf>,
+    /// Path to the client certificate (PEM) for the nodes requiring the client authentication.
+    /// Requires `scylla_tls_ca_cert` and `scylla_tls_client_key`
+    #[clap(long, env)]
+    pub scylla_tls_client_cert: Option<std::path::PathBuf>,
+    /// Path to the private key (PEM) of the client certificate
+    #[clap(long, env)]
+    pub scylla_tls_client_key: Option<std::path::PathBuf>,
+    /// Timeout of establishing the connection to a node, in seconds
+    #[clap(long, default_value = "5", env)]
+    pub scylla_connection_timeout: u64,
+    /// Number of the connections to every node.
+    /// By default the driver opens one connection per shard of the node
+    #[clap(long, env)]
+    pub scylla_pool_size_per_host: Option<std::num::NonZeroUsize>,
+    /// Compression of the traffic between the binary and the nodes: lz4 or snappy
+    #[clap(long, env)]
+    pub scylla_compression: Option<ScyllaCompression>,
+    /// Initial delay of the backoff between the retries of the failed query, in milliseconds.
+    /// The delay doubles on every retry and is randomized (full jitter)
+    #[clap(long, default_value = "100", env)]
+    pub scylla_retry_initial_delay: u64,
+    /// Max delay of the backoff between the retries of the failed query, in milliseconds
+    #[clap(long, default_value = "10000", env)]
+    pub scylla_retry_max_delay: u64,
+    /// Total time to retry the failed query, in milliseconds. Ignored in `strict_mode`,
+    /// where the query is retried until it succeeds
+    #[clap(long, default_value = "30000", env)]
+    pub scylla_retry_budget: u64,
+    /// zstd level (1-22) of the compression of the contract code, the contract data values
+    /// and the transaction details written by the indexers. The blobs are written uncompressed
+    /// if not set, both the compressed and the uncompressed blobs are read regardless of it.
+    /// Applies to ScyllaDB only, the embedded RocksDB and the PostgreSQL storages write the blobs as is
+    #[clap(long, env)]
+    pub scylla_blob_compression_level: Option<i32>,
+    /// The blobs smaller than this, in bytes, are written uncompressed. Applies to ScyllaDB only
+    #[clap(long, default_value = "512", env)]
+    pub scylla_blob_compression_min_size: usize,
+    /// Fraction of the queries traced by ScyllaDB, from 0.0 to 1.0
+    #[cfg(feature = "scylla_db_tracing")]
+    #[clap(long, default_value = "0.01", env)]
+    pub scylla_tracing_sample_rate: f64,
+}
+
+impl ScyllaConnectionArgs {
+    /// Returns `None` if TLS is not configured
+    pub fn ssl_context(&self) -> anyhow::Result<Option<openssl::ssl::SslContext>> {
+        let ca_cert = match &self.scylla_tls_ca_cert {
+            Some(ca_cert) => ca_cert,
+            None => {
+                if self.scylla_tls_client_cert.is_some() || self.scylla_tls_client_key.is_some() {
+                    anyhow::bail!("The TLS client certificate requires `scylla_tls_ca_cert`");
+                }
+                return Ok(None);
+            }
+        };
+
+        let mut context_builder =
+            openssl::ssl::SslContextBuilder::new(openssl::ssl::SslMethod::tls())?;
+        context_builder.set_ca_file(ca_cert)?;
+        context_builder.set_verify(openssl::ssl::SslVerifyMode::PEER);
+        match (&self.scylla_tls_client_cert, &self.scylla_tls_client_key) {
+            (Some(client_cert), Some(client_key)) => {
+                context_builder
+                    .set_certificate_file(client_cert, openssl::ssl::SslFiletype::PEM)?;
+                context_builder.set_private_key_file(client_key, openssl::ssl::SslFiletype::PEM)?;
+                context_builder.check_private_key()?;
+            }
+            (None, None) => {}
+            _ => anyhow::bail!(
+                "Both `scylla_tls_client_cert` and `scylla_tls_client_key` have to be provided"
+            ),
+        }
+        Ok(Some(context_builder.build()))
+    }
+}
+
+pub struct ScyllaSessionOptions {
+    /// Comma-separated list of the contact points. Example: "10.0.0.1:9042,10.0.0.2:9042"
+    pub scylla_url: String,
+    pub scylla_user: Option<String>,
+    pub scylla_password: Option<String>,
+    pub scylla_preferred_dc: Option<String>,
+    /// In seconds
+    pub keepalive_interval: Option<u64>,
+    pub max_retry: u8,
+    pub strict_mode: bool,
+    pub connection: ScyllaConnectionArgs,
+}
+
+impl ScyllaSessionOptions {
+    pub fn contact_points(&self) -> Vec<&str> {
+        self.scylla_url
+            .split(',')
+            .map(str::trim)
+            .filter(|contact_point| !contact_point.is_empty())
+            .collect()
+    }
+}
+
+/// The driver session with the settings applied by `ScyllaStorageManager` to the queries made through it
+pub struct ScyllaSession {
+    session: scylla::Session,
+    pub retry_backoff: crate::retry::RetryBackoff,
+    /// Compression of the blobs written by the indexers
+    pub blob_compression: crate::blob_compression::BlobCompression,
+    /// Fraction of the queries traced by ScyllaDB, all the queries are traced by default
+    #[cfg(feature = "scylla_db_tracing")]
+    pub tracing_sample_rate: f64,
+}
+
+impl ScyllaSession {
+    pub fn new(
+        session: scylla::Session,
+        retry_backoff: crate::retry::RetryBackoff,
+        blob_compression: crate::blob_compression::BlobCompression,
+    ) -> Self {
+        Self {
+            session,
+            retry_backoff,
+            blob_compression,
+            #[cfg(feature = "scylla_db_tracing")]
+            tracing_sample_rate: 1.0,
+        }
+    }
+
+    #[cfg(feature = "scylla_db_tracing")]
+    pub fn with_tracing_sample_rate(mut self, sample_rate: f64) -> Self {
+        self.tracing_sample_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl std::ops::Deref for ScyllaSession {
+    type Target = scylla::Session;
+
+    fn deref(&self) -> &Self::Target {
+        &self.session
+    }
+}
@@ -0,0 +1,225 @@
+// Parallel full scans of the ScyllaDB tables by the token ranges.
+//
+// The token ring (`i64::MIN..=i64::MAX`) is split into contiguous sub-ranges queried at most
+// `parallel_queries` at a time, as a sub-range query completes the next one is started,
+// until all the sub-ranges are completed.
+// The recommended values are:
+//     parallel queries = (nodes in cluster) ✕ (cores in node) ✕ 3
+//     sub-ranges = (parallel queries) ✕ 100
+//
+// The statement has to select by the token range of the partition key with two bind markers:
+//     SELECT ... FROM table WHERE token(partition_key) >= ? AND token(partition_key) <= ?
+
+use futures::StreamExt;
+
+const SUB_RANGES_PER_PARALLEL_QUERY: u64 = 100;
+
+/// Inclusive range of the tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+/// Splits the token ring into `sub_ranges` contiguous ranges covering every token
+pub fn split_token_ring(sub_ranges: u64) -> Vec<TokenRange> {
+    let sub_ranges = u128::from(sub_ranges.max(1));
+    let ring_size = 1u128 << 64;
+    (0..sub_ranges)
+        .map(|index| TokenRange {
+            start: token(ring_size * index / sub_ranges),
+            end: token(ring_size * (index + 1) / sub_ranges - 1),
+        })
+        .collect()
+}
+
+/// Converts the offset from the start of the ring to the token
+fn token(offset: u128) -> i64 {
+    (offset as i128 + i128::from(i64::MIN)) as i64
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanProgress {
+    pub total_ranges: u64,
+    pub completed_ranges: u64,
+    pub rows: u64,
+}
+
+#[derive(Debug, Default)]
+struct ProgressCounters {
+    completed_ranges: std::sync::atomic::AtomicU64,
+    rows: std::sync::atomic::AtomicU64,
+}
+
+pub struct TokenRangeScan {
+    scylla_session: std::sync::Arc<crate::session::ScyllaSession>,
+    statement: crate::NamedStatement,
+    parallel_queries: usize,
+    ranges: Vec<TokenRange>,
+    progress: std::sync::Arc<ProgressCounters>,
+}
+
+impl TokenRangeScan {
+    pub fn new(
+        scylla_session: std::sync::Arc<crate::session::ScyllaSession>,
+        statement: crate::NamedStatement,
+        parallel_queries: usize,
+    ) -> Self {
+        let parallel_queries = parallel_queries.max(1);
+        Self {
+            scylla_session,
+            statement,
+            parallel_queries,
+            ranges: split_token_ring(parallel_queries as u64 * SUB_RANGES_PER_PARALLEL_QUERY),
+            progress: std::sync::Arc::new(ProgressCounters::default()),
+        }
+    }
+
+    /// Overrides the default number of the sub-ranges (`parallel_queries` ✕ 100)
+    pub fn with_sub_ranges(mut self, sub_ranges: u64) -> Self {
+        self.ranges = split_token_ring(sub_ranges);
+        self
+    }
+
+    /// Can be called while the rows are streamed
+    pub fn progress(&self) -> ScanProgress {
+        ScanProgress {
+            total_ranges: self.ranges.len() as u64,
+            completed_ranges: self
+                .progress
+                .completed_ranges
+                .load(std::sync::atomic::Ordering::Relaxed),
+            rows: self
+                .progress
+                .rows
+                .load(std::sync::atomic::Ordering::Relaxed),
+        }
+    }
+
+    /// Streams the rows of all the sub-ranges, the rows of the sub-range come together
+    /// but the sub-ranges come in the order of completion.
+    /// The stream yields the error and continues with the other sub-ranges if the sub-range query fails
+    pub fn rows<RowT>(&self) -> futures::stream::BoxStream<'static, anyhow::Result<RowT>>
+    where
+        RowT: scylla::FromRow + Send + 'static,
+    {
+        let scylla_session = self.scylla_session.clone();
+        let statement = self.statement.statement.clone();
+        let progress = self.progress.clone();
+        let total_ranges = self.ranges.len();
+        let statement_name = self.statement.name.clone();
+
+        futures::stream::iter(self.ranges.clone())
+            .map(move |range| {
+                let scylla_session = scylla_session.clone();
+                let statement = statement.clone();
+                let progress = progress.clone();
+                let statement_name = statement_name.clone();
+                tokio::spawn(async move {
+                    let rows = scan_range::<RowT>(&scylla_session, statement, range).await?;
+                    crate::metrics::SCYLLA_QUERY_ROWS_TOTAL
+                        .with_label_values(&[&statement_name])
+                        .inc_by(rows.len() as u64);
+                    progress
+                        .rows
+                        .fetch_add(rows.len() as u64, std::sync::atomic::Ordering::Relaxed);
+                    let completed_ranges = progress
+                        .completed_ranges
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                        + 1;
+                    tracing::debug!(
+                        "Token range scan of {}: {}/{} sub-ranges completed",
+                        statement_name,
+                        completed_ranges,
+                        total_ranges
+                    );
+                    Ok(rows)
+                })
+            })
+            .buffer_unordered(self.parallel_queries)
+            .flat_map(|rows| {
+                let rows = match rows {
+                    Ok(Ok(rows)) => rows.into_iter().map(Ok).collect(),
+                    Ok(Err(err)) => vec![Err(err)],
+                    Err(err) => vec![Err(err.into())],
+                };
+                futures::stream::iter(rows)
+            })
+            .boxed()
+    }
+}
+
+async fn scan_range<RowT: scylla::FromRow>(
+    scylla_session: &scylla::Session,
+    statement: scylla::prepared_statement::PreparedStatement,
+    range: TokenRange,
+) -> anyhow::Result<Vec<RowT>> {
+    let mut rows = vec![];
+    let mut rows_stream = scylla_session
+        .execute_iter(statement, (range.start, range.end))
+        .await?
+        .into_typed::<RowT>();
+    while let Some(row) = rows_stream.next().await {
+        rows.push(row?);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_covers_the_ring(ranges: &[TokenRange]) {
+        assert_eq!(ranges.first().unwrap().start, i64::MIN);
+        assert_eq!(ranges.last().unwrap().end, i64::MAX);
+        for range in ranges {
+            assert!(range.start <= range.end, "{:?} is empty", range);
+        }
+        for pair in ranges.windows(2) {
+            assert_eq!(
+                i128::from(pair[0].end) + 1,
+                i128::from(pair[1].start),
+                "{:?} and {:?} are not contiguous",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn split_token_ring_covers_the_ring_without_gaps() {
+        for sub_ranges in [1, 2, 3, 7, 100, 1000, 12_345] {
+            let ranges = split_token_ring(sub_ranges);
+            assert_eq!(ranges.len() as u64, sub_ranges);
+            assert_covers_the_ring(&ranges);
+        }
+    }
+
+    #[test]
+    fn split_token_ring_returns_the_whole_ring_for_zero_sub_ranges() {
+        assert_eq!(
+            split_token_ring(0),
+            vec![TokenRange {
+                start: i64::MIN,
+                end: i64::MAX
+            }]
+        );
+    }
+
+    #[test]
+    fn split_token_ring_splits_into_equal_ranges() {
+        assert_eq!(
+            split_token_ring(2),
+            vec![
+                TokenRange {
+                    start: i64::MIN,
+                    end: -1
+                },
+                TokenRange {
+                    start: 0,
+                    end: i64::MAX
+                },
+            ]
+        );
+    }
+}
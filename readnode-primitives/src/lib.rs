@@ -135,3 +135,84 @@ impl TransactionDetails {
         }
     }
 }
+
+/// The state change of the block as it is stored in the `state_changes_by_block` table.
+/// The contract code is already stored once per the code hash, so the code of
+/// the `ContractCodeUpdate` is replaced with its hash and resolved on read.
+/// The changes stored before keep the code itself and have no `code_hash`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredStateChange {
+    #[serde(flatten)]
+    pub state_change: views::StateChangeWithCauseView,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_hash: Option<near_indexer_primitives::CryptoHash>,
+}
+
+impl StoredStateChange {
+    pub fn new(state_change: &views::StateChangeWithCauseView) -> Self {
+        let mut code_hash = None;
+        let value = match &state_change.value {
+            views::StateChangeValueView::ContractCodeUpdate { account_id, code } => {
+                code_hash = Some(near_indexer_primitives::near_primitives::hash::hash(code));
+                views::StateChangeValueView::ContractCodeUpdate {
+                    account_id: account_id.clone(),
+                    code: vec![],
+                }
+            }
+            value => value.clone(),
+        };
+        Self {
+            state_change: views::StateChangeWithCauseView {
+                cause: state_change.cause.clone(),
+                value,
+            },
+            code_hash,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract_code_update(code: Vec<u8>) -> views::StateChangeWithCauseView {
+        views::StateChangeWithCauseView {
+            cause: views::StateChangeCauseView::InitialState,
+            value: views::StateChangeValueView::ContractCodeUpdate {
+                account_id: "test.near".parse().unwrap(),
+                code,
+            },
+        }
+    }
+
+    #[test]
+    fn contract_code_is_stored_as_its_hash() {
+        let code = b"contract code".to_vec();
+        let stored =
+            serde_json::to_vec(&StoredStateChange::new(&contract_code_update(code.clone())))
+                .unwrap();
+
+        let stored: StoredStateChange = serde_json::from_slice(&stored).unwrap();
+        assert_eq!(
+            stored.code_hash,
+            Some(near_indexer_primitives::near_primitives::hash::hash(&code))
+        );
+        assert!(matches!(
+            stored.state_change.value,
+            views::StateChangeValueView::ContractCodeUpdate { code, .. } if code.is_empty()
+        ));
+    }
+
+    #[test]
+    fn state_changes_stored_before_are_read_without_code_hash() {
+        let code = b"contract code".to_vec();
+        let stored = serde_json::to_vec(&contract_code_update(code.clone())).unwrap();
+
+        let stored: StoredStateChange = serde_json::from_slice(&stored).unwrap();
+        assert_eq!(stored.code_hash, None);
+        assert!(matches!(
+            stored.state_change.value,
+            views::StateChangeValueView::ContractCodeUpdate { code: stored_code, .. } if stored_code == code
+        ));
+    }
+}
@@ -79,3 +79,436 @@ impl<K: std::hash::Hash + Eq, V> LruMemoryCache<K, V> {
         self.inner.len()
     }
 }
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NegativeCacheKey {
+    Account(near_primitives::types::AccountId),
+    AccessKey(near_primitives::types::AccountId, near_crypto::PublicKey),
+}
+
+/// A bounded cache of the accounts and access keys known to be missing in the database.
+/// Every entry keeps the block height the miss was observed at. The entry is invalidated
+/// as soon as a new block touches the account or the access key, and expires after
+/// `ttl_blocks`. Only the misses of the blocks already stored by the state-indexer
+/// are remembered, see `remember_missing`.
+pub struct NegativeCache {
+    inner: Option<lru::LruCache<NegativeCacheKey, u64>>,
+    ttl_blocks: u64,
+}
+
+impl NegativeCache {
+    /// Create a new cache with a maximum number of entries.
+    /// The cache is disabled if `capacity` is 0.
+    pub fn new(capacity: usize, ttl_blocks: u64) -> Self {
+        NegativeCache {
+            inner: std::num::NonZeroUsize::new(capacity).map(lru::LruCache::new),
+            ttl_blocks,
+        }
+    }
+
+    /// Remembers the key as missing at the given block height.
+    pub fn put(&mut self, key: NegativeCacheKey, block_height: u64) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.put(key, block_height);
+        }
+    }
+
+    /// Returns a bool indicating whether the key is known to be missing at the given block height.
+    /// Expired entries are removed from the cache.
+    pub fn contains(&mut self, key: &NegativeCacheKey, block_height: u64) -> bool {
+        let Some(inner) = self.inner.as_mut() else {
+            return false;
+        };
+        match inner.get(key).copied() {
+            Some(observed_block_height) if observed_block_height <= block_height => {
+                if block_height - observed_block_height <= self.ttl_blocks {
+                    true
+                } else {
+                    inner.pop(key);
+                    false
+                }
+            }
+            // The key might have existed before the miss was observed
+            Some(_) => false,
+            None => false,
+        }
+    }
+
+    /// Forgets the key, should be called when a new block touches it.
+    pub fn invalidate(&mut self, key: &NegativeCacheKey) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.pop(key);
+        }
+    }
+}
+
+/// Memory and optionally disk backed cache of the full block views and shards
+/// to avoid fetching the same data from S3 over and over again.
+/// `BlockView` and `IndexerShard` are not `Clone`, so the cache keeps them serialized to JSON
+/// and every read deserializes a fresh copy. On disk the data is stored in the same layout
+/// as in the NEAR Lake S3 bucket: `<disk_cache_dir>/<block_height>/block.json`
+/// and `<disk_cache_dir>/<block_height>/shard_<shard_id>.json`, so the disk cache directory
+/// can be used as the `lake_local_path` as well
+pub struct BlockDataCache {
+    block_views: Option<std::sync::Mutex<lru::LruCache<u64, std::sync::Arc<Vec<u8>>>>>,
+    shards: Option<std::sync::Mutex<lru::LruCache<(u64, u64), std::sync::Arc<Vec<u8>>>>>,
+    disk_cache: Option<std::sync::Arc<DiskCache>>,
+}
+
+impl BlockDataCache {
+    /// Create a new cache with a maximum number of block views and shards kept in memory
+    /// and a maximum number of blocks kept on disk.
+    /// The memory cache is disabled if the capacity is 0, the disk cache is disabled if
+    /// `disk_cache_dir` is not provided or `disk_cache_blocks` is 0.
+    pub fn new(
+        block_views_capacity: usize,
+        shards_capacity: usize,
+        disk_cache_dir: Option<std::path::PathBuf>,
+        disk_cache_blocks: usize,
+    ) -> Self {
+        BlockDataCache {
+            block_views: std::num::NonZeroUsize::new(block_views_capacity)
+                .map(|capacity| std::sync::Mutex::new(lru::LruCache::new(capacity))),
+            shards: std::num::NonZeroUsize::new(shards_capacity)
+                .map(|capacity| std::sync::Mutex::new(lru::LruCache::new(capacity))),
+            disk_cache: disk_cache_dir
+                .zip(std::num::NonZeroUsize::new(disk_cache_blocks))
+                .map(|(dir, capacity)| std::sync::Arc::new(DiskCache::new(dir, capacity))),
+        }
+    }
+
+    /// Returns the block view from memory or disk.
+    pub async fn get_block_view(
+        &self,
+        block_height: u64,
+    ) -> Option<near_primitives::views::BlockView> {
+        self.get(
+            self.block_views.as_ref(),
+            block_height,
+            block_height,
+            "block.json".to_string(),
+        )
+        .await
+    }
+
+    /// Puts the block view into memory and disk caches.
+    pub fn put_block_view(
+        &self,
+        block_height: u64,
+        block_view: &near_primitives::views::BlockView,
+    ) {
+        self.put(
+            self.block_views.as_ref(),
+            block_height,
+            block_height,
+            "block.json".to_string(),
+            block_view,
+        )
+    }
+
+    /// Returns the shard from memory or disk.
+    pub async fn get_shard(
+        &self,
+        block_height: u64,
+        shard_id: u64,
+    ) -> Option<near_indexer_primitives::IndexerShard> {
+        self.get(
+            self.shards.as_ref(),
+            (block_height, shard_id),
+            block_height,
+            format!("shard_{}.json", shard_id),
+        )
+        .await
+    }
+
+    /// Puts the shard into memory and disk caches.
+    pub fn put_shard(&self, block_height: u64, shard: &near_indexer_primitives::IndexerShard) {
+        self.put(
+            self.shards.as_ref(),
+            (block_height, shard.shard_id),
+            block_height,
+            format!("shard_{}.json", shard.shard_id),
+            shard,
+        )
+    }
+
+    /// Looks up the memory cache first and falls back to the disk cache.
+    /// The data read from disk is promoted to the memory cache.
+    async fn get<K: std::hash::Hash + Eq, T: serde::de::DeserializeOwned>(
+        &self,
+        memory_cache: Option<&std::sync::Mutex<lru::LruCache<K, std::sync::Arc<Vec<u8>>>>>,
+        key: K,
+        block_height: u64,
+        file_name: String,
+    ) -> Option<T> {
+        let cached = memory_cache.and_then(|cache| cache.lock().unwrap().get(&key).cloned());
+        let content = match cached {
+            Some(content) => content,
+            None => {
+                let content = std::sync::Arc::new(
+                    self.disk_cache
+                        .as_ref()?
+                        .read(block_height, &file_name)
+                        .await?,
+                );
+                if let Some(cache) = memory_cache {
+                    cache.lock().unwrap().put(key, content.clone());
+                }
+                content
+            }
+        };
+        match serde_json::from_slice(&content) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to parse cached {} of block {}: {:?}",
+                    file_name,
+                    block_height,
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Puts the data into the memory cache and spawns the write to the disk cache,
+    /// so the block streaming and the requests don't wait for the disk
+    fn put<K: std::hash::Hash + Eq, T: serde::Serialize>(
+        &self,
+        memory_cache: Option<&std::sync::Mutex<lru::LruCache<K, std::sync::Arc<Vec<u8>>>>>,
+        key: K,
+        block_height: u64,
+        file_name: String,
+        value: &T,
+    ) {
+        let content = match serde_json::to_vec(value) {
+            Ok(content) => std::sync::Arc::new(content),
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to serialize {} of block {}: {:?}",
+                    file_name,
+                    block_height,
+                    err
+                );
+                return;
+            }
+        };
+        if let Some(disk_cache) = &self.disk_cache {
+            let disk_cache = std::sync::Arc::clone(disk_cache);
+            let content = std::sync::Arc::clone(&content);
+            tokio::spawn(async move { disk_cache.write(block_height, &file_name, &content).await });
+        }
+        if let Some(cache) = memory_cache {
+            cache.lock().unwrap().put(key, content);
+        }
+    }
+}
+
+/// The blocks cached on disk, at most `capacity` of them. Once there are more blocks
+/// the directory of the least recently read or written block is removed
+struct DiskCache {
+    dir: std::path::PathBuf,
+    blocks: std::sync::Mutex<lru::LruCache<u64, ()>>,
+}
+
+impl DiskCache {
+    /// The blocks cached by the previous runs are tracked as the least recently used ones,
+    /// the lowest of them are removed if there are more than `capacity`
+    fn new(dir: std::path::PathBuf, capacity: std::num::NonZeroUsize) -> Self {
+        let mut cached_blocks: Vec<u64> = std::fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        cached_blocks.sort_unstable();
+        let mut blocks = lru::LruCache::new(capacity);
+        for block_height in cached_blocks {
+            if let Some((evicted_block_height, _)) = blocks.push(block_height, ()) {
+                if let Err(err) =
+                    std::fs::remove_dir_all(Self::block_dir(&dir, evicted_block_height))
+                {
+                    tracing::warn!(
+                        "Failed to remove block {} from the disk cache: {:?}",
+                        evicted_block_height,
+                        err
+                    );
+                }
+            }
+        }
+        Self {
+            dir,
+            blocks: std::sync::Mutex::new(blocks),
+        }
+    }
+
+    fn block_dir(dir: &std::path::Path, block_height: u64) -> std::path::PathBuf {
+        dir.join(block_source::block_dir_name(block_height))
+    }
+
+    async fn read(&self, block_height: u64, file_name: &str) -> Option<Vec<u8>> {
+        let content = tokio::fs::read(Self::block_dir(&self.dir, block_height).join(file_name))
+            .await
+            .ok()?;
+        self.blocks.lock().unwrap().promote(&block_height);
+        Some(content)
+    }
+
+    /// Failing to write the disk cache is not critical, so we only log the error
+    async fn write(&self, block_height: u64, file_name: &str, content: &[u8]) {
+        let evicted_block_height = match self.blocks.lock().unwrap().push(block_height, ()) {
+            Some((evicted_block_height, _)) if evicted_block_height != block_height => {
+                Some(evicted_block_height)
+            }
+            _ => None,
+        };
+        if let Some(evicted_block_height) = evicted_block_height {
+            if let Err(err) =
+                tokio::fs::remove_dir_all(Self::block_dir(&self.dir, evicted_block_height)).await
+            {
+                tracing::warn!(
+                    "Failed to remove block {} from the disk cache: {:?}",
+                    evicted_block_height,
+                    err
+                );
+            }
+        }
+        let block_dir = Self::block_dir(&self.dir, block_height);
+        let result = async {
+            tokio::fs::create_dir_all(&block_dir).await?;
+            // Write to a temporary file first to not let the readers see a partially written file
+            let tmp_path = block_dir.join(format!("{}.tmp", file_name));
+            tokio::fs::write(&tmp_path, content).await?;
+            tokio::fs::rename(&tmp_path, block_dir.join(file_name)).await
+        }
+        .await;
+        if let Err(err) = result {
+            tracing::warn!(
+                "Failed to write {} of block {} to the disk cache: {:?}",
+                file_name,
+                block_height,
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory in the system temp directory, removed when dropped
+    struct TestDir(std::path::PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "read-rpc-block-data-cache-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn memory_cache() -> Option<std::sync::Mutex<lru::LruCache<u64, std::sync::Arc<Vec<u8>>>>> {
+        Some(std::sync::Mutex::new(lru::LruCache::new(
+            std::num::NonZeroUsize::new(10).unwrap(),
+        )))
+    }
+
+    fn block_file(dir: &TestDir, block_height: u64, file_name: &str) -> std::path::PathBuf {
+        DiskCache::block_dir(&dir.0, block_height).join(file_name)
+    }
+
+    #[tokio::test]
+    async fn get_reads_the_memory_cache_first() {
+        let dir = TestDir::new("memory-first");
+        let cache = BlockDataCache::new(0, 0, Some(dir.0.clone()), 10);
+        let memory_cache = memory_cache();
+        let disk_cache = cache.disk_cache.as_ref().unwrap();
+        disk_cache.write(1, "block.json", b"\"disk\"").await;
+        memory_cache
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .put(1, std::sync::Arc::new(b"\"memory\"".to_vec()));
+        let cached: Option<String> = cache
+            .get(memory_cache.as_ref(), 1, 1, "block.json".to_string())
+            .await;
+        assert_eq!(cached.as_deref(), Some("memory"));
+    }
+
+    #[tokio::test]
+    async fn get_promotes_the_disk_cache_to_memory() {
+        let dir = TestDir::new("disk-promoted");
+        let cache = BlockDataCache::new(0, 0, Some(dir.0.clone()), 10);
+        let memory_cache = memory_cache();
+        let disk_cache = cache.disk_cache.as_ref().unwrap();
+        disk_cache.write(1, "block.json", b"\"disk\"").await;
+        let cached: Option<String> = cache
+            .get(memory_cache.as_ref(), 1, 1, "block.json".to_string())
+            .await;
+        assert_eq!(cached.as_deref(), Some("disk"));
+
+        std::fs::remove_file(block_file(&dir, 1, "block.json")).unwrap();
+        let cached: Option<String> = cache
+            .get(memory_cache.as_ref(), 1, 1, "block.json".to_string())
+            .await;
+        assert_eq!(cached.as_deref(), Some("disk"));
+        let missing: Option<String> = cache
+            .get(memory_cache.as_ref(), 2, 2, "block.json".to_string())
+            .await;
+        assert_eq!(missing, None);
+    }
+
+    #[tokio::test]
+    async fn write_replaces_the_file_through_a_temporary_file() {
+        let dir = TestDir::new("write-rename");
+        let disk_cache = DiskCache::new(dir.0.clone(), std::num::NonZeroUsize::new(10).unwrap());
+        disk_cache.write(1, "shard_0.json", b"old").await;
+        disk_cache.write(1, "shard_0.json", b"new").await;
+        assert_eq!(
+            std::fs::read(block_file(&dir, 1, "shard_0.json")).unwrap(),
+            b"new"
+        );
+        assert!(!block_file(&dir, 1, "shard_0.json.tmp").exists());
+        assert_eq!(disk_cache.read(1, "shard_0.json").await.unwrap(), b"new");
+    }
+
+    #[tokio::test]
+    async fn write_evicts_the_least_recently_used_block() {
+        let dir = TestDir::new("eviction");
+        let disk_cache = DiskCache::new(dir.0.clone(), std::num::NonZeroUsize::new(2).unwrap());
+        disk_cache.write(1, "block.json", b"1").await;
+        disk_cache.write(2, "block.json", b"2").await;
+        disk_cache.write(2, "shard_0.json", b"2").await;
+        assert!(disk_cache.read(1, "block.json").await.is_some());
+        disk_cache.write(3, "block.json", b"3").await;
+        assert!(block_file(&dir, 1, "block.json").exists());
+        assert!(!DiskCache::block_dir(&dir.0, 2).exists());
+        assert!(block_file(&dir, 3, "block.json").exists());
+    }
+
+    #[tokio::test]
+    async fn new_evicts_the_lowest_blocks_of_the_previous_runs() {
+        let dir = TestDir::new("previous-runs");
+        for block_height in 1..=3 {
+            std::fs::create_dir_all(DiskCache::block_dir(&dir.0, block_height)).unwrap();
+        }
+        let disk_cache = DiskCache::new(dir.0.clone(), std::num::NonZeroUsize::new(2).unwrap());
+        assert!(!DiskCache::block_dir(&dir.0, 1).exists());
+        assert!(DiskCache::block_dir(&dir.0, 2).exists());
+        disk_cache.write(4, "block.json", b"4").await;
+        assert!(!DiskCache::block_dir(&dir.0, 2).exists());
+        assert!(DiskCache::block_dir(&dir.0, 3).exists());
+    }
+}
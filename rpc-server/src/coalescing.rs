@@ -0,0 +1,224 @@
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+type SharedExecution<V> = Shared<BoxFuture<'static, Result<V, ExecutionCancelled>>>;
+type InFlight<K, V> = std::sync::Mutex<HashMap<K, (u64, SharedExecution<V>)>>;
+
+/// The shared execution was cancelled before it completed, e.g. on the runtime shutdown
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("The coalesced execution was cancelled")]
+pub struct ExecutionCancelled;
+
+/// Singleflight-like layer that allows concurrent identical requests to share one execution.
+/// The first request for a key spawns the future, the others await the same shared future
+/// and receive a clone of its result.
+pub struct RequestCoalescer<K, V>
+where
+    V: Clone,
+{
+    in_flight: std::sync::Arc<InFlight<K, V>>,
+    next_execution_id: std::sync::atomic::AtomicU64,
+}
+
+impl<K, V> RequestCoalescer<K, V>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            in_flight: std::sync::Arc::new(std::sync::Mutex::new(HashMap::new())),
+            next_execution_id: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    /// Runs `future` unless an identical request (same `key`) is already in flight,
+    /// in which case the result of the in-flight request is awaited and returned instead.
+    /// The panic of the execution is resumed in every awaiting request
+    pub async fn run<F>(&self, key: K, future: F) -> Result<V, ExecutionCancelled>
+    where
+        F: std::future::Future<Output = V> + Send + 'static,
+    {
+        let shared_future = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some((_, shared_future)) => {
+                    crate::metrics::COALESCED_REQUESTS_TOTAL.inc();
+                    shared_future.clone()
+                }
+                None => {
+                    let execution_id = self
+                        .next_execution_id
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    // The execution is spawned so it completes and removes its entry even if
+                    // all the awaiting requests are cancelled by the clients
+                    let cleanup = InFlightCleanup {
+                        in_flight: std::sync::Arc::clone(&self.in_flight),
+                        key: key.clone(),
+                        execution_id,
+                    };
+                    let handle = tokio::spawn(async move {
+                        let _cleanup = cleanup;
+                        future.await
+                    });
+                    let shared_future = async move {
+                        match handle.await {
+                            Ok(result) => Ok(result),
+                            Err(err) if err.is_panic() => {
+                                std::panic::resume_unwind(err.into_panic())
+                            }
+                            Err(_) => Err(ExecutionCancelled),
+                        }
+                    }
+                    .boxed()
+                    .shared();
+                    in_flight.insert(key, (execution_id, shared_future.clone()));
+                    shared_future
+                }
+            }
+        };
+
+        shared_future.await
+    }
+}
+
+impl<K, V> Default for RequestCoalescer<K, V>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Removes the entry of the execution from the in-flight map once the execution is finished
+/// or panicked. The execution id protects from removing a newer execution for the same key.
+struct InFlightCleanup<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    in_flight: std::sync::Arc<InFlight<K, V>>,
+    key: K,
+    execution_id: u64,
+}
+
+impl<K, V> Drop for InFlightCleanup<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|err| err.into_inner());
+        if in_flight.get(&self.key).map_or(false, |(execution_id, _)| {
+            *execution_id == self.execution_id
+        }) {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_flight_len(coalescer: &RequestCoalescer<&'static str, u64>) -> usize {
+        coalescer.in_flight.lock().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn concurrent_identical_requests_run_once() {
+        let coalescer = RequestCoalescer::new();
+        let executions = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (sender, receiver) = tokio::sync::oneshot::channel::<u64>();
+        let execution = |receiver: tokio::sync::oneshot::Receiver<u64>| {
+            let executions = executions.clone();
+            async move {
+                executions.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                receiver.await.unwrap()
+            }
+        };
+        let (_, unused_receiver) = tokio::sync::oneshot::channel::<u64>();
+        let (first, second, _) = futures::join!(
+            coalescer.run("key", execution(receiver)),
+            coalescer.run("key", execution(unused_receiver)),
+            async { sender.send(42).unwrap() },
+        );
+        assert_eq!(first.unwrap(), 42);
+        assert_eq!(second.unwrap(), 42);
+        assert_eq!(executions.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(in_flight_len(&coalescer), 0);
+    }
+
+    #[tokio::test]
+    async fn sequential_requests_run_separately() {
+        let coalescer = RequestCoalescer::new();
+        assert_eq!(coalescer.run("key", async { 1 }).await.unwrap(), 1);
+        assert_eq!(in_flight_len(&coalescer), 0);
+        assert_eq!(coalescer.run("key", async { 2 }).await.unwrap(), 2);
+        assert_eq!(in_flight_len(&coalescer), 0);
+    }
+
+    #[tokio::test]
+    async fn older_execution_cleanup_keeps_the_newer_entry() {
+        let coalescer = RequestCoalescer::<&'static str, u64>::new();
+        let mut request = Box::pin(coalescer.run("key", futures::future::pending()));
+        assert!(futures::poll!(request.as_mut()).is_pending());
+        // The cleanup of an older execution for the same key doesn't remove the in-flight one
+        drop(InFlightCleanup {
+            in_flight: std::sync::Arc::clone(&coalescer.in_flight),
+            key: "key",
+            execution_id: u64::MAX,
+        });
+        assert_eq!(in_flight_len(&coalescer), 1);
+    }
+
+    #[tokio::test]
+    async fn panicked_execution_removes_the_entry() {
+        let coalescer = std::sync::Arc::new(RequestCoalescer::<&'static str, u64>::new());
+        let request = {
+            let coalescer = coalescer.clone();
+            tokio::spawn(async move {
+                coalescer
+                    .run("key", async { panic!("execution panicked") })
+                    .await
+            })
+        };
+        assert!(request.await.unwrap_err().is_panic());
+        assert_eq!(in_flight_len(&coalescer), 0);
+    }
+
+    #[tokio::test]
+    async fn dropped_request_completes_the_execution() {
+        let coalescer = RequestCoalescer::new();
+        let (sender, receiver) = tokio::sync::oneshot::channel::<u64>();
+        let mut request = Box::pin(coalescer.run("key", async move { receiver.await.unwrap() }));
+        assert!(futures::poll!(request.as_mut()).is_pending());
+        drop(request);
+        assert_eq!(in_flight_len(&coalescer), 1);
+        sender.send(42).unwrap();
+        for _ in 0..100 {
+            if in_flight_len(&coalescer) == 0 {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(in_flight_len(&coalescer), 0);
+    }
+
+    #[test]
+    fn cancelled_execution_is_an_error() {
+        let coalescer = RequestCoalescer::<&'static str, u64>::new();
+        let mut request = Box::pin(coalescer.run("key", futures::future::pending()));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async { assert!(futures::poll!(request.as_mut()).is_pending()) });
+        // The shutdown cancels the spawned execution
+        drop(runtime);
+        assert!(futures::executor::block_on(request).is_err());
+        assert_eq!(in_flight_len(&coalescer), 0);
+    }
+}
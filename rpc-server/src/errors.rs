@@ -23,6 +23,19 @@ impl RPCError {
         ))
     }
 
+    /// The state of the account is skipped by the account filter of the indexer,
+    /// so the server can't tell anything about it
+    pub(crate) fn account_not_indexed(account_id: &near_primitives::types::AccountId) -> Self {
+        Self::from(near_jsonrpc_primitives::errors::RpcError::new(
+            -32000,
+            String::from("Server error"),
+            Some(serde_json::json!(format!(
+                "Account {} is not indexed by this server",
+                account_id
+            ))),
+        ))
+    }
+
     pub(crate) fn parse_error(msg: &str) -> Self {
         Self::from(near_jsonrpc_primitives::errors::RpcError::new(
             -32700,
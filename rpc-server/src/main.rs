@@ -13,6 +13,7 @@ use tracing_subscriber::util::SubscriberInitExt;
 extern crate lazy_static;
 
 mod cache;
+mod coalescing;
 mod config;
 mod errors;
 mod metrics;
@@ -64,6 +65,50 @@ fn init_logging(use_tracer: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+async fn init_db_manager(
+    opts: &Opts,
+) -> anyhow::Result<std::sync::Arc<dyn storage::ReaderStorage>> {
+    #[cfg(feature = "rocksdb")]
+    if let Some(rocksdb_path) = &opts.rocksdb_path {
+        tracing::info!("Opening the embedded RocksDB storage...");
+        let embedded_storage =
+            std::sync::Arc::new(storage::embedded::EmbeddedStorage::open(rocksdb_path)?);
+        let catch_up_interval = std::time::Duration::from_millis(opts.rocksdb_catch_up_interval);
+        let storage = std::sync::Arc::clone(&embedded_storage);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(catch_up_interval).await;
+                if let Err(err) = storage.catch_up_with_primary().await {
+                    tracing::warn!("Failed to catch up with the indexers databases: {:?}", err);
+                }
+            }
+        });
+        return Ok(embedded_storage);
+    }
+
+    #[cfg(feature = "postgres")]
+    if let Some(postgres_url) = &opts.postgres_url {
+        tracing::info!("Connecting to the PostgreSQL database...");
+        return Ok(std::sync::Arc::new(
+            database::postgres::PostgresDBManager::connect(
+                postgres_url,
+                opts.postgres_max_connections,
+                opts.scylla_migrations,
+            )
+            .await?,
+        ));
+    }
+
+    Ok(std::sync::Arc::new(
+        *storage::ScyllaDBManager::new(
+            &opts.to_scylla_session_options(),
+            opts.scylla_migrations,
+            &opts.scylla_keyspaces,
+        )
+        .await?,
+    ))
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
@@ -122,44 +167,64 @@ async fn main() -> anyhow::Result<()> {
         cache::LruMemoryCache::new(contract_code_cache_size),
     ));
 
-    let scylla_db_manager = std::sync::Arc::new(
-        *storage::ScyllaDBManager::new(
-            &opts.scylla_url,
-            opts.scylla_user.as_deref(),
-            opts.scylla_password.as_deref(),
-            opts.scylla_preferred_dc.as_deref(),
-            Some(opts.scylla_keepalive_interval),
-            opts.max_retry,
-            opts.strict_mode,
-        )
-        .await?,
-    );
+    let negative_cache = std::sync::Arc::new(std::sync::RwLock::new(cache::NegativeCache::new(
+        opts.negative_cache_size,
+        opts.negative_cache_ttl_blocks,
+    )));
+
+    let block_data_cache = std::sync::Arc::new(cache::BlockDataCache::new(
+        opts.block_view_cache_size,
+        opts.shard_cache_size,
+        opts.block_data_cache_dir.clone(),
+        opts.block_data_disk_cache_size,
+    ));
+
+    let db_manager = init_db_manager(&opts).await?;
+
+    let indexed_block_height = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    if let Some(state_indexer_id) = opts.state_indexer_id.clone() {
+        tokio::spawn(utils::update_indexed_block_height_regularly(
+            std::sync::Arc::clone(&db_manager),
+            state_indexer_id,
+            std::sync::Arc::clone(&indexed_block_height),
+            std::time::Duration::from_millis(opts.state_indexer_progress_interval),
+        ));
+    }
 
     tracing::info!("Get genesis config...");
     let genesis_config = near_rpc_client
         .call(near_jsonrpc_client::methods::EXPERIMENTAL_genesis_config::RpcGenesisConfigRequest)
         .await?;
     let lake_config = opts.to_lake_config(final_block.block_height).await?;
-    let s3_config = opts.to_s3_config().await;
+    let block_fetcher = opts.to_block_fetcher().await?;
 
     let state = ServerContext {
-        s3_client: near_lake_framework::s3_fetchers::LakeS3Client::new(
-            aws_sdk_s3::Client::from_conf(s3_config),
-        ),
-        scylla_db_manager,
+        block_fetcher,
+        db_manager,
         near_rpc_client: near_rpc_client.clone(),
-        s3_bucket_name: opts.s3_bucket_name,
         genesis_config,
         blocks_cache: std::sync::Arc::clone(&blocks_cache),
         final_block_height: std::sync::Arc::clone(&final_block_height),
+        indexed_block_height,
         compiled_contract_code_cache,
         contract_code_cache,
         max_gas_burnt: opts.max_gas_burnt,
+        account_filter: opts.account_filter.clone(),
+        negative_cache: std::sync::Arc::clone(&negative_cache),
+        block_data_cache: std::sync::Arc::clone(&block_data_cache),
+        account_coalescer: std::sync::Arc::new(coalescing::RequestCoalescer::new()),
+        function_call_coalescer: std::sync::Arc::new(coalescing::RequestCoalescer::new()),
     };
 
     tokio::spawn(async move {
-        update_final_block_height_regularly(final_block_height.clone(), blocks_cache, lake_config)
-            .await
+        update_final_block_height_regularly(
+            final_block_height.clone(),
+            blocks_cache,
+            negative_cache,
+            block_data_cache,
+            lake_config,
+        )
+        .await
     });
 
     let rpc = Server::new()
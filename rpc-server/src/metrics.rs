@@ -28,11 +28,26 @@ lazy_static! {
         "Total number of the request where set sync_checkpoint"
     )
     .unwrap();
+    pub(crate) static ref ACCOUNT_FILTER_PROXIED_REQUESTS_TOTAL: IntCounter = try_create_int_counter(
+        "total_account_filter_proxied_requests",
+        "Total number of the changes requests proxied to near-rpc because the account filter is configured"
+    )
+    .unwrap();
     pub(crate) static ref FINAL_BLOCK_HEIGHT: IntGauge = try_create_int_gauge(
         "final_block_height",
         "The final block height from the perspective of the READ RPC server"
     )
     .unwrap();
+    pub(crate) static ref COALESCED_REQUESTS_TOTAL: IntCounter = try_create_int_counter(
+        "total_coalesced_requests",
+        "Total number of the requests which were served by an identical in-flight request"
+    )
+    .unwrap();
+    pub(crate) static ref NEGATIVE_CACHE_HITS_TOTAL: IntCounter = try_create_int_counter(
+        "total_negative_cache_hits",
+        "Total number of the requests for missing accounts or access keys served from the negative cache"
+    )
+    .unwrap();
 
     // REQUESTS TOTAL COUNTERS
     // query requests counters
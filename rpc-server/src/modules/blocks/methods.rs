@@ -1,8 +1,8 @@
 use crate::config::ServerContext;
 use crate::errors::RPCError;
 use crate::modules::blocks::utils::{
-    fetch_block_from_cache_or_get, fetch_chunk_from_s3, is_matching_change,
-    scylla_db_convert_block_hash_to_block_height,
+    fetch_block_from_cache_or_get, fetch_chunk_from_cache_or_get, fetch_shard_from_cache_or_get,
+    is_matching_change, scylla_db_convert_block_hash_to_block_height,
     scylla_db_convert_block_height_and_shard_id_to_height_included_and_shard_id,
     scylla_db_convert_chunk_hash_to_block_height_and_shard_id,
 };
@@ -155,6 +155,12 @@ pub async fn changes_in_block_by_type(
         near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockByTypeRequest,
     >,
 ) -> Result<near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockResponse, RPCError> {
+    if !data.account_filter.is_empty() {
+        // The state-indexer doesn't store the changes of the accounts which are not indexed,
+        // so the changes of the block stored by it are incomplete
+        crate::metrics::ACCOUNT_FILTER_PROXIED_REQUESTS_TOTAL.inc();
+        return Ok(proxy_rpc_call(&data.near_rpc_client, params).await?);
+    }
     match &params.block_reference {
         near_primitives::types::BlockReference::SyncCheckpoint(_) => {
             // Increase the SYNC_CHECKPOINT_REQUESTS_TOTAL metric if the request has
@@ -193,6 +199,12 @@ pub async fn changes_in_block(
     >,
 ) -> Result<near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockByTypeResponse, RPCError>
 {
+    if !data.account_filter.is_empty() {
+        // The state-indexer doesn't store the changes of the accounts which are not indexed,
+        // so the changes of the block stored by it are incomplete
+        crate::metrics::ACCOUNT_FILTER_PROXIED_REQUESTS_TOTAL.inc();
+        return Ok(proxy_rpc_call(&data.near_rpc_client, params).await?);
+    }
     match &params.block_reference {
         near_primitives::types::BlockReference::SyncCheckpoint(_) => {
             // Increase the SYNC_CHECKPOINT_REQUESTS_TOTAL metric if the request has
@@ -379,8 +391,7 @@ pub async fn fetch_block(
         near_primitives::types::BlockReference::BlockId(block_id) => match block_id {
             near_primitives::types::BlockId::Height(block_height) => Ok(block_height),
             near_primitives::types::BlockId::Hash(block_hash) => {
-                scylla_db_convert_block_hash_to_block_height(&data.scylla_db_manager, block_hash)
-                    .await
+                scylla_db_convert_block_hash_to_block_height(&data.db_manager, block_hash).await
             }
         },
         near_primitives::types::BlockReference::Finality(finality) => match finality {
@@ -399,17 +410,21 @@ pub async fn fetch_block(
             },
         ),
     };
-    let block_view = near_lake_framework::s3_fetchers::fetch_block_or_retry(
-        &data.s3_client,
-        &data.s3_bucket_name,
-        block_height?,
-    )
-    .await
-    .map_err(
-        |err| near_jsonrpc_primitives::types::blocks::RpcBlockError::UnknownBlock {
-            error_message: err.to_string(),
-        },
-    )?;
+    let block_height = block_height?;
+    if let Some(block_view) = data.block_data_cache.get_block_view(block_height).await {
+        return Ok(near_jsonrpc_primitives::types::blocks::RpcBlockResponse { block_view });
+    }
+    let block_view = data
+        .block_fetcher
+        .fetch_block(block_height)
+        .await
+        .map_err(
+            |err| near_jsonrpc_primitives::types::blocks::RpcBlockError::UnknownBlock {
+                error_message: err.to_string(),
+            },
+        )?;
+    data.block_data_cache
+        .put_block_view(block_height, &block_view);
     Ok(near_jsonrpc_primitives::types::blocks::RpcBlockResponse { block_view })
 }
 
@@ -428,41 +443,30 @@ pub async fn fetch_chunk(
         } => match block_id {
             near_primitives::types::BlockId::Height(block_height) => {
                 scylla_db_convert_block_height_and_shard_id_to_height_included_and_shard_id(
-                    &data.scylla_db_manager,
+                    &data.db_manager,
                     block_height,
                     shard_id,
                 )
                 .await?
             }
             near_primitives::types::BlockId::Hash(block_hash) => {
-                let block_height = scylla_db_convert_block_hash_to_block_height(
-                    &data.scylla_db_manager,
-                    block_hash,
-                )
-                .await
-                .map_err(|err| {
-                    near_jsonrpc_primitives::types::chunks::RpcChunkError::InternalError {
-                        error_message: err.to_string(),
-                    }
-                })?;
+                let block_height =
+                    scylla_db_convert_block_hash_to_block_height(&data.db_manager, block_hash)
+                        .await
+                        .map_err(|err| {
+                            near_jsonrpc_primitives::types::chunks::RpcChunkError::InternalError {
+                                error_message: err.to_string(),
+                            }
+                        })?;
                 (block_height, shard_id)
             }
         },
         near_jsonrpc_primitives::types::chunks::ChunkReference::ChunkHash { chunk_id } => {
-            scylla_db_convert_chunk_hash_to_block_height_and_shard_id(
-                &data.scylla_db_manager,
-                chunk_id,
-            )
-            .await?
+            scylla_db_convert_chunk_hash_to_block_height_and_shard_id(&data.db_manager, chunk_id)
+                .await?
         }
     };
-    let chunk_view = fetch_chunk_from_s3(
-        &data.s3_client,
-        &data.s3_bucket_name,
-        block_height,
-        shard_id,
-    )
-    .await?;
+    let chunk_view = fetch_chunk_from_cache_or_get(data, block_height, shard_id).await?;
 
     Ok(near_jsonrpc_primitives::types::chunks::RpcChunkResponse { chunk_view })
 }
@@ -475,46 +479,43 @@ async fn fetch_changes_in_block(
     near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockByTypeResponse,
     near_jsonrpc_primitives::types::changes::RpcStateChangesError,
 > {
-    let shards = fetch_shards(data, block).await.map_err(|err| {
+    let state_changes = fetch_state_changes(data, block).await.map_err(|err| {
         near_jsonrpc_primitives::types::changes::RpcStateChangesError::InternalError {
             error_message: err.to_string(),
         }
     })?;
 
-    let trie_keys = shards
-        .into_iter()
-        .flat_map(|shard| shard.state_changes)
-        .map(
-            |state_change_with_cause| match state_change_with_cause.value {
-                StateChangeValueView::AccountUpdate { account_id, .. }
-                | StateChangeValueView::AccountDeletion { account_id } => {
-                    TrieKey::Account { account_id }
-                }
-                StateChangeValueView::DataUpdate {
-                    account_id, key, ..
-                }
-                | StateChangeValueView::DataDeletion { account_id, key } => {
-                    let key: Vec<u8> = key.into();
-                    TrieKey::ContractData { account_id, key }
-                }
-                StateChangeValueView::ContractCodeUpdate { account_id, .. }
-                | StateChangeValueView::ContractCodeDeletion { account_id } => {
-                    TrieKey::ContractCode { account_id }
-                }
-                StateChangeValueView::AccessKeyUpdate {
-                    account_id,
-                    public_key,
-                    ..
-                }
-                | StateChangeValueView::AccessKeyDeletion {
-                    account_id,
-                    public_key,
-                } => TrieKey::AccessKey {
-                    account_id,
-                    public_key,
-                },
+    let trie_keys = state_changes.into_iter().map(|state_change_with_cause| {
+        match state_change_with_cause.value {
+            StateChangeValueView::AccountUpdate { account_id, .. }
+            | StateChangeValueView::AccountDeletion { account_id } => {
+                TrieKey::Account { account_id }
+            }
+            StateChangeValueView::DataUpdate {
+                account_id, key, ..
+            }
+            | StateChangeValueView::DataDeletion { account_id, key } => {
+                let key: Vec<u8> = key.into();
+                TrieKey::ContractData { account_id, key }
+            }
+            StateChangeValueView::ContractCodeUpdate { account_id, .. }
+            | StateChangeValueView::ContractCodeDeletion { account_id } => {
+                TrieKey::ContractCode { account_id }
+            }
+            StateChangeValueView::AccessKeyUpdate {
+                account_id,
+                public_key,
+                ..
+            }
+            | StateChangeValueView::AccessKeyDeletion {
+                account_id,
+                public_key,
+            } => TrieKey::AccessKey {
+                account_id,
+                public_key,
             },
-        );
+        }
+    });
 
     let mut unique_trie_keys = vec![];
     for trie_key in trie_keys {
@@ -563,14 +564,13 @@ async fn fetch_changes_in_block_by_type(
     near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockResponse,
     near_jsonrpc_primitives::types::changes::RpcStateChangesError,
 > {
-    let shards = fetch_shards(data, block).await.map_err(|err| {
+    let state_changes = fetch_state_changes(data, block).await.map_err(|err| {
         near_jsonrpc_primitives::types::changes::RpcStateChangesError::InternalError {
             error_message: err.to_string(),
         }
     })?;
-    let changes = shards
+    let changes = state_changes
         .into_iter()
-        .flat_map(|shard| shard.state_changes)
         .filter(|change| is_matching_change(change, state_changes_request))
         .collect();
     Ok(
@@ -581,6 +581,56 @@ async fn fetch_changes_in_block_by_type(
     )
 }
 
+/// Reads the state changes of the block from the `state_changes_by_block` table
+/// and falls back to collecting them from the shards if the block is not indexed there (yet)
+#[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(data)))]
+async fn fetch_state_changes(
+    data: &Data<ServerContext>,
+    block: crate::modules::blocks::CacheBlock,
+) -> anyhow::Result<Vec<near_primitives::views::StateChangeWithCauseView>> {
+    match data
+        .db_manager
+        .get_block_state_changes(block.block_height)
+        .await
+    {
+        Ok(state_changes) => {
+            futures::future::try_join_all(state_changes.into_iter().map(|state_change| {
+                resolve_contract_code(&data.db_manager, block.block_height, state_change)
+            }))
+            .await
+        }
+        Err(err) => {
+            tracing::debug!(
+                "Failed to read state changes from the database, fetching shards: {:?}",
+                err
+            );
+            let shards = fetch_shards(data, block).await?;
+            Ok(shards
+                .into_iter()
+                .flat_map(|shard| shard.state_changes)
+                .collect())
+        }
+    }
+}
+
+/// Puts back the code of the deployed contract, which is stored by its hash
+/// in the `contract_code` table instead of the state change itself
+async fn resolve_contract_code(
+    db_manager: &std::sync::Arc<dyn crate::storage::ReaderStorage>,
+    block_height: BlockHeight,
+    stored_state_change: readnode_primitives::StoredStateChange,
+) -> anyhow::Result<near_primitives::views::StateChangeWithCauseView> {
+    let mut state_change = stored_state_change.state_change;
+    if let (Some(code_hash), StateChangeValueView::ContractCodeUpdate { account_id, code }) =
+        (stored_state_change.code_hash, &mut state_change.value)
+    {
+        *code = db_manager
+            .get_contract_code_by_hash(account_id, block_height, code_hash)
+            .await?;
+    }
+    Ok(state_change)
+}
+
 #[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(data)))]
 async fn fetch_shards(
     data: &Data<ServerContext>,
@@ -589,14 +639,7 @@ async fn fetch_shards(
     let fetch_shards_futures = (0..block.chunks_included)
         .collect::<Vec<u64>>()
         .into_iter()
-        .map(|shard_id| {
-            near_lake_framework::s3_fetchers::fetch_shard_or_retry(
-                &data.s3_client,
-                &data.s3_bucket_name,
-                block.block_height,
-                shard_id,
-            )
-        });
+        .map(|shard_id| fetch_shard_from_cache_or_get(data, block.block_height, shard_id));
     futures::future::try_join_all(fetch_shards_futures)
         .await
         .map_err(|err| {
@@ -607,3 +650,50 @@ async fn fetch_shards(
             )
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::storage::ReaderStorage;
+
+    #[tokio::test]
+    async fn state_changes_get_the_contract_code_back() {
+        let account_id: near_primitives::types::AccountId = "alice.near".parse().unwrap();
+        let code = b"contract code".to_vec();
+        let storage = InMemoryStorage::new();
+        storage.put_contract_code(
+            account_id.clone(),
+            10,
+            near_primitives::hash::CryptoHash::default(),
+            Some(code.clone()),
+        );
+        let state_change = near_primitives::views::StateChangeWithCauseView {
+            cause: near_primitives::views::StateChangeCauseView::InitialState,
+            value: StateChangeValueView::ContractCodeUpdate {
+                account_id: account_id.clone(),
+                code: code.clone(),
+            },
+        };
+        storage
+            .put_block_state_changes(10, &[state_change])
+            .unwrap();
+        let db_manager: std::sync::Arc<dyn ReaderStorage> = std::sync::Arc::new(storage);
+
+        let mut state_changes = db_manager.get_block_state_changes(10).await.unwrap();
+        assert_eq!(state_changes.len(), 1);
+        let stored_state_change = state_changes.remove(0);
+        assert!(matches!(
+            &stored_state_change.state_change.value,
+            StateChangeValueView::ContractCodeUpdate { code, .. } if code.is_empty()
+        ));
+
+        let state_change = resolve_contract_code(&db_manager, 10, stored_state_change)
+            .await
+            .unwrap();
+        assert!(matches!(
+            state_change.value,
+            StateChangeValueView::ContractCodeUpdate { code: resolved_code, .. } if resolved_code == code
+        ));
+    }
+}
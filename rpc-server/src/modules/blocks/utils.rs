@@ -1,33 +1,44 @@
 use crate::config::ServerContext;
 use crate::modules::blocks::methods::fetch_block;
 use crate::modules::blocks::CacheBlock;
-use crate::storage::ScyllaDBManager;
+use crate::storage::ReaderStorage;
 use near_primitives::views::{StateChangeValueView, StateChangesRequestView};
 
-#[cfg_attr(
-    feature = "tracing-instrumentation",
-    tracing::instrument(skip(s3_client))
-)]
-pub async fn fetch_chunk_from_s3(
-    s3_client: &near_lake_framework::s3_fetchers::LakeS3Client,
-    s3_bucket_name: &str,
+/// Fetches the shard from the block data cache or from the block source if it is not cached yet
+#[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(data)))]
+pub async fn fetch_shard_from_cache_or_get(
+    data: &jsonrpc_v2::Data<ServerContext>,
+    block_height: near_primitives::types::BlockHeight,
+    shard_id: near_primitives::types::ShardId,
+) -> anyhow::Result<near_indexer_primitives::IndexerShard> {
+    if let Some(shard) = data
+        .block_data_cache
+        .get_shard(block_height, shard_id)
+        .await
+    {
+        return Ok(shard);
+    }
+    let shard = data
+        .block_fetcher
+        .fetch_shard(block_height, shard_id)
+        .await?;
+    data.block_data_cache.put_shard(block_height, &shard);
+    Ok(shard)
+}
+
+#[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(data)))]
+pub async fn fetch_chunk_from_cache_or_get(
+    data: &jsonrpc_v2::Data<ServerContext>,
     block_height: near_primitives::types::BlockHeight,
     shard_id: near_primitives::types::ShardId,
 ) -> Result<near_primitives::views::ChunkView, near_jsonrpc_primitives::types::chunks::RpcChunkError>
 {
     tracing::debug!(
-        "`fetch_chunk_from_s3` call: block_height {}, shard_id {}",
+        "`fetch_chunk_from_cache_or_get` call: block_height {}, shard_id {}",
         block_height,
         shard_id
     );
-    match near_lake_framework::s3_fetchers::fetch_shard_or_retry(
-        s3_client,
-        s3_bucket_name,
-        block_height,
-        shard_id,
-    )
-    .await
-    {
+    match fetch_shard_from_cache_or_get(data, block_height, shard_id).await {
         Ok(shard) => match shard.chunk {
             Some(chunk) => {
                 // We collect a list of local receipt ids to filter out local receipts from the chunk
@@ -78,14 +89,14 @@ pub async fn fetch_chunk_from_s3(
 
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(scylla_db_manager))
+    tracing::instrument(skip(db_manager))
 )]
 pub async fn scylla_db_convert_block_hash_to_block_height(
-    scylla_db_manager: &std::sync::Arc<ScyllaDBManager>,
+    db_manager: &std::sync::Arc<dyn ReaderStorage>,
     block_hash: near_primitives::hash::CryptoHash,
 ) -> Result<u64, near_jsonrpc_primitives::types::blocks::RpcBlockError> {
     tracing::debug!("`scylla_db_convert_block_hash_to_block_height` call");
-    match scylla_db_manager.get_block_by_hash(block_hash).await {
+    match db_manager.get_block_by_hash(block_hash).await {
         Ok(block_height) => Ok(block_height),
         Err(err) => Err(
             near_jsonrpc_primitives::types::blocks::RpcBlockError::UnknownBlock {
@@ -97,10 +108,10 @@ pub async fn scylla_db_convert_block_hash_to_block_height(
 
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(scylla_db_manager))
+    tracing::instrument(skip(db_manager))
 )]
 pub async fn scylla_db_convert_chunk_hash_to_block_height_and_shard_id(
-    scylla_db_manager: &std::sync::Arc<ScyllaDBManager>,
+    db_manager: &std::sync::Arc<dyn ReaderStorage>,
     chunk_hash: near_primitives::hash::CryptoHash,
 ) -> Result<
     (
@@ -110,7 +121,7 @@ pub async fn scylla_db_convert_chunk_hash_to_block_height_and_shard_id(
     near_jsonrpc_primitives::types::chunks::RpcChunkError,
 > {
     tracing::debug!("`scylla_db_convert_chunk_hash_to_block_height_and_shard_id` call");
-    match scylla_db_manager.get_block_by_chunk_hash(chunk_hash).await {
+    match db_manager.get_block_by_chunk_hash(chunk_hash).await {
         Ok(block_id_shard_id) => Ok((block_id_shard_id.0, block_id_shard_id.1)),
         Err(_err) => Err(
             near_jsonrpc_primitives::types::chunks::RpcChunkError::InternalError {
@@ -122,10 +133,10 @@ pub async fn scylla_db_convert_chunk_hash_to_block_height_and_shard_id(
 
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(scylla_db_manager))
+    tracing::instrument(skip(db_manager))
 )]
 pub async fn scylla_db_convert_block_height_and_shard_id_to_height_included_and_shard_id(
-    scylla_db_manager: &std::sync::Arc<ScyllaDBManager>,
+    db_manager: &std::sync::Arc<dyn ReaderStorage>,
     block_height: near_primitives::types::BlockHeight,
     shard_id: near_primitives::types::ShardId,
 ) -> Result<
@@ -138,7 +149,7 @@ pub async fn scylla_db_convert_block_height_and_shard_id_to_height_included_and_
     tracing::debug!(
         "`scylla_db_convert_block_height_and_shard_id_to_height_included_and_shard_id` call"
     );
-    Ok(scylla_db_manager
+    Ok(db_manager
         .get_block_by_height_and_shard_id(block_height, shard_id)
         .await
         .map_err(
@@ -161,7 +172,7 @@ pub async fn fetch_block_from_cache_or_get(
         near_primitives::types::BlockReference::BlockId(block_id) => match block_id {
             near_primitives::types::BlockId::Height(block_height) => Ok(block_height),
             near_primitives::types::BlockId::Hash(hash) => {
-                scylla_db_convert_block_hash_to_block_height(&data.scylla_db_manager, hash).await
+                scylla_db_convert_block_hash_to_block_height(&data.db_manager, hash).await
             }
         },
         near_primitives::types::BlockReference::Finality(_) => {
@@ -273,3 +284,58 @@ pub(crate) fn is_matching_change(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+
+    #[tokio::test]
+    async fn block_and_chunk_hashes_are_converted_to_the_block_height() {
+        let block_hash = near_primitives::hash::hash(b"block");
+        let chunk_hash = near_primitives::hash::hash(b"chunk");
+        let storage = InMemoryStorage::new();
+        storage.put_block(block_hash, 10);
+        // The chunk of the shard 1 missing in the block 11 is the one included in the block 10
+        storage.put_chunk(chunk_hash, 11, 1, 10);
+        let db_manager: std::sync::Arc<dyn ReaderStorage> = std::sync::Arc::new(storage);
+
+        assert_eq!(
+            scylla_db_convert_block_hash_to_block_height(&db_manager, block_hash)
+                .await
+                .unwrap(),
+            10
+        );
+        assert!(
+            scylla_db_convert_block_hash_to_block_height(&db_manager, chunk_hash)
+                .await
+                .is_err()
+        );
+
+        assert_eq!(
+            scylla_db_convert_chunk_hash_to_block_height_and_shard_id(&db_manager, chunk_hash)
+                .await
+                .unwrap(),
+            (10, 1)
+        );
+        assert_eq!(
+            scylla_db_convert_block_height_and_shard_id_to_height_included_and_shard_id(
+                &db_manager,
+                11,
+                1
+            )
+            .await
+            .unwrap(),
+            (10, 1)
+        );
+        assert!(
+            scylla_db_convert_block_height_and_shard_id_to_height_included_and_shard_id(
+                &db_manager,
+                11,
+                0
+            )
+            .await
+            .is_err()
+        );
+    }
+}
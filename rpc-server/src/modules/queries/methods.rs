@@ -1,10 +1,15 @@
+use crate::cache::NegativeCacheKey;
 use crate::config::ServerContext;
 use crate::errors::RPCError;
 use crate::modules::blocks::utils::fetch_block_from_cache_or_get;
 use crate::modules::blocks::CacheBlock;
 #[cfg(feature = "account_access_keys")]
 use crate::modules::queries::utils::fetch_list_access_keys_from_scylla_db;
-use crate::modules::queries::utils::{fetch_state_from_scylla_db, run_contract};
+use crate::modules::queries::utils::{
+    fetch_account_coalesced, fetch_state_from_scylla_db, is_known_missing, remember_missing,
+    run_contract_coalesced,
+};
+use crate::storage::is_not_found_error;
 use crate::utils::proxy_rpc_call;
 #[cfg(feature = "shadow_data_consistency")]
 use crate::utils::shadow_compare_results;
@@ -54,6 +59,18 @@ async fn query_call(
 ) -> Result<near_jsonrpc_primitives::types::query::RpcQueryResponse, RPCError> {
     tracing::debug!("`query` call. Params: {:?}", params,);
 
+    let account_id = match &params.request {
+        near_primitives::views::QueryRequest::ViewAccount { account_id }
+        | near_primitives::views::QueryRequest::ViewCode { account_id }
+        | near_primitives::views::QueryRequest::ViewAccessKey { account_id, .. }
+        | near_primitives::views::QueryRequest::ViewState { account_id, .. }
+        | near_primitives::views::QueryRequest::CallFunction { account_id, .. }
+        | near_primitives::views::QueryRequest::ViewAccessKeyList { account_id } => account_id,
+    };
+    if !data.account_filter.is_indexed(account_id.as_ref()) {
+        return Err(RPCError::account_not_indexed(account_id));
+    }
+
     let block = fetch_block_from_cache_or_get(&data, params.block_reference.clone())
         .await
         .map_err(near_jsonrpc_primitives::errors::RpcError::from)?;
@@ -215,17 +232,38 @@ async fn view_account(
         block.block_height
     );
 
-    let account = data
-        .scylla_db_manager
-        .get_account(account_id, block.block_height)
-        .await
-        .map_err(
-            |_err| near_jsonrpc_primitives::types::query::RpcQueryError::UnknownAccount {
-                requested_account_id: account_id.clone(),
-                block_height: block.block_height,
-                block_hash: block.block_hash,
-            },
-        )?;
+    let unknown_account = || near_jsonrpc_primitives::types::query::RpcQueryError::UnknownAccount {
+        requested_account_id: account_id.clone(),
+        block_height: block.block_height,
+        block_hash: block.block_hash,
+    };
+    let negative_cache_key = NegativeCacheKey::Account(account_id.clone());
+    if is_known_missing(
+        &data.negative_cache,
+        &negative_cache_key,
+        block.block_height,
+    ) {
+        return Err(unknown_account());
+    }
+
+    let indexed_block_height = data
+        .indexed_block_height
+        .load(std::sync::atomic::Ordering::SeqCst);
+    let account = match fetch_account_coalesced(data, account_id, block.block_height).await {
+        Ok(account) => account,
+        Err(err) => {
+            if is_not_found_error(&err) {
+                remember_missing(
+                    &data.negative_cache,
+                    &data.final_block_height,
+                    indexed_block_height,
+                    negative_cache_key,
+                    block.block_height,
+                );
+            }
+            return Err(unknown_account());
+        }
+    };
 
     Ok(near_jsonrpc_primitives::types::query::RpcQueryResponse {
         kind: near_jsonrpc_primitives::types::query::QueryResponseKind::ViewAccount(
@@ -251,7 +289,7 @@ async fn view_code(
         block.block_height
     );
     let contract = data
-        .scylla_db_manager
+        .db_manager
         .get_account(account_id, block.block_height)
         .await
         .map_err(
@@ -261,24 +299,38 @@ async fn view_code(
                 block_hash: block.block_hash,
             },
         )?;
-    let contract_code = data
-        .scylla_db_manager
-        .get_contract_code(account_id, block.block_height)
-        .await
-        .map_err(
-            |_err| near_jsonrpc_primitives::types::query::RpcQueryError::NoContractCode {
-                contract_account_id: account_id.clone(),
-                block_height: block.block_height,
-                block_hash: block.block_hash,
-            },
-        )?;
+    let code_hash = contract.data.code_hash();
+    let cached_code = data
+        .contract_code_cache
+        .write()
+        .unwrap()
+        .get(&code_hash)
+        .cloned();
+    let contract_code = match cached_code {
+        Some(code) => code,
+        None => {
+            let code = data
+                .db_manager
+                .get_contract_code_by_hash(account_id, block.block_height, code_hash)
+                .await
+                .map_err(|_err| {
+                    near_jsonrpc_primitives::types::query::RpcQueryError::NoContractCode {
+                        contract_account_id: account_id.clone(),
+                        block_height: block.block_height,
+                        block_hash: block.block_hash,
+                    }
+                })?;
+            data.contract_code_cache
+                .write()
+                .unwrap()
+                .put(code_hash, code.clone());
+            code
+        }
+    };
     Ok(near_jsonrpc_primitives::types::query::RpcQueryResponse {
         kind: near_jsonrpc_primitives::types::query::QueryResponseKind::ViewCode(
             near_primitives::views::ContractCodeView::from(
-                near_primitives::contract::ContractCode::new(
-                    contract_code.data,
-                    Some(contract.data.code_hash()),
-                ),
+                near_primitives::contract::ContractCode::new(contract_code, Some(code_hash)),
             ),
         ),
         block_height: contract.block_height,
@@ -305,18 +357,9 @@ async fn function_call(
         args,
     );
 
-    let call_results = run_contract(
-        account_id,
-        method_name,
-        args,
-        data.scylla_db_manager.clone(),
-        &data.compiled_contract_code_cache,
-        &data.contract_code_cache,
-        block,
-        data.max_gas_burnt,
-    )
-    .await
-    .map_err(|err| err.to_rpc_query_error(block.block_height, block.block_hash))?;
+    let call_results = run_contract_coalesced(data, account_id, method_name, args, block)
+        .await
+        .map_err(|err| err.to_rpc_query_error(block.block_height, block.block_hash))?;
     Ok(near_jsonrpc_primitives::types::query::RpcQueryResponse {
         kind: near_jsonrpc_primitives::types::query::QueryResponseKind::CallResult(
             near_primitives::views::CallResult {
@@ -346,20 +389,16 @@ async fn view_state(
         prefix,
     );
 
-    let contract_state = fetch_state_from_scylla_db(
-        &data.scylla_db_manager,
-        account_id,
-        block.block_height,
-        prefix,
-    )
-    .await
-    .map_err(
-        |_err| near_jsonrpc_primitives::types::query::RpcQueryError::UnknownAccount {
-            requested_account_id: account_id.clone(),
-            block_height: block.block_height,
-            block_hash: block.block_hash,
-        },
-    )?;
+    let contract_state =
+        fetch_state_from_scylla_db(&data.db_manager, account_id, block.block_height, prefix)
+            .await
+            .map_err(|_err| {
+                near_jsonrpc_primitives::types::query::RpcQueryError::UnknownAccount {
+                    requested_account_id: account_id.clone(),
+                    block_height: block.block_height,
+                    block_hash: block.block_hash,
+                }
+            })?;
 
     Ok(near_jsonrpc_primitives::types::query::RpcQueryResponse {
         kind: near_jsonrpc_primitives::types::query::QueryResponseKind::ViewState(contract_state),
@@ -388,17 +427,43 @@ async fn view_access_key(
         public_key.to_string(),
     );
 
-    let access_key = data
-        .scylla_db_manager
+    let unknown_access_key =
+        |public_key| near_jsonrpc_primitives::types::query::RpcQueryError::UnknownAccessKey {
+            public_key,
+            block_height: block.block_height,
+            block_hash: block.block_hash,
+        };
+    let negative_cache_key = NegativeCacheKey::AccessKey(account_id.clone(), public_key.clone());
+    if is_known_missing(
+        &data.negative_cache,
+        &negative_cache_key,
+        block.block_height,
+    ) {
+        return Err(unknown_access_key(public_key));
+    }
+
+    let indexed_block_height = data
+        .indexed_block_height
+        .load(std::sync::atomic::Ordering::SeqCst);
+    let access_key = match data
+        .db_manager
         .get_access_key(account_id, block.block_height, public_key.clone())
         .await
-        .map_err(
-            |_err| near_jsonrpc_primitives::types::query::RpcQueryError::UnknownAccessKey {
-                public_key,
-                block_height: block.block_height,
-                block_hash: block.block_hash,
-            },
-        )?;
+    {
+        Ok(access_key) => access_key,
+        Err(err) => {
+            if is_not_found_error(&err) {
+                remember_missing(
+                    &data.negative_cache,
+                    &data.final_block_height,
+                    indexed_block_height,
+                    negative_cache_key,
+                    block.block_height,
+                );
+            }
+            return Err(unknown_access_key(public_key));
+        }
+    };
 
     Ok(near_jsonrpc_primitives::types::query::RpcQueryResponse {
         kind: near_jsonrpc_primitives::types::query::QueryResponseKind::AccessKey(
@@ -425,19 +490,16 @@ async fn view_access_keys_list(
         block.block_height,
     );
 
-    let access_keys = fetch_list_access_keys_from_scylla_db(
-        &data.scylla_db_manager,
-        account_id,
-        block.block_height,
-    )
-    .await
-    // TODO: review this once we implement the `account_access_keys` after the redesign
-    // this error has to be the same the real NEAR JSON RPC returns in this case
-    .map_err(
-        |err| near_jsonrpc_primitives::types::query::RpcQueryError::InternalError {
-            error_message: format!("Failed to fetch access keys: {}", err),
-        },
-    )?;
+    let access_keys =
+        fetch_list_access_keys_from_scylla_db(&data.db_manager, account_id, block.block_height)
+            .await
+            // TODO: review this once we implement the `account_access_keys` after the redesign
+            // this error has to be the same the real NEAR JSON RPC returns in this case
+            .map_err(
+                |err| near_jsonrpc_primitives::types::query::RpcQueryError::InternalError {
+                    error_message: format!("Failed to fetch access keys: {}", err),
+                },
+            )?;
 
     Ok(near_jsonrpc_primitives::types::query::RpcQueryResponse {
         kind: near_jsonrpc_primitives::types::query::QueryResponseKind::AccessKeyList(
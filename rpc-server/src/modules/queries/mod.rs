@@ -1,4 +1,4 @@
-use crate::storage::ScyllaDBManager;
+use crate::storage::ReaderStorage;
 use futures::executor::block_on;
 use std::collections::HashMap;
 
@@ -9,8 +9,23 @@ const MAX_LIMIT: u8 = 100;
 
 pub type Result<T> = ::std::result::Result<T, near_vm_logic::VMLogicError>;
 
+/// Identifies the requests which can share one execution in the `RequestCoalescer`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum QueryCoalescingKey {
+    ViewAccount {
+        account_id: near_primitives::types::AccountId,
+        block_height: near_primitives::types::BlockHeight,
+    },
+    CallFunction {
+        account_id: near_primitives::types::AccountId,
+        method_name: String,
+        args: Vec<u8>,
+        block_height: near_primitives::types::BlockHeight,
+    },
+}
+
 pub struct CodeStorage {
-    scylla_db_manager: std::sync::Arc<ScyllaDBManager>,
+    db_manager: std::sync::Arc<dyn ReaderStorage>,
     account_id: near_primitives::types::AccountId,
     block_height: near_primitives::types::BlockHeight,
     validators: HashMap<near_primitives::types::AccountId, near_primitives::types::Balance>,
@@ -33,12 +48,12 @@ impl near_vm_logic::ValuePtr for StorageValuePtr {
 
 impl CodeStorage {
     pub fn init(
-        scylla_db_manager: std::sync::Arc<ScyllaDBManager>,
+        db_manager: std::sync::Arc<dyn ReaderStorage>,
         account_id: near_primitives::types::AccountId,
         block_height: near_primitives::types::BlockHeight,
     ) -> Self {
         Self {
-            scylla_db_manager,
+            db_manager,
             account_id,
             block_height,
             validators: Default::default(), // TODO: Should be store list of validators in the current epoch.
@@ -66,11 +81,9 @@ impl near_vm_logic::External for CodeStorage {
         key: &[u8],
         _mode: near_vm_logic::StorageGetMode,
     ) -> Result<Option<Box<dyn near_vm_logic::ValuePtr>>> {
-        let get_db_data = self.scylla_db_manager.get_state_key_value(
-            &self.account_id,
-            self.block_height,
-            key.to_vec(),
-        );
+        let get_db_data =
+            self.db_manager
+                .get_state_key_value(&self.account_id, self.block_height, key.to_vec());
         match block_on(get_db_data) {
             Ok(data) => Ok(if !data.is_empty() {
                 Some(Box::new(StorageValuePtr { value: data }) as Box<_>)
@@ -108,11 +121,9 @@ impl near_vm_logic::External for CodeStorage {
         key: &[u8],
         _mode: near_vm_logic::StorageGetMode,
     ) -> Result<bool> {
-        let get_db_state_keys = self.scylla_db_manager.get_state_key_value(
-            &self.account_id,
-            self.block_height,
-            key.to_vec(),
-        );
+        let get_db_state_keys =
+            self.db_manager
+                .get_state_key_value(&self.account_id, self.block_height, key.to_vec());
         match block_on(get_db_state_keys) {
             Ok(data) => Ok(!data.is_empty()),
             Err(_) => Ok(false),
@@ -11,8 +11,9 @@ use tokio::task;
 use crate::config::CompiledCodeCache;
 use crate::errors::FunctionCallError;
 use crate::modules::queries::{CodeStorage, MAX_LIMIT};
-use crate::storage::ScyllaDBManager;
+use crate::storage::ReaderStorage;
 
+#[derive(Clone)]
 pub struct RunContractResponse {
     pub result: Vec<u8>,
     pub logs: Vec<String>,
@@ -22,10 +23,10 @@ pub struct RunContractResponse {
 
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(scylla_db_manager))
+    tracing::instrument(skip(db_manager))
 )]
 pub async fn get_state_keys_from_scylla(
-    scylla_db_manager: &std::sync::Arc<ScyllaDBManager>,
+    db_manager: &std::sync::Arc<dyn ReaderStorage>,
     account_id: &near_primitives::types::AccountId,
     block_height: near_primitives::types::BlockHeight,
     prefix: &[u8],
@@ -39,17 +40,19 @@ pub async fn get_state_keys_from_scylla(
     let mut data: HashMap<crate::storage::StateKey, crate::storage::StateValue> = HashMap::new();
     let result = {
         if !prefix.is_empty() {
-            scylla_db_manager
-                .get_state_keys_by_prefix(account_id, prefix)
+            db_manager
+                .get_state_keys_by_prefix(account_id, block_height, prefix)
                 .await
         } else {
-            scylla_db_manager.get_all_state_keys(account_id).await
+            db_manager
+                .get_all_state_keys(account_id, block_height)
+                .await
         }
     };
     match result {
         Ok(state_keys) => {
             for state_key in state_keys {
-                let state_value_result = scylla_db_manager
+                let state_value_result = db_manager
                     .get_state_key_value(account_id, block_height, state_key.clone())
                     .await;
                 if let Ok(state_value) = state_value_result {
@@ -71,10 +74,10 @@ pub async fn get_state_keys_from_scylla(
 #[cfg(feature = "account_access_keys")]
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(scylla_db_manager))
+    tracing::instrument(skip(db_manager))
 )]
 pub async fn fetch_list_access_keys_from_scylla_db(
-    scylla_db_manager: &std::sync::Arc<ScyllaDBManager>,
+    db_manager: &std::sync::Arc<dyn ReaderStorage>,
     account_id: &near_primitives::types::AccountId,
     block_height: near_primitives::types::BlockHeight,
 ) -> anyhow::Result<Vec<near_primitives::views::AccessKeyInfoView>> {
@@ -83,11 +86,9 @@ pub async fn fetch_list_access_keys_from_scylla_db(
         account_id,
         block_height,
     );
-    let row = scylla_db_manager
+    let account_keys = db_manager
         .get_account_access_keys(account_id, block_height)
         .await?;
-    let (account_keys,): (HashMap<String, Vec<u8>>,) =
-        row.into_typed::<(HashMap<String, Vec<u8>>,)>()?;
     let account_keys_view = account_keys
         .into_iter()
         .map(
@@ -107,10 +108,10 @@ pub async fn fetch_list_access_keys_from_scylla_db(
 
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(scylla_db_manager))
+    tracing::instrument(skip(db_manager))
 )]
 pub async fn fetch_state_from_scylla_db(
-    scylla_db_manager: &std::sync::Arc<ScyllaDBManager>,
+    db_manager: &std::sync::Arc<dyn ReaderStorage>,
     account_id: &near_primitives::types::AccountId,
     block_height: near_primitives::types::BlockHeight,
     prefix: &[u8],
@@ -122,7 +123,7 @@ pub async fn fetch_state_from_scylla_db(
         prefix,
     );
     let state_from_db =
-        get_state_keys_from_scylla(scylla_db_manager, account_id, block_height, prefix).await;
+        get_state_keys_from_scylla(db_manager, account_id, block_height, prefix).await;
     if state_from_db.is_empty() {
         anyhow::bail!("Data not found in db")
     } else {
@@ -144,12 +145,7 @@ pub async fn fetch_state_from_scylla_db(
 #[allow(clippy::too_many_arguments)]
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(
-        scylla_db_manager,
-        context,
-        contract_code,
-        compiled_contract_code_cache
-    ))
+    tracing::instrument(skip(db_manager, context, contract_code, compiled_contract_code_cache))
 )]
 async fn run_code_in_vm_runner(
     contract_code: near_primitives::contract::ContractCode,
@@ -157,12 +153,12 @@ async fn run_code_in_vm_runner(
     context: near_vm_logic::VMContext,
     account_id: near_primitives::types::AccountId,
     block_height: near_primitives::types::BlockHeight,
-    scylla_db_manager: std::sync::Arc<ScyllaDBManager>,
+    db_manager: std::sync::Arc<dyn ReaderStorage>,
     latest_protocol_version: near_primitives::types::ProtocolVersion,
     compiled_contract_code_cache: &std::sync::Arc<CompiledCodeCache>,
 ) -> Result<near_vm_logic::VMOutcome, near_primitives::errors::RuntimeError> {
     let contract_method_name = String::from(method_name);
-    let mut external = CodeStorage::init(scylla_db_manager.clone(), account_id, block_height);
+    let mut external = CodeStorage::init(db_manager.clone(), account_id, block_height);
     let code_cache = std::sync::Arc::clone(compiled_contract_code_cache);
 
     let results = task::spawn_blocking(move || {
@@ -225,17 +221,13 @@ async fn run_code_in_vm_runner(
 #[allow(clippy::too_many_arguments)]
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(
-        scylla_db_manager,
-        compiled_contract_code_cache,
-        contract_code_cache
-    ))
+    tracing::instrument(skip(db_manager, compiled_contract_code_cache, contract_code_cache))
 )]
 pub async fn run_contract(
     account_id: near_primitives::types::AccountId,
     method_name: &str,
     args: near_primitives::types::FunctionArgs,
-    scylla_db_manager: std::sync::Arc<ScyllaDBManager>,
+    db_manager: std::sync::Arc<dyn ReaderStorage>,
     compiled_contract_code_cache: &std::sync::Arc<CompiledCodeCache>,
     contract_code_cache: &std::sync::Arc<
         std::sync::RwLock<crate::cache::LruMemoryCache<near_primitives::hash::CryptoHash, Vec<u8>>>,
@@ -243,7 +235,7 @@ pub async fn run_contract(
     block: crate::modules::blocks::CacheBlock,
     max_gas_burnt: near_primitives_core::types::Gas,
 ) -> Result<RunContractResponse, FunctionCallError> {
-    let contract = scylla_db_manager
+    let contract = db_manager
         .get_account(&account_id, block.block_height)
         .await
         .map_err(|_| FunctionCallError::AccountDoesNotExist {
@@ -261,8 +253,12 @@ pub async fn run_contract(
             near_primitives::contract::ContractCode::new(code, Some(contract.data.code_hash()))
         }
         None => {
-            let code = scylla_db_manager
-                .get_contract_code(&account_id, block.block_height)
+            let code = db_manager
+                .get_contract_code_by_hash(
+                    &account_id,
+                    block.block_height,
+                    contract.data.code_hash(),
+                )
                 .await
                 .map_err(|_| FunctionCallError::InvalidAccountId {
                     requested_account_id: account_id.clone(),
@@ -270,8 +266,8 @@ pub async fn run_contract(
             contract_code_cache
                 .write()
                 .unwrap()
-                .put(contract.data.code_hash(), code.data.clone());
-            near_primitives::contract::ContractCode::new(code.data, Some(contract.data.code_hash()))
+                .put(contract.data.code_hash(), code.clone());
+            near_primitives::contract::ContractCode::new(code, Some(contract.data.code_hash()))
         }
     };
     let public_key = PublicKey::empty(KeyType::ED25519);
@@ -305,7 +301,7 @@ pub async fn run_contract(
         context,
         account_id,
         block.block_height,
-        scylla_db_manager.clone(),
+        db_manager.clone(),
         block.latest_protocol_version,
         compiled_contract_code_cache,
     )
@@ -332,3 +328,209 @@ pub async fn run_contract(
         })
     }
 }
+
+/// Checks whether the account or access key is known to be missing at the given block height
+pub fn is_known_missing(
+    negative_cache: &std::sync::RwLock<crate::cache::NegativeCache>,
+    key: &crate::cache::NegativeCacheKey,
+    block_height: near_primitives::types::BlockHeight,
+) -> bool {
+    let is_missing = negative_cache.write().unwrap().contains(key, block_height);
+    if is_missing {
+        crate::metrics::NEGATIVE_CACHE_HITS_TOTAL.inc();
+    }
+    is_missing
+}
+
+/// Remembers the account or access key as missing if the lookup was made for the final block
+/// and the state-indexer had already stored that block when the lookup started.
+/// `indexed_block_height` has to be read before the lookup, otherwise the miss of the block
+/// the state-indexer is still writing could be cached.
+/// The final block height is checked under the lock to avoid racing with the invalidation
+/// of the negative cache on the new blocks.
+pub fn remember_missing(
+    negative_cache: &std::sync::RwLock<crate::cache::NegativeCache>,
+    final_block_height: &std::sync::atomic::AtomicU64,
+    indexed_block_height: near_primitives::types::BlockHeight,
+    key: crate::cache::NegativeCacheKey,
+    block_height: near_primitives::types::BlockHeight,
+) {
+    if block_height > indexed_block_height {
+        return;
+    }
+    let mut negative_cache = negative_cache.write().unwrap();
+    if block_height == final_block_height.load(std::sync::atomic::Ordering::SeqCst) {
+        negative_cache.put(key, block_height);
+    }
+}
+
+/// Fetches the account. Identical concurrent requests to the final block share one database read
+#[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(data)))]
+pub async fn fetch_account_coalesced(
+    data: &crate::config::ServerContext,
+    account_id: &near_primitives::types::AccountId,
+    block_height: near_primitives::types::BlockHeight,
+) -> Result<
+    crate::storage::QueryData<near_primitives::account::Account>,
+    std::sync::Arc<anyhow::Error>,
+> {
+    let db_manager = data.db_manager.clone();
+    let account_id = account_id.clone();
+    if block_height
+        != data
+            .final_block_height
+            .load(std::sync::atomic::Ordering::SeqCst)
+    {
+        return db_manager
+            .get_account(&account_id, block_height)
+            .await
+            .map_err(std::sync::Arc::new);
+    }
+    let key = crate::modules::queries::QueryCoalescingKey::ViewAccount {
+        account_id: account_id.clone(),
+        block_height,
+    };
+    data.account_coalescer
+        .run(key, async move {
+            db_manager
+                .get_account(&account_id, block_height)
+                .await
+                .map_err(std::sync::Arc::new)
+        })
+        .await
+        .unwrap_or_else(|err| Err(std::sync::Arc::new(err.into())))
+}
+
+/// Runs the contract function. Identical concurrent requests to the final block share
+/// one execution including the database reads and the VM run
+#[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(data)))]
+pub async fn run_contract_coalesced(
+    data: &crate::config::ServerContext,
+    account_id: near_primitives::types::AccountId,
+    method_name: &str,
+    args: near_primitives::types::FunctionArgs,
+    block: crate::modules::blocks::CacheBlock,
+) -> Result<RunContractResponse, FunctionCallError> {
+    if block.block_height
+        != data
+            .final_block_height
+            .load(std::sync::atomic::Ordering::SeqCst)
+    {
+        return run_contract(
+            account_id,
+            method_name,
+            args,
+            data.db_manager.clone(),
+            &data.compiled_contract_code_cache,
+            &data.contract_code_cache,
+            block,
+            data.max_gas_burnt,
+        )
+        .await;
+    }
+    let key = crate::modules::queries::QueryCoalescingKey::CallFunction {
+        account_id: account_id.clone(),
+        method_name: method_name.to_string(),
+        args: args.clone().into(),
+        block_height: block.block_height,
+    };
+    let method_name = method_name.to_string();
+    let db_manager = data.db_manager.clone();
+    let compiled_contract_code_cache = data.compiled_contract_code_cache.clone();
+    let contract_code_cache = data.contract_code_cache.clone();
+    let max_gas_burnt = data.max_gas_burnt;
+    data.function_call_coalescer
+        .run(key, async move {
+            run_contract(
+                account_id,
+                &method_name,
+                args,
+                db_manager,
+                &compiled_contract_code_cache,
+                &contract_code_cache,
+                block,
+                max_gas_burnt,
+            )
+            .await
+        })
+        .await
+        .unwrap_or_else(|err| {
+            Err(FunctionCallError::InternalError {
+                error_message: err.to_string(),
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+
+    fn storage_with_state(account_id: &near_primitives::types::AccountId) -> InMemoryStorage {
+        let storage = InMemoryStorage::new();
+        let block_hash = near_primitives::hash::CryptoHash::default();
+        for (block_height, key, value) in [
+            (10, b"STATE-a".to_vec(), Some(b"1".to_vec())),
+            (10, b"STATE-b".to_vec(), Some(b"2".to_vec())),
+            (20, b"OTHER".to_vec(), Some(b"3".to_vec())),
+            (30, b"STATE-a".to_vec(), None),
+        ] {
+            storage.put_state_value(account_id.clone(), block_height, block_hash, key, value);
+        }
+        storage
+    }
+
+    fn state_values(result: near_primitives::views::ViewStateResult) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut values: Vec<_> = result
+            .values
+            .into_iter()
+            .map(|item| (item.key.to_vec(), item.value.to_vec()))
+            .collect();
+        values.sort();
+        values
+    }
+
+    #[tokio::test]
+    async fn view_state_returns_the_keys_alive_at_the_block_height() {
+        let account_id: near_primitives::types::AccountId = "alice.near".parse().unwrap();
+        let db_manager: std::sync::Arc<dyn ReaderStorage> =
+            std::sync::Arc::new(storage_with_state(&account_id));
+
+        let result = fetch_state_from_scylla_db(&db_manager, &account_id, 25, &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            state_values(result),
+            vec![
+                (b"OTHER".to_vec(), b"3".to_vec()),
+                (b"STATE-a".to_vec(), b"1".to_vec()),
+                (b"STATE-b".to_vec(), b"2".to_vec()),
+            ]
+        );
+
+        let result = fetch_state_from_scylla_db(&db_manager, &account_id, 30, b"STATE")
+            .await
+            .unwrap();
+        assert_eq!(
+            state_values(result),
+            vec![(b"STATE-b".to_vec(), b"2".to_vec())]
+        );
+    }
+
+    #[tokio::test]
+    async fn view_state_fails_before_the_state_is_written() {
+        let account_id: near_primitives::types::AccountId = "alice.near".parse().unwrap();
+        let db_manager: std::sync::Arc<dyn ReaderStorage> =
+            std::sync::Arc::new(storage_with_state(&account_id));
+
+        assert!(fetch_state_from_scylla_db(&db_manager, &account_id, 5, &[])
+            .await
+            .is_err());
+        let other_account_id = "bob.near".parse().unwrap();
+        assert!(
+            fetch_state_from_scylla_db(&db_manager, &other_account_id, 25, &[])
+                .await
+                .is_err()
+        );
+    }
+}
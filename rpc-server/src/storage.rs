@@ -2,19 +2,30 @@ use std::convert::TryFrom;
 use std::str::FromStr;
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use futures::StreamExt;
 use num_traits::ToPrimitive;
-use scylla::{prepared_statement::PreparedStatement, IntoTypedRows};
+use scylla::IntoTypedRows;
 
 use database::ScyllaStorageManager;
 
+#[cfg(feature = "rocksdb")]
+pub mod embedded;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+// Lets the tests run the handlers against the prepared data without the database
+#[cfg(test)]
+pub mod memory;
+
 pub type StateKey = Vec<u8>;
 pub type StateValue = Vec<u8>;
 pub struct BlockHeightShardId(pub u64, pub u64);
+#[derive(Clone)]
 pub struct QueryData<T: BorshDeserialize> {
     pub data: T,
     pub block_height: near_primitives_core::types::BlockHeight,
     pub block_hash: near_indexer_primitives::CryptoHash,
 }
+#[derive(Clone)]
 pub struct ReceiptRecord {
     pub receipt_id: near_primitives::hash::CryptoHash,
     pub parent_transaction_hash: near_primitives::hash::CryptoHash,
@@ -27,99 +38,435 @@ pub struct BlockRecord {
     pub hash: near_primitives::hash::CryptoHash,
 }
 
+/// The requested data is absent in the storage or was deleted at the requested block height
+#[derive(thiserror::Error, Debug)]
+#[error("{0} not found")]
+pub struct DataNotFoundError(pub String);
+
+/// Returns true if the error means the requested row is absent or was deleted
+/// (the data value is null) rather than a failure to query the database
+pub fn is_not_found_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<DataNotFoundError>().is_some()
+        || err
+            .downcast_ref::<scylla::transport::query_result::SingleRowError>()
+            .is_some()
+        || err
+            .downcast_ref::<scylla::cql_to_rust::FromRowError>()
+            .is_some()
+}
+
+#[cfg(any(feature = "rocksdb", feature = "postgres"))]
+fn parse_block_hash(block_hash: &str) -> anyhow::Result<near_primitives::hash::CryptoHash> {
+    near_primitives::hash::CryptoHash::from_str(block_hash)
+        .map_err(|err| anyhow::anyhow!("Failed to parse `block_hash` to CryptoHash: {}", err))
+}
+
+/// Converts the record of the storages keeping the whole history of the key to the `QueryData`,
+/// the deleted value is reported as not found
+#[cfg(any(feature = "rocksdb", feature = "postgres"))]
+fn query_data<T: BorshDeserialize>(
+    record: Option<database::VersionedRecord>,
+    description: impl FnOnce() -> String,
+) -> anyhow::Result<QueryData<T>> {
+    match record {
+        Some(database::VersionedRecord {
+            block_height,
+            block_hash,
+            data: Some(data),
+        }) => QueryData::try_from((data, block_height, parse_block_hash(&block_hash)?)),
+        _ => Err(DataNotFoundError(description()).into()),
+    }
+}
+
+/// Merges the hex-encoded keys of the versioned key index with the flag telling if the key is deleted
+/// and the keys of the legacy `account_state` index, returns the keys which are not deleted.
+/// The legacy keys are the keys written before the versioned index, the versioned records of them
+/// (e.g. the deletion) take precedence
+fn alive_state_keys(
+    mut versioned_state_keys: std::collections::BTreeMap<String, bool>,
+    legacy_state_keys: Vec<String>,
+) -> anyhow::Result<Vec<StateKey>> {
+    for data_key in legacy_state_keys {
+        versioned_state_keys.entry(data_key).or_insert(false);
+    }
+    versioned_state_keys
+        .into_iter()
+        .filter(|(_, deleted)| !deleted)
+        .map(|(data_key, _)| Ok(hex::decode(data_key)?))
+        .collect()
+}
+
+/// The read access to the data collected by the indexers
+/// `ScyllaDBManager` is the production implementation,
+/// `embedded::EmbeddedStorage` reads the embedded RocksDB databases of the single-node deployments,
+/// `database::postgres::PostgresDBManager` reads the PostgreSQL database,
+/// `memory::InMemoryStorage` keeps the data in memory to run the handlers without the database
+#[async_trait::async_trait]
+pub trait ReaderStorage: Send + Sync {
+    /// Searches the block height by the given block hash
+    async fn get_block_by_hash(
+        &self,
+        block_hash: near_primitives::hash::CryptoHash,
+    ) -> anyhow::Result<u64>;
+
+    /// Searches the block height and shard id by the given chunk hash
+    async fn get_block_by_chunk_hash(
+        &self,
+        chunk_hash: near_primitives::hash::CryptoHash,
+    ) -> anyhow::Result<BlockHeightShardId>;
+
+    /// Returns the state keys of the given account id alive at the given block height.
+    /// Until the versioned key index of ScyllaDB is backfilled, the keys written before it
+    /// are returned even if they are deleted, the values of the deleted ones are empty
+    async fn get_all_state_keys(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        block_height: near_primitives::types::BlockHeight,
+    ) -> anyhow::Result<Vec<StateKey>>;
+
+    /// Returns the state keys of the given account id alive at the given block height
+    /// filtered by the given prefix
+    async fn get_state_keys_by_prefix(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        block_height: near_primitives::types::BlockHeight,
+        prefix: &[u8],
+    ) -> anyhow::Result<Vec<StateKey>>;
+
+    /// Returns the state value for the given key of the given account at the given block height
+    async fn get_state_key_value(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        block_height: near_primitives::types::BlockHeight,
+        key_data: StateKey,
+    ) -> anyhow::Result<StateValue>;
+
+    /// Returns the near_primitives::account::Account at the given block height
+    async fn get_account(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        request_block_height: near_primitives::types::BlockHeight,
+    ) -> anyhow::Result<QueryData<near_primitives::account::Account>>;
+
+    /// Returns the contract code at the given block height
+    async fn get_contract_code(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        request_block_height: near_primitives::types::BlockHeight,
+    ) -> anyhow::Result<QueryData<Vec<u8>>>;
+
+    /// Returns the contract code by the `code_hash` of the account at the given block height.
+    /// The storages keeping the code by the code hash don't read the history of the account,
+    /// the other ones return the contract code of the account at the given block height
+    async fn get_contract_code_by_hash(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        request_block_height: near_primitives::types::BlockHeight,
+        _code_hash: near_primitives::hash::CryptoHash,
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(self
+            .get_contract_code(account_id, request_block_height)
+            .await?
+            .data)
+    }
+
+    /// Returns the near_primitives::account::AccessKey at the given block height
+    async fn get_access_key(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        request_block_height: near_primitives::types::BlockHeight,
+        public_key: near_crypto::PublicKey,
+    ) -> anyhow::Result<QueryData<near_primitives::account::AccessKey>>;
+
+    /// Returns the borsh-serialized access keys of the account by hex-encoded public keys
+    #[cfg(feature = "account_access_keys")]
+    async fn get_account_access_keys(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        block_height: near_primitives::types::BlockHeight,
+    ) -> anyhow::Result<std::collections::HashMap<String, Vec<u8>>>;
+
+    /// Returns the near_primitives::views::ReceiptView at the given receipt_id
+    async fn get_receipt_by_id(
+        &self,
+        receipt_id: near_primitives::hash::CryptoHash,
+    ) -> anyhow::Result<ReceiptRecord>;
+
+    /// Returns the readnode_primitives::TransactionDetails at the given transaction hash
+    async fn get_transaction_by_hash(
+        &self,
+        transaction_hash: &str,
+    ) -> anyhow::Result<readnode_primitives::TransactionDetails>;
+
+    /// Returns the block height and shard id by the given block height
+    async fn get_block_by_height_and_shard_id(
+        &self,
+        block_height: near_primitives::types::BlockHeight,
+        shard_id: near_primitives::types::ShardId,
+    ) -> anyhow::Result<BlockHeightShardId>;
+
+    /// Returns all the state changes of the block in the order they were applied,
+    /// the code of the deployed contracts is to be read by the `code_hash` of the change
+    /// Fails if the block is not indexed yet or its changes are stored partially
+    async fn get_block_state_changes(
+        &self,
+        block_height: near_primitives::types::BlockHeight,
+    ) -> anyhow::Result<Vec<readnode_primitives::StoredStateChange>>;
+
+    /// Returns the block height the state-indexer with the given id has persisted as processed,
+    /// all the blocks at and below it are stored
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<near_primitives::types::BlockHeight>>;
+}
+
 pub struct ScyllaDBManager {
-    scylla_session: std::sync::Arc<scylla::Session>,
-    get_block_by_hash: PreparedStatement,
-    get_block_by_chunk_id: PreparedStatement,
-    get_all_state_keys: PreparedStatement,
-    get_state_keys_by_prefix: PreparedStatement,
-    get_state_key_value: PreparedStatement,
-    get_account: PreparedStatement,
-    get_contract_code: PreparedStatement,
-    get_access_key: PreparedStatement,
+    scylla_session: std::sync::Arc<database::session::ScyllaSession>,
+    get_block_by_hash: database::NamedStatement,
+    get_block_by_chunk_id: database::NamedStatement,
+    get_all_state_keys: database::NamedStatement,
+    get_state_keys_by_prefix: database::NamedStatement,
+    get_state_key_value: database::NamedStatement,
+    get_account: database::NamedStatement,
+    get_contract_code: database::NamedStatement,
+    get_contract_code_by_hash: database::NamedStatement,
+    get_access_key: database::NamedStatement,
     #[cfg(feature = "account_access_keys")]
-    get_account_access_keys: PreparedStatement,
-    get_receipt: PreparedStatement,
-    get_transaction_by_hash: PreparedStatement,
-    get_stored_at_block_height_and_shard_id_by_block_height: PreparedStatement,
+    get_account_access_keys: database::NamedStatement,
+    get_receipt: database::NamedStatement,
+    get_transaction_by_hash: database::NamedStatement,
+    get_stored_at_block_height_and_shard_id_by_block_height: database::NamedStatement,
+    get_block_state_changes: database::NamedStatement,
+    get_last_processed_block_height: database::NamedStatement,
+    get_legacy_state_keys: database::NamedStatement,
+    get_legacy_state_keys_by_prefix: database::NamedStatement,
+    /// Set once the state-indexer has recorded the keys of the legacy `account_state` index
+    /// as copied into `account_state_versions`
+    state_keys_backfilled: std::sync::atomic::AtomicBool,
 }
 
 #[async_trait::async_trait]
 impl ScyllaStorageManager for ScyllaDBManager {
+    /// The server reads the keyspaces of the indexers, it has no keyspaces of its own
+    fn migrations(
+        keyspaces: &database::keyspaces::ScyllaKeyspaces,
+    ) -> Vec<(String, &'static database::migrations::KeyspaceMigrations)> {
+        vec![
+            (
+                keyspaces.scylla_state_indexer_keyspace.clone(),
+                &database::migrations::STATE_INDEXER,
+            ),
+            (
+                keyspaces.scylla_tx_indexer_keyspace.clone(),
+                &database::migrations::TX_INDEXER,
+            ),
+        ]
+    }
+
     async fn prepare(
-        scylla_db_session: std::sync::Arc<scylla::Session>,
+        scylla_db_session: std::sync::Arc<database::session::ScyllaSession>,
+        keyspaces: &database::keyspaces::ScyllaKeyspaces,
     ) -> anyhow::Result<Box<Self>> {
+        let state_indexer_keyspace = &keyspaces.scylla_state_indexer_keyspace;
+        let tx_indexer_keyspace = &keyspaces.scylla_tx_indexer_keyspace;
         Ok(Box::new(Self {
             scylla_session: scylla_db_session.clone(),
 
             get_block_by_hash: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT block_height FROM state_indexer.blocks WHERE block_hash = ?",
+                "get_block_by_hash",
+                &format!("SELECT block_height FROM {state_indexer_keyspace}.blocks WHERE block_hash = ?"),
             ).await?,
 
             get_block_by_chunk_id: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT stored_at_block_height, shard_id FROM state_indexer.chunks WHERE chunk_hash = ? LIMIT 1",
+                "get_block_by_chunk_id",
+                &format!("SELECT stored_at_block_height, shard_id FROM {state_indexer_keyspace}.chunks WHERE chunk_hash = ? LIMIT 1"),
             ).await?,
 
             get_all_state_keys: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT data_key FROM state_indexer.account_state WHERE account_id = ?",
+                "get_all_state_keys",
+                // The first row of the group is the latest record of the key at or below the block height
+                &format!("SELECT data_key, deleted FROM {state_indexer_keyspace}.account_state_versions WHERE account_id = ? AND block_height <= ? GROUP BY data_key ALLOW FILTERING"),
             ).await?,
 
             get_state_keys_by_prefix: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT data_key FROM state_indexer.account_state WHERE account_id = ? AND data_key LIKE ?",
+                "get_state_keys_by_prefix",
+                &format!("SELECT data_key, deleted FROM {state_indexer_keyspace}.account_state_versions WHERE account_id = ? AND data_key >= ? AND data_key < ? AND block_height <= ? GROUP BY data_key ALLOW FILTERING"),
             ).await?,
 
             get_state_key_value: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT data_value FROM state_indexer.state_changes_data WHERE account_id = ? AND block_height <= ? AND data_key = ? LIMIT 1",
+                "get_state_key_value",
+                &format!("SELECT data_value FROM {state_indexer_keyspace}.state_changes_data WHERE account_id = ? AND block_height <= ? AND data_key = ? LIMIT 1"),
             ).await?,
 
             get_account: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT block_height, block_hash, data_value FROM state_indexer.state_changes_account WHERE account_id = ? AND block_height <= ? LIMIT 1",
+                "get_account",
+                &format!("SELECT block_height, block_hash, data_value FROM {state_indexer_keyspace}.state_changes_account WHERE account_id = ? AND block_height <= ? LIMIT 1"),
             ).await?,
 
             get_contract_code: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT block_height, block_hash, data_value FROM state_indexer.state_changes_contract WHERE account_id = ? AND block_height <= ? LIMIT 1",
+                "get_contract_code",
+                &format!("SELECT block_height, block_hash, data_value, code_hash FROM {state_indexer_keyspace}.state_changes_contract WHERE account_id = ? AND block_height <= ? LIMIT 1"),
+            ).await?,
+
+            get_contract_code_by_hash: Self::prepare_read_query(
+                &scylla_db_session,
+                "get_contract_code_by_hash",
+                &format!("SELECT code FROM {state_indexer_keyspace}.contract_code WHERE code_hash = ?"),
             ).await?,
 
             get_access_key: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT block_height, block_hash, data_value FROM state_indexer.state_changes_access_key WHERE account_id = ? AND block_height <= ? AND data_key = ? LIMIT 1",
+                "get_access_key",
+                &format!("SELECT block_height, block_hash, data_value FROM {state_indexer_keyspace}.state_changes_access_key WHERE account_id = ? AND block_height <= ? AND data_key = ? LIMIT 1"),
             ).await?,
             #[cfg(feature = "account_access_keys")]
             get_account_access_keys: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT active_access_keys FROM state_indexer.account_access_keys WHERE account_id = ? AND block_height <= ? LIMIT 1",
+                "get_account_access_keys",
+                &format!("SELECT active_access_keys FROM {state_indexer_keyspace}.account_access_keys WHERE account_id = ? AND block_height <= ? LIMIT 1"),
             ).await?,
 
             get_receipt: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT receipt_id, parent_transaction_hash, block_height, shard_id FROM tx_indexer.receipts_map WHERE receipt_id = ?",
+                "get_receipt",
+                &format!("SELECT receipt_id, parent_transaction_hash, block_height, shard_id FROM {tx_indexer_keyspace}.receipts_map WHERE receipt_id = ?"),
             ).await?,
 
             // Using LIMIT 1 here as transactions is expected to be ordered by block_height but we know about hash collisions
             // ref: https://github.com/near/near-indexer-for-explorer/issues/84
             get_transaction_by_hash: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT transaction_details FROM tx_indexer.transactions_details WHERE transaction_hash = ? LIMIT 1",
+                "get_transaction_by_hash",
+                &format!("SELECT transaction_details FROM {tx_indexer_keyspace}.transactions_details WHERE transaction_hash = ? LIMIT 1"),
             ).await?,
 
             get_stored_at_block_height_and_shard_id_by_block_height: Self::prepare_read_query(
                 &scylla_db_session,
-                "SELECT stored_at_block_height, shard_id FROM state_indexer.chunks WHERE block_height = ?",
+                "get_stored_at_block_height_and_shard_id_by_block_height",
+                &format!("SELECT stored_at_block_height, shard_id FROM {state_indexer_keyspace}.chunks WHERE block_height = ?"),
             ).await?,
+
+            get_block_state_changes: Self::prepare_read_query(
+                &scylla_db_session,
+                "get_block_state_changes",
+                &format!("SELECT changes_count, change_value FROM {state_indexer_keyspace}.state_changes_by_block WHERE block_height = ?"),
+            ).await?,
+
+            get_last_processed_block_height: Self::prepare_read_query(
+                &scylla_db_session,
+                "get_last_processed_block_height",
+                &format!("SELECT last_processed_block_height FROM {state_indexer_keyspace}.meta WHERE indexer_id = ?"),
+            ).await?,
+
+            get_legacy_state_keys: Self::prepare_read_query(
+                &scylla_db_session,
+                "get_legacy_state_keys",
+                &format!("SELECT data_key FROM {state_indexer_keyspace}.account_state WHERE account_id = ?"),
+            ).await?,
+
+            get_legacy_state_keys_by_prefix: Self::prepare_read_query(
+                &scylla_db_session,
+                "get_legacy_state_keys_by_prefix",
+                &format!("SELECT data_key FROM {state_indexer_keyspace}.account_state WHERE account_id = ? AND data_key >= ? AND data_key < ?"),
+            ).await?,
+
+            state_keys_backfilled: std::sync::atomic::AtomicBool::new(false),
         }))
     }
 }
 
 impl ScyllaDBManager {
+    /// Returns `None` if the code is not stored by the code hash
+    async fn get_code_by_hash(&self, code_hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let row = Self::execute_prepared_query(
+            &self.scylla_session,
+            &self.get_contract_code_by_hash,
+            (code_hash,),
+        )
+        .await?
+        .rows
+        .unwrap_or_default()
+        .into_typed::<(Vec<u8>,)>()
+        .next()
+        .transpose()?;
+        row.map(|(code,)| database::blob_compression::decompress(code))
+            .transpose()
+    }
+
+    /// Pages through the latest records of the keys in the versioned key index
+    /// and returns the hex-encoded keys with the flag telling if the key is deleted
+    async fn get_versioned_state_keys(
+        &self,
+        statement: &database::NamedStatement,
+        values: impl scylla::frame::value::ValueList,
+    ) -> anyhow::Result<std::collections::BTreeMap<String, bool>> {
+        let mut state_keys = std::collections::BTreeMap::new();
+        let mut rows_stream = self
+            .scylla_session
+            .execute_iter(statement.statement.clone(), values)
+            .await?
+            .into_typed::<(String, Option<bool>)>();
+        while let Some(row) = rows_stream.next().await {
+            let (data_key, deleted) = row?;
+            state_keys.insert(data_key, deleted.unwrap_or_default());
+        }
+        Ok(state_keys)
+    }
+
+    /// Pages through the keys of the legacy `account_state` index
+    /// unless they are copied into the versioned key index, returns the hex-encoded keys
+    async fn get_legacy_state_keys(
+        &self,
+        statement: &database::NamedStatement,
+        values: impl scylla::frame::value::ValueList,
+    ) -> anyhow::Result<Vec<String>> {
+        if self.state_keys_backfilled().await? {
+            return Ok(vec![]);
+        }
+        let mut state_keys = vec![];
+        let mut rows_stream = self
+            .scylla_session
+            .execute_iter(statement.statement.clone(), values)
+            .await?
+            .into_typed::<(String,)>();
+        while let Some(row) = rows_stream.next().await {
+            let (data_key,) = row?;
+            state_keys.push(data_key);
+        }
+        Ok(state_keys)
+    }
+
+    async fn state_keys_backfilled(&self) -> anyhow::Result<bool> {
+        if self
+            .state_keys_backfilled
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            return Ok(true);
+        }
+        let backfilled = self
+            .get_last_processed_block_height(database::migrations::STATE_KEYS_BACKFILL_MARKER)
+            .await?
+            .is_some();
+        if backfilled {
+            self.state_keys_backfilled
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+        Ok(backfilled)
+    }
+}
+
+#[async_trait::async_trait]
+impl ReaderStorage for ScyllaDBManager {
     /// Searches the block height by the given block hash
-    pub async fn get_block_by_hash(
+    async fn get_block_by_hash(
         &self,
         block_hash: near_primitives::hash::CryptoHash,
     ) -> anyhow::Result<u64> {
@@ -138,7 +485,7 @@ impl ScyllaDBManager {
     }
 
     /// Searches the block height and shard id by the given chunk hash
-    pub async fn get_block_by_chunk_hash(
+    async fn get_block_by_chunk_hash(
         &self,
         chunk_hash: near_primitives::hash::CryptoHash,
     ) -> anyhow::Result<BlockHeightShardId> {
@@ -162,47 +509,54 @@ impl ScyllaDBManager {
             })
     }
 
-    /// Returns all state keys for the given account id
-    pub async fn get_all_state_keys(
+    /// Returns the state keys of the given account id alive at the given block height
+    async fn get_all_state_keys(
         &self,
         account_id: &near_primitives::types::AccountId,
+        block_height: near_primitives::types::BlockHeight,
     ) -> anyhow::Result<Vec<StateKey>> {
-        let result = Self::execute_prepared_query(
-            &self.scylla_session,
-            &self.get_all_state_keys,
-            (account_id.to_string(),),
-        )
-        .await?
-        .rows_typed::<(String,)>()?
-        .filter_map(|row| row.ok().and_then(|(value,)| hex::decode(value).ok()));
-
-        Ok(result.collect())
+        let (versioned_state_keys, legacy_state_keys) = futures::try_join!(
+            self.get_versioned_state_keys(
+                &self.get_all_state_keys,
+                (
+                    account_id.to_string(),
+                    num_bigint::BigInt::from(block_height),
+                ),
+            ),
+            self.get_legacy_state_keys(&self.get_legacy_state_keys, (account_id.to_string(),)),
+        )?;
+        alive_state_keys(versioned_state_keys, legacy_state_keys)
     }
 
-    /// Returns state keys for the given account id filtered by the given prefix
-    pub async fn get_state_keys_by_prefix(
+    /// Returns the state keys of the given account id alive at the given block height
+    /// filtered by the given prefix
+    async fn get_state_keys_by_prefix(
         &self,
         account_id: &near_primitives::types::AccountId,
+        block_height: near_primitives::types::BlockHeight,
         prefix: &[u8],
     ) -> anyhow::Result<Vec<StateKey>> {
-        let hex_str_prefix = hex::encode(prefix);
-        let result = Self::execute_prepared_query(
-            &self.scylla_session,
-            &self.get_state_keys_by_prefix,
-            (
-                account_id.to_string(),
-                format!("{hex_str_prefix}%").to_string(),
+        let (start, end) = database::hex_prefix_range(prefix);
+        let (versioned_state_keys, legacy_state_keys) = futures::try_join!(
+            self.get_versioned_state_keys(
+                &self.get_state_keys_by_prefix,
+                (
+                    account_id.to_string(),
+                    &start,
+                    &end,
+                    num_bigint::BigInt::from(block_height),
+                ),
             ),
-        )
-        .await?
-        .rows_typed::<(String,)>()?
-        .filter_map(|row| row.ok().and_then(|(value,)| hex::decode(value).ok()));
-
-        Ok(result.collect())
+            self.get_legacy_state_keys(
+                &self.get_legacy_state_keys_by_prefix,
+                (account_id.to_string(), &start, &end),
+            ),
+        )?;
+        alive_state_keys(versioned_state_keys, legacy_state_keys)
     }
 
     /// Returns the state value for the given key of the given account at the given block height
-    pub async fn get_state_key_value(
+    async fn get_state_key_value(
         &self,
         account_id: &near_primitives::types::AccountId,
         block_height: near_primitives::types::BlockHeight,
@@ -220,11 +574,11 @@ impl ScyllaDBManager {
         .await?
         .single_row_typed::<(StateValue,)>()?;
 
-        Ok(result.0)
+        database::blob_compression::decompress(result.0)
     }
 
     /// Returns the near_primitives::account::Account at the given block height
-    pub async fn get_account(
+    async fn get_account(
         &self,
         account_id: &near_primitives::types::AccountId,
         request_block_height: near_primitives::types::BlockHeight,
@@ -251,12 +605,12 @@ impl ScyllaDBManager {
     }
 
     /// Returns the contract code at the given block height
-    pub async fn get_contract_code(
+    async fn get_contract_code(
         &self,
         account_id: &near_primitives::types::AccountId,
         request_block_height: near_primitives::types::BlockHeight,
     ) -> anyhow::Result<QueryData<Vec<u8>>> {
-        let (block_height, block_hash, contract_code) = Self::execute_prepared_query(
+        let (block_height, block_hash, contract_code, code_hash) = Self::execute_prepared_query(
             &self.scylla_session,
             &self.get_contract_code,
             (
@@ -266,9 +620,22 @@ impl ScyllaDBManager {
         )
         .await?
         .single_row()?
-        .into_typed::<(num_bigint::BigInt, String, Vec<u8>)>()?;
+        .into_typed::<(num_bigint::BigInt, String, Option<Vec<u8>>, Option<String>)>()?;
 
         let block = BlockRecord::try_from((block_hash, block_height))?;
+        // The code written before the code was stored by the code hash is kept in the history row
+        let contract_code = match (contract_code, code_hash) {
+            (_, Some(code_hash)) => self
+                .get_code_by_hash(&code_hash)
+                .await?
+                .ok_or_else(|| DataNotFoundError(format!("Contract code {}", code_hash)))?,
+            (Some(contract_code), None) => contract_code,
+            (None, None) => anyhow::bail!(
+                "Contract code of {} is deleted at block {}",
+                account_id,
+                block.height
+            ),
+        };
 
         Ok(QueryData {
             data: contract_code,
@@ -277,8 +644,26 @@ impl ScyllaDBManager {
         })
     }
 
+    /// Returns the contract code by the code hash,
+    /// falls back to the history of the account for the code stored before the code hash index
+    async fn get_contract_code_by_hash(
+        &self,
+        account_id: &near_primitives::types::AccountId,
+        request_block_height: near_primitives::types::BlockHeight,
+        code_hash: near_primitives::hash::CryptoHash,
+    ) -> anyhow::Result<Vec<u8>> {
+        // Only the missing code falls back, the failed query is reported
+        match self.get_code_by_hash(&code_hash.to_string()).await? {
+            Some(code) => Ok(code),
+            None => Ok(self
+                .get_contract_code(account_id, request_block_height)
+                .await?
+                .data),
+        }
+    }
+
     /// Returns the near_primitives::account::AccessKey at the given block height
-    pub async fn get_access_key(
+    async fn get_access_key(
         &self,
         account_id: &near_primitives::types::AccountId,
         request_block_height: near_primitives::types::BlockHeight,
@@ -308,12 +693,12 @@ impl ScyllaDBManager {
     }
 
     #[cfg(feature = "account_access_keys")]
-    pub async fn get_account_access_keys(
+    async fn get_account_access_keys(
         &self,
         account_id: &near_primitives::types::AccountId,
         block_height: near_primitives::types::BlockHeight,
-    ) -> anyhow::Result<scylla::frame::response::result::Row> {
-        let result = Self::execute_prepared_query(
+    ) -> anyhow::Result<std::collections::HashMap<String, Vec<u8>>> {
+        let (account_keys,) = Self::execute_prepared_query(
             &self.scylla_session,
             &self.get_account_access_keys,
             (
@@ -322,12 +707,13 @@ impl ScyllaDBManager {
             ),
         )
         .await?
-        .single_row()?;
-        Ok(result)
+        .single_row()?
+        .into_typed::<(std::collections::HashMap<String, Vec<u8>>,)>()?;
+        Ok(account_keys)
     }
 
     /// Returns the near_primitives::views::ReceiptView at the given receipt_id
-    pub async fn get_receipt_by_id(
+    async fn get_receipt_by_id(
         &self,
         receipt_id: near_primitives::hash::CryptoHash,
     ) -> anyhow::Result<ReceiptRecord> {
@@ -344,7 +730,7 @@ impl ScyllaDBManager {
     }
 
     /// Returns the readnode_primitives::TransactionDetails at the given transaction hash
-    pub async fn get_transaction_by_hash(
+    async fn get_transaction_by_hash(
         &self,
         transaction_hash: &str,
     ) -> anyhow::Result<readnode_primitives::TransactionDetails> {
@@ -358,12 +744,12 @@ impl ScyllaDBManager {
         .into_typed::<(Vec<u8>,)>()?;
 
         Ok(readnode_primitives::TransactionDetails::try_from_slice(
-            &data_value,
+            &database::blob_compression::decompress(data_value)?,
         )?)
     }
 
     /// Returns the block height and shard id by the given block height
-    pub async fn get_block_by_height_and_shard_id(
+    async fn get_block_by_height_and_shard_id(
         &self,
         block_height: near_primitives::types::BlockHeight,
         shard_id: near_primitives::types::ShardId,
@@ -391,6 +777,67 @@ impl ScyllaDBManager {
                 ))
             })
     }
+
+    /// Returns all the state changes of the block in the order they were applied
+    /// Fails if the block is not indexed yet or its changes are stored partially
+    async fn get_block_state_changes(
+        &self,
+        block_height: near_primitives::types::BlockHeight,
+    ) -> anyhow::Result<Vec<readnode_primitives::StoredStateChange>> {
+        let rows = Self::execute_prepared_query(
+            &self.scylla_session,
+            &self.get_block_state_changes,
+            (num_bigint::BigInt::from(block_height),),
+        )
+        .await?
+        .rows()?;
+
+        let mut changes_count = None;
+        let mut state_changes = vec![];
+        for row in rows.into_typed::<(Option<i32>, Option<Vec<u8>>)>() {
+            let (count, change_value) = row?;
+            changes_count = changes_count.or(count);
+            // The partition of the block without state changes consists of the static columns only
+            if let Some(change_value) = change_value {
+                state_changes.push(serde_json::from_slice(&change_value)?);
+            }
+        }
+
+        match changes_count {
+            Some(count) if usize::try_from(count)? == state_changes.len() => Ok(state_changes),
+            Some(count) => Err(anyhow::anyhow!(
+                "State changes of block {} are stored partially: {} of {}",
+                block_height,
+                state_changes.len(),
+                count
+            )),
+            None => {
+                Err(DataNotFoundError(format!("State changes of block {}", block_height)).into())
+            }
+        }
+    }
+
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<near_primitives::types::BlockHeight>> {
+        let rows = Self::execute_prepared_query(
+            &self.scylla_session,
+            &self.get_last_processed_block_height,
+            (indexer_id,),
+        )
+        .await?
+        .rows()?;
+        rows.into_typed::<(num_bigint::BigInt,)>()
+            .next()
+            .map(|row| {
+                let (block_height,) = row?;
+                block_height
+                    .to_u64()
+                    .ok_or_else(|| anyhow::anyhow!("Failed to parse `last_processed_block_height`"))
+            })
+            .transpose()
+    }
 }
 
 // TryFrom impls for defined types
@@ -491,3 +938,19 @@ impl TryFrom<(String, num_bigint::BigInt)> for BlockRecord {
         Ok(BlockRecord { height, hash })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alive_state_keys_merges_the_legacy_keys() {
+        let versioned_state_keys =
+            std::collections::BTreeMap::from([(hex::encode("b"), false), (hex::encode("c"), true)]);
+        let legacy_state_keys = vec![hex::encode("a"), hex::encode("b"), hex::encode("c")];
+        assert_eq!(
+            alive_state_keys(versioned_state_keys, legacy_state_keys).unwrap(),
+            vec![b"a".to_vec(), b"b".to_vec()]
+        );
+    }
+}
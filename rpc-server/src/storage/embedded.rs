@@ -0,0 +1,285 @@
+use std::str::FromStr;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use database::embedded::RocksDBManager;
+use database::VersionedRecord;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
+
+use super::{
+    parse_block_hash, query_data, BlockHeightShardId, DataNotFoundError, QueryData, ReaderStorage,
+    ReceiptRecord, StateKey, StateValue,
+};
+
+/// Reads the data from the embedded RocksDB databases of the state-indexer and the tx-indexer.
+/// The databases are opened as a secondary instance, so the new data becomes visible
+/// only after `catch_up_with_primary` is called
+pub struct EmbeddedStorage {
+    state: RocksDBManager,
+    tx: RocksDBManager,
+}
+
+impl EmbeddedStorage {
+    pub fn open(rocksdb_path: &std::path::Path) -> anyhow::Result<Self> {
+        // Every process opening the database as a secondary instance needs its own directory
+        let secondary_path = std::env::temp_dir().join(format!("read-rpc-{}", std::process::id()));
+        Ok(Self {
+            state: RocksDBManager::open_secondary(
+                &rocksdb_path.join(database::embedded::STATE_INDEXER_DIR),
+                &secondary_path.join(database::embedded::STATE_INDEXER_DIR),
+                &database::embedded::STATE_INDEXER_COLUMN_FAMILIES,
+            )?,
+            tx: RocksDBManager::open_secondary(
+                &rocksdb_path.join(database::embedded::TX_INDEXER_DIR),
+                &secondary_path.join(database::embedded::TX_INDEXER_DIR),
+                &database::embedded::TX_INDEXER_COLUMN_FAMILIES,
+            )?,
+        })
+    }
+
+    /// Makes the data written by the indexers since the last call visible to the server
+    pub async fn catch_up_with_primary(&self) -> anyhow::Result<()> {
+        self.state
+            .run_blocking(|db| db.catch_up_with_primary())
+            .await?;
+        self.tx.run_blocking(|db| db.catch_up_with_primary()).await
+    }
+}
+
+#[async_trait::async_trait]
+impl ReaderStorage for EmbeddedStorage {
+    async fn get_block_by_hash(&self, block_hash: CryptoHash) -> anyhow::Result<u64> {
+        self.state
+            .run_blocking(move |db| db.get_block_height_by_hash(&block_hash.to_string()))
+            .await?
+            .ok_or_else(|| DataNotFoundError(format!("Block {}", block_hash)).into())
+    }
+
+    async fn get_block_by_chunk_hash(
+        &self,
+        chunk_hash: CryptoHash,
+    ) -> anyhow::Result<BlockHeightShardId> {
+        self.state
+            .run_blocking(move |db| db.get_chunk(&chunk_hash.to_string()))
+            .await?
+            .map(|(block_height, shard_id)| BlockHeightShardId(block_height, shard_id))
+            .ok_or_else(|| DataNotFoundError(format!("Chunk {}", chunk_hash)).into())
+    }
+
+    async fn get_all_state_keys(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<Vec<StateKey>> {
+        self.get_state_keys_by_prefix(account_id, block_height, &[])
+            .await
+    }
+
+    async fn get_state_keys_by_prefix(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        prefix: &[u8],
+    ) -> anyhow::Result<Vec<StateKey>> {
+        let (account_id, prefix) = (account_id.clone(), prefix.to_vec());
+        self.state
+            .run_blocking(move |db| {
+                db.get_state_keys_by_prefix(account_id.as_ref(), block_height, &prefix)
+            })
+            .await
+    }
+
+    async fn get_state_key_value(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        key_data: StateKey,
+    ) -> anyhow::Result<StateValue> {
+        let record = {
+            let (account_id, key_data) = (account_id.clone(), key_data.clone());
+            self.state
+                .run_blocking(move |db| {
+                    db.get_state_value(account_id.as_ref(), block_height, &key_data)
+                })
+                .await?
+        };
+        match record {
+            Some(VersionedRecord {
+                data: Some(value), ..
+            }) => Ok(value),
+            _ => Err(DataNotFoundError(format!(
+                "State key {} of {} at block {}",
+                hex::encode(&key_data),
+                account_id,
+                block_height
+            ))
+            .into()),
+        }
+    }
+
+    async fn get_account(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+    ) -> anyhow::Result<QueryData<near_primitives::account::Account>> {
+        let record = {
+            let account_id = account_id.clone();
+            self.state
+                .run_blocking(move |db| db.get_account(account_id.as_ref(), request_block_height))
+                .await?
+        };
+        query_data(record, || {
+            format!("Account {} at block {}", account_id, request_block_height)
+        })
+    }
+
+    async fn get_contract_code(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+    ) -> anyhow::Result<QueryData<Vec<u8>>> {
+        let record = {
+            let account_id = account_id.clone();
+            self.state
+                .run_blocking(move |db| {
+                    db.get_contract_code(account_id.as_ref(), request_block_height)
+                })
+                .await?
+        };
+        match record {
+            // The code is stored as is, not borsh-serialized
+            Some(VersionedRecord {
+                block_height,
+                block_hash,
+                data: Some(code),
+            }) => Ok(QueryData {
+                data: code,
+                block_height,
+                block_hash: parse_block_hash(&block_hash)?,
+            }),
+            _ => Err(DataNotFoundError(format!(
+                "Contract code of {} at block {}",
+                account_id, request_block_height
+            ))
+            .into()),
+        }
+    }
+
+    async fn get_access_key(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+        public_key: near_crypto::PublicKey,
+    ) -> anyhow::Result<QueryData<near_primitives::account::AccessKey>> {
+        let record = {
+            let (account_id, public_key) = (account_id.clone(), public_key.try_to_vec()?);
+            self.state
+                .run_blocking(move |db| {
+                    db.get_access_key(account_id.as_ref(), request_block_height, &public_key)
+                })
+                .await?
+        };
+        query_data(record, || {
+            format!(
+                "Access key {} of {} at block {}",
+                public_key, account_id, request_block_height
+            )
+        })
+    }
+
+    #[cfg(feature = "account_access_keys")]
+    async fn get_account_access_keys(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<std::collections::HashMap<String, Vec<u8>>> {
+        let account_id = account_id.clone();
+        Ok(self
+            .state
+            .run_blocking(move |db| db.get_access_keys(account_id.as_ref(), block_height))
+            .await?
+            .into_iter()
+            .map(|(public_key, access_key)| (hex::encode(public_key), access_key))
+            .collect())
+    }
+
+    async fn get_receipt_by_id(&self, receipt_id: CryptoHash) -> anyhow::Result<ReceiptRecord> {
+        let (parent_transaction_hash, block_height, shard_id) = self
+            .tx
+            .run_blocking(move |db| db.get_receipt(&receipt_id.to_string()))
+            .await?
+            .ok_or_else(|| DataNotFoundError(format!("Receipt {}", receipt_id)))?;
+        Ok(ReceiptRecord {
+            receipt_id,
+            parent_transaction_hash: CryptoHash::from_str(&parent_transaction_hash).map_err(
+                |err| {
+                    anyhow::anyhow!(
+                        "Failed to parse `parent_transaction_hash` to CryptoHash: {}",
+                        err
+                    )
+                },
+            )?,
+            block_height,
+            shard_id,
+        })
+    }
+
+    async fn get_transaction_by_hash(
+        &self,
+        transaction_hash: &str,
+    ) -> anyhow::Result<readnode_primitives::TransactionDetails> {
+        let transaction_details = {
+            let transaction_hash = transaction_hash.to_string();
+            self.tx
+                .run_blocking(move |db| db.get_transaction(&transaction_hash))
+                .await?
+        }
+        .ok_or_else(|| DataNotFoundError(format!("Transaction {}", transaction_hash)))?;
+        Ok(readnode_primitives::TransactionDetails::try_from_slice(
+            &transaction_details,
+        )?)
+    }
+
+    async fn get_block_by_height_and_shard_id(
+        &self,
+        block_height: BlockHeight,
+        shard_id: ShardId,
+    ) -> anyhow::Result<BlockHeightShardId> {
+        self.state
+            .run_blocking(move |db| db.get_chunk_stored_at_block_height(block_height, shard_id))
+            .await?
+            .map(|stored_at_block_height| BlockHeightShardId(stored_at_block_height, shard_id))
+            .ok_or_else(|| {
+                DataNotFoundError(format!(
+                    "Block height {} and shard id {}",
+                    block_height, shard_id
+                ))
+                .into()
+            })
+    }
+
+    async fn get_block_state_changes(
+        &self,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<Vec<readnode_primitives::StoredStateChange>> {
+        // The state changes of the block are written at once, so they are never stored partially
+        self.state
+            .run_blocking(move |db| db.get_block_state_changes(block_height))
+            .await?
+            .ok_or_else(|| DataNotFoundError(format!("State changes of block {}", block_height)))?
+            .iter()
+            .map(|state_change| Ok(serde_json::from_slice(state_change)?))
+            .collect()
+    }
+
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<BlockHeight>> {
+        let indexer_id = indexer_id.to_string();
+        self.state
+            .run_blocking(move |db| db.get_last_processed_block_height(&indexer_id))
+            .await
+    }
+}
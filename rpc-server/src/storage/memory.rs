@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, HashMap};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
+
+use super::{
+    BlockHeightShardId, DataNotFoundError, QueryData, ReaderStorage, ReceiptRecord, StateKey,
+    StateValue,
+};
+
+/// The history of the value by block height.
+/// `None` means the value was deleted at that block
+type History<T> = BTreeMap<BlockHeight, (CryptoHash, Option<T>)>;
+
+#[derive(Default)]
+struct Data {
+    blocks: HashMap<CryptoHash, BlockHeight>,
+    chunks_by_hash: HashMap<CryptoHash, (BlockHeight, ShardId)>,
+    chunks_by_block: HashMap<(BlockHeight, ShardId), BlockHeight>,
+    state: BTreeMap<(AccountId, StateKey), History<StateValue>>,
+    contracts: HashMap<AccountId, History<Vec<u8>>>,
+    // Serialized the same way the state-indexer stores them, since the views are not `Clone`
+    block_state_changes: HashMap<BlockHeight, Vec<Vec<u8>>>,
+    last_processed_block_heights: HashMap<String, BlockHeight>,
+}
+
+/// Keeps the data in memory instead of the database.
+/// Allows to run the handlers against the prepared data without the ScyllaDB cluster.
+/// Only the data the handler tests need can be put, the accounts, access keys,
+/// receipts and transactions are never found
+#[derive(Default)]
+pub struct InMemoryStorage {
+    data: std::sync::RwLock<Data>,
+}
+
+/// Returns the latest value stored at or before the given block height
+fn latest_value<T>(
+    history: Option<&History<T>>,
+    block_height: BlockHeight,
+    description: impl FnOnce() -> String,
+) -> anyhow::Result<QueryData<T>>
+where
+    T: borsh::BorshDeserialize + Clone,
+{
+    match history.and_then(|history| history.range(..=block_height).next_back()) {
+        Some((height, (hash, Some(value)))) => Ok(QueryData {
+            data: value.clone(),
+            block_height: *height,
+            block_hash: *hash,
+        }),
+        _ => Err(DataNotFoundError(description()).into()),
+    }
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put_block(&self, block_hash: CryptoHash, block_height: BlockHeight) {
+        self.data
+            .write()
+            .unwrap()
+            .blocks
+            .insert(block_hash, block_height);
+    }
+
+    pub fn put_chunk(
+        &self,
+        chunk_hash: CryptoHash,
+        block_height: BlockHeight,
+        shard_id: ShardId,
+        stored_at_block_height: BlockHeight,
+    ) {
+        let mut data = self.data.write().unwrap();
+        data.chunks_by_hash
+            .insert(chunk_hash, (stored_at_block_height, shard_id));
+        data.chunks_by_block
+            .insert((block_height, shard_id), stored_at_block_height);
+    }
+
+    /// Stores the state value of the account, `None` deletes the key
+    pub fn put_state_value(
+        &self,
+        account_id: AccountId,
+        block_height: BlockHeight,
+        block_hash: CryptoHash,
+        key: StateKey,
+        value: Option<StateValue>,
+    ) {
+        self.data
+            .write()
+            .unwrap()
+            .state
+            .entry((account_id, key))
+            .or_default()
+            .insert(block_height, (block_hash, value));
+    }
+
+    /// Stores the contract code, `None` deletes the contract
+    pub fn put_contract_code(
+        &self,
+        account_id: AccountId,
+        block_height: BlockHeight,
+        block_hash: CryptoHash,
+        code: Option<Vec<u8>>,
+    ) {
+        self.data
+            .write()
+            .unwrap()
+            .contracts
+            .entry(account_id)
+            .or_default()
+            .insert(block_height, (block_hash, code));
+    }
+
+    pub fn put_block_state_changes(
+        &self,
+        block_height: BlockHeight,
+        state_changes: &[near_primitives::views::StateChangeWithCauseView],
+    ) -> anyhow::Result<()> {
+        let state_changes = state_changes
+            .iter()
+            .map(|state_change| {
+                serde_json::to_vec(&readnode_primitives::StoredStateChange::new(state_change))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.data
+            .write()
+            .unwrap()
+            .block_state_changes
+            .insert(block_height, state_changes);
+        Ok(())
+    }
+
+    pub fn update_meta(&self, indexer_id: &str, block_height: BlockHeight) {
+        self.data
+            .write()
+            .unwrap()
+            .last_processed_block_heights
+            .insert(indexer_id.to_string(), block_height);
+    }
+}
+
+#[async_trait::async_trait]
+impl ReaderStorage for InMemoryStorage {
+    async fn get_block_by_hash(&self, block_hash: CryptoHash) -> anyhow::Result<u64> {
+        self.data
+            .read()
+            .unwrap()
+            .blocks
+            .get(&block_hash)
+            .copied()
+            .ok_or_else(|| DataNotFoundError(format!("Block {}", block_hash)).into())
+    }
+
+    async fn get_block_by_chunk_hash(
+        &self,
+        chunk_hash: CryptoHash,
+    ) -> anyhow::Result<BlockHeightShardId> {
+        self.data
+            .read()
+            .unwrap()
+            .chunks_by_hash
+            .get(&chunk_hash)
+            .map(|(block_height, shard_id)| BlockHeightShardId(*block_height, *shard_id))
+            .ok_or_else(|| DataNotFoundError(format!("Chunk {}", chunk_hash)).into())
+    }
+
+    async fn get_all_state_keys(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<Vec<StateKey>> {
+        self.get_state_keys_by_prefix(account_id, block_height, &[])
+            .await
+    }
+
+    async fn get_state_keys_by_prefix(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        prefix: &[u8],
+    ) -> anyhow::Result<Vec<StateKey>> {
+        Ok(self
+            .data
+            .read()
+            .unwrap()
+            .state
+            .iter()
+            .filter(|((key_account_id, key), history)| {
+                key_account_id == account_id
+                    && key.starts_with(prefix)
+                    && matches!(
+                        history.range(..=block_height).next_back(),
+                        Some((_, (_, Some(_))))
+                    )
+            })
+            .map(|((_, key), _)| key.clone())
+            .collect())
+    }
+
+    async fn get_state_key_value(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        key_data: StateKey,
+    ) -> anyhow::Result<StateValue> {
+        let data = self.data.read().unwrap();
+        let history = data.state.get(&(account_id.clone(), key_data.clone()));
+        latest_value(history, block_height, || {
+            format!(
+                "State key {} of {} at block {}",
+                hex::encode(&key_data),
+                account_id,
+                block_height
+            )
+        })
+        .map(|value| value.data)
+    }
+
+    async fn get_account(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+    ) -> anyhow::Result<QueryData<near_primitives::account::Account>> {
+        Err(DataNotFoundError(format!(
+            "Account {} at block {}",
+            account_id, request_block_height
+        ))
+        .into())
+    }
+
+    async fn get_contract_code(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+    ) -> anyhow::Result<QueryData<Vec<u8>>> {
+        let data = self.data.read().unwrap();
+        latest_value(data.contracts.get(account_id), request_block_height, || {
+            format!(
+                "Contract code of {} at block {}",
+                account_id, request_block_height
+            )
+        })
+    }
+
+    async fn get_access_key(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+        public_key: near_crypto::PublicKey,
+    ) -> anyhow::Result<QueryData<near_primitives::account::AccessKey>> {
+        Err(DataNotFoundError(format!(
+            "Access key {} of {} at block {}",
+            public_key, account_id, request_block_height
+        ))
+        .into())
+    }
+
+    #[cfg(feature = "account_access_keys")]
+    async fn get_account_access_keys(
+        &self,
+        _account_id: &AccountId,
+        _block_height: BlockHeight,
+    ) -> anyhow::Result<HashMap<String, Vec<u8>>> {
+        Ok(HashMap::new())
+    }
+
+    async fn get_receipt_by_id(&self, receipt_id: CryptoHash) -> anyhow::Result<ReceiptRecord> {
+        Err(DataNotFoundError(format!("Receipt {}", receipt_id)).into())
+    }
+
+    async fn get_transaction_by_hash(
+        &self,
+        transaction_hash: &str,
+    ) -> anyhow::Result<readnode_primitives::TransactionDetails> {
+        Err(DataNotFoundError(format!("Transaction {}", transaction_hash)).into())
+    }
+
+    async fn get_block_by_height_and_shard_id(
+        &self,
+        block_height: BlockHeight,
+        shard_id: ShardId,
+    ) -> anyhow::Result<BlockHeightShardId> {
+        self.data
+            .read()
+            .unwrap()
+            .chunks_by_block
+            .get(&(block_height, shard_id))
+            .map(|stored_at_block_height| BlockHeightShardId(*stored_at_block_height, shard_id))
+            .ok_or_else(|| {
+                DataNotFoundError(format!(
+                    "Block height {} and shard id {}",
+                    block_height, shard_id
+                ))
+                .into()
+            })
+    }
+
+    async fn get_block_state_changes(
+        &self,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<Vec<readnode_primitives::StoredStateChange>> {
+        let data = self.data.read().unwrap();
+        let state_changes = data
+            .block_state_changes
+            .get(&block_height)
+            .ok_or_else(|| DataNotFoundError(format!("State changes of block {}", block_height)))?;
+        state_changes
+            .iter()
+            .map(|state_change| Ok(serde_json::from_slice(state_change)?))
+            .collect()
+    }
+
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<BlockHeight>> {
+        Ok(self
+            .data
+            .read()
+            .unwrap()
+            .last_processed_block_heights
+            .get(indexer_id)
+            .copied())
+    }
+}
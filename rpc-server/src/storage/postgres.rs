@@ -0,0 +1,226 @@
+use std::str::FromStr;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use database::postgres::PostgresDBManager;
+use database::VersionedRecord;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, ShardId};
+
+use super::{
+    parse_block_hash, query_data, BlockHeightShardId, DataNotFoundError, QueryData, ReaderStorage,
+    ReceiptRecord, StateKey, StateValue,
+};
+
+/// Reads the data written by the state-indexer and the tx-indexer to the PostgreSQL database
+#[async_trait::async_trait]
+impl ReaderStorage for PostgresDBManager {
+    async fn get_block_by_hash(&self, block_hash: CryptoHash) -> anyhow::Result<u64> {
+        self.get_block_height_by_hash(&block_hash.to_string())
+            .await?
+            .ok_or_else(|| DataNotFoundError(format!("Block {}", block_hash)).into())
+    }
+
+    async fn get_block_by_chunk_hash(
+        &self,
+        chunk_hash: CryptoHash,
+    ) -> anyhow::Result<BlockHeightShardId> {
+        self.get_chunk(&chunk_hash.to_string())
+            .await?
+            .map(|(block_height, shard_id)| BlockHeightShardId(block_height, shard_id))
+            .ok_or_else(|| DataNotFoundError(format!("Chunk {}", chunk_hash)).into())
+    }
+
+    async fn get_all_state_keys(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<Vec<StateKey>> {
+        PostgresDBManager::get_state_keys_by_prefix(self, account_id.as_ref(), block_height, &[])
+            .await
+    }
+
+    async fn get_state_keys_by_prefix(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        prefix: &[u8],
+    ) -> anyhow::Result<Vec<StateKey>> {
+        PostgresDBManager::get_state_keys_by_prefix(self, account_id.as_ref(), block_height, prefix)
+            .await
+    }
+
+    async fn get_state_key_value(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        key_data: StateKey,
+    ) -> anyhow::Result<StateValue> {
+        match self
+            .get_state_value(account_id.as_ref(), block_height, &key_data)
+            .await?
+        {
+            Some(VersionedRecord {
+                data: Some(value), ..
+            }) => Ok(value),
+            _ => Err(DataNotFoundError(format!(
+                "State key {} of {} at block {}",
+                hex::encode(&key_data),
+                account_id,
+                block_height
+            ))
+            .into()),
+        }
+    }
+
+    async fn get_account(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+    ) -> anyhow::Result<QueryData<near_primitives::account::Account>> {
+        query_data(
+            PostgresDBManager::get_account(self, account_id.as_ref(), request_block_height).await?,
+            || format!("Account {} at block {}", account_id, request_block_height),
+        )
+    }
+
+    async fn get_contract_code(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+    ) -> anyhow::Result<QueryData<Vec<u8>>> {
+        let record =
+            PostgresDBManager::get_contract_code(self, account_id.as_ref(), request_block_height)
+                .await?;
+        match record {
+            // The code is stored as is, not borsh-serialized
+            Some(VersionedRecord {
+                block_height,
+                block_hash,
+                data: Some(code),
+            }) => Ok(QueryData {
+                data: code,
+                block_height,
+                block_hash: parse_block_hash(&block_hash)?,
+            }),
+            _ => Err(DataNotFoundError(format!(
+                "Contract code of {} at block {}",
+                account_id, request_block_height
+            ))
+            .into()),
+        }
+    }
+
+    async fn get_access_key(
+        &self,
+        account_id: &AccountId,
+        request_block_height: BlockHeight,
+        public_key: near_crypto::PublicKey,
+    ) -> anyhow::Result<QueryData<near_primitives::account::AccessKey>> {
+        query_data(
+            PostgresDBManager::get_access_key(
+                self,
+                account_id.as_ref(),
+                request_block_height,
+                &public_key.try_to_vec()?,
+            )
+            .await?,
+            || {
+                format!(
+                    "Access key {} of {} at block {}",
+                    public_key, account_id, request_block_height
+                )
+            },
+        )
+    }
+
+    #[cfg(feature = "account_access_keys")]
+    async fn get_account_access_keys(
+        &self,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<std::collections::HashMap<String, Vec<u8>>> {
+        Ok(self
+            .get_access_keys(account_id.as_ref(), block_height)
+            .await?
+            .into_iter()
+            .map(|(public_key, access_key)| (hex::encode(public_key), access_key))
+            .collect())
+    }
+
+    async fn get_receipt_by_id(&self, receipt_id: CryptoHash) -> anyhow::Result<ReceiptRecord> {
+        let (parent_transaction_hash, block_height, shard_id) = self
+            .get_receipt(&receipt_id.to_string())
+            .await?
+            .ok_or_else(|| DataNotFoundError(format!("Receipt {}", receipt_id)))?;
+        Ok(ReceiptRecord {
+            receipt_id,
+            parent_transaction_hash: CryptoHash::from_str(&parent_transaction_hash).map_err(
+                |err| {
+                    anyhow::anyhow!(
+                        "Failed to parse `parent_transaction_hash` to CryptoHash: {}",
+                        err
+                    )
+                },
+            )?,
+            block_height,
+            shard_id,
+        })
+    }
+
+    async fn get_transaction_by_hash(
+        &self,
+        transaction_hash: &str,
+    ) -> anyhow::Result<readnode_primitives::TransactionDetails> {
+        let transaction_details = self
+            .get_transaction(transaction_hash)
+            .await?
+            .ok_or_else(|| DataNotFoundError(format!("Transaction {}", transaction_hash)))?;
+        Ok(readnode_primitives::TransactionDetails::try_from_slice(
+            &transaction_details,
+        )?)
+    }
+
+    async fn get_block_by_height_and_shard_id(
+        &self,
+        block_height: BlockHeight,
+        shard_id: ShardId,
+    ) -> anyhow::Result<BlockHeightShardId> {
+        self.get_chunk_stored_at_block_height(block_height, shard_id)
+            .await?
+            .map(|stored_at_block_height| BlockHeightShardId(stored_at_block_height, shard_id))
+            .ok_or_else(|| {
+                DataNotFoundError(format!(
+                    "Block height {} and shard id {}",
+                    block_height, shard_id
+                ))
+                .into()
+            })
+    }
+
+    async fn get_block_state_changes(
+        &self,
+        block_height: BlockHeight,
+    ) -> anyhow::Result<Vec<readnode_primitives::StoredStateChange>> {
+        // The state changes of the block are written in a single statement,
+        // so they are never stored partially
+        PostgresDBManager::get_block_state_changes(self, block_height)
+            .await?
+            .ok_or_else(|| DataNotFoundError(format!("State changes of block {}", block_height)))?
+            .iter()
+            .map(|state_change| Ok(serde_json::from_slice(state_change)?))
+            .collect()
+    }
+
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<BlockHeight>> {
+        PostgresDBManager::get_last_processed_block_height(
+            self,
+            database::postgres::STATE_INDEXER_SCHEMA,
+            indexer_id,
+        )
+        .await
+    }
+}
@@ -54,6 +54,8 @@ async fn handle_streamer_message(
     streamer_message: near_indexer_primitives::StreamerMessage,
     blocks_cache: std::sync::Arc<std::sync::RwLock<crate::cache::LruMemoryCache<u64, CacheBlock>>>,
     final_block_height: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    negative_cache: std::sync::Arc<std::sync::RwLock<crate::cache::NegativeCache>>,
+    block_data_cache: std::sync::Arc<crate::cache::BlockDataCache>,
 ) -> anyhow::Result<()> {
     let block = CacheBlock {
         block_hash: streamer_message.block.header.hash,
@@ -63,25 +65,91 @@ async fn handle_streamer_message(
         chunks_included: streamer_message.block.header.chunks_included,
         state_root: streamer_message.block.header.prev_state_root,
     };
-    final_block_height.store(block.block_height, std::sync::atomic::Ordering::SeqCst);
+    // Invalidate the negative cache entries touched by the block and update the final block height
+    // while holding the lock to not let the requests remember a miss for the outdated final block
+    {
+        let mut negative_cache = negative_cache.write().unwrap();
+        for state_change in streamer_message
+            .shards
+            .iter()
+            .flat_map(|shard| shard.state_changes.iter())
+        {
+            match &state_change.value {
+                near_primitives::views::StateChangeValueView::AccountUpdate {
+                    account_id, ..
+                } => negative_cache
+                    .invalidate(&crate::cache::NegativeCacheKey::Account(account_id.clone())),
+                near_primitives::views::StateChangeValueView::AccessKeyUpdate {
+                    account_id,
+                    public_key,
+                    ..
+                } => negative_cache.invalidate(&crate::cache::NegativeCacheKey::AccessKey(
+                    account_id.clone(),
+                    public_key.clone(),
+                )),
+                _ => {}
+            }
+        }
+        final_block_height.store(block.block_height, std::sync::atomic::Ordering::SeqCst);
+    }
     blocks_cache.write().unwrap().put(block.block_height, block);
+    // Keep the recent blocks data to serve `block`, `chunk` and `EXPERIMENTAL_changes*`
+    // without going to S3
+    // The disk writes are spawned, so the stream is not held up by the disk
+    block_data_cache.put_block_view(block.block_height, &streamer_message.block);
+    for shard in &streamer_message.shards {
+        block_data_cache.put_shard(block.block_height, shard);
+    }
     crate::metrics::FINAL_BLOCK_HEIGHT.set(i64::try_from(block.block_height)?);
     Ok(())
 }
 
+/// Keeps the last block height persisted by the state-indexer up to date
+pub async fn update_indexed_block_height_regularly(
+    db_manager: std::sync::Arc<dyn crate::storage::ReaderStorage>,
+    state_indexer_id: String,
+    indexed_block_height: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    interval: std::time::Duration,
+) {
+    loop {
+        match db_manager
+            .get_last_processed_block_height(&state_indexer_id)
+            .await
+        {
+            Ok(Some(block_height)) => {
+                indexed_block_height.fetch_max(block_height, std::sync::atomic::Ordering::SeqCst);
+            }
+            Ok(None) => tracing::warn!(
+                "The progress of the state-indexer `{}` is not found",
+                state_indexer_id
+            ),
+            Err(err) => tracing::warn!(
+                "Failed to read the progress of the state-indexer `{}`: {:?}",
+                state_indexer_id,
+                err
+            ),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
 pub async fn update_final_block_height_regularly(
     final_block_height: std::sync::Arc<std::sync::atomic::AtomicU64>,
     blocks_cache: std::sync::Arc<std::sync::RwLock<crate::cache::LruMemoryCache<u64, CacheBlock>>>,
-    lake_config: near_lake_framework::LakeConfig,
+    negative_cache: std::sync::Arc<std::sync::RwLock<crate::cache::NegativeCache>>,
+    block_data_cache: std::sync::Arc<crate::cache::BlockDataCache>,
+    lake_config: block_source::BlockSourceConfig,
 ) -> anyhow::Result<()> {
     tracing::info!("Task to get and store final block in the cache started");
-    let (sender, stream) = near_lake_framework::streamer(lake_config);
+    let (sender, stream) = block_source::streamer(lake_config);
     let mut handlers = tokio_stream::wrappers::ReceiverStream::new(stream)
         .map(|streamer_message| {
             handle_streamer_message(
                 streamer_message,
                 std::sync::Arc::clone(&blocks_cache),
                 std::sync::Arc::clone(&final_block_height),
+                std::sync::Arc::clone(&negative_cache),
+                std::sync::Arc::clone(&block_data_cache),
             )
         })
         .buffer_unordered(1usize);
@@ -466,3 +534,38 @@ macro_rules! capture_shadow_consistency_error {
 }
 #[cfg(feature = "shadow_data_consistency")]
 pub(crate) use capture_shadow_consistency_error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+
+    #[tokio::test]
+    async fn indexed_block_height_follows_the_state_indexer_progress() {
+        let storage = std::sync::Arc::new(InMemoryStorage::new());
+        let indexed_block_height = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(5));
+        let handle = tokio::spawn(update_indexed_block_height_regularly(
+            storage.clone(),
+            "state-indexer".to_string(),
+            indexed_block_height.clone(),
+            std::time::Duration::from_millis(10),
+        ));
+
+        storage.update_meta("other-indexer", 20);
+        storage.update_meta("state-indexer", 10);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(
+            indexed_block_height.load(std::sync::atomic::Ordering::SeqCst),
+            10
+        );
+
+        // The persisted progress never moves the known indexed block height back
+        storage.update_meta("state-indexer", 7);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(
+            indexed_block_height.load(std::sync::atomic::Ordering::SeqCst),
+            10
+        );
+        handle.abort();
+    }
+}
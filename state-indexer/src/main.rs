@@ -10,6 +10,9 @@ use near_primitives_core::account::Account;
 
 mod configs;
 mod metrics;
+mod progress;
+mod state_records;
+mod storage;
 
 #[macro_use]
 extern crate lazy_static;
@@ -19,13 +22,15 @@ pub(crate) const INDEXER: &str = "state_indexer";
 
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(streamer_message, scylla_storage, indexer_id))
+    tracing::instrument(skip(streamer_message, db_manager, indexer_id, progress_watermark, account_filter))
 )]
 async fn handle_streamer_message(
-    streamer_message: near_indexer_primitives::StreamerMessage,
-    scylla_storage: &configs::ScyllaDBManager,
+    mut streamer_message: near_indexer_primitives::StreamerMessage,
+    db_manager: &dyn storage::StateIndexerStorage,
     indexer_id: &str,
     stats: std::sync::Arc<tokio::sync::RwLock<metrics::Stats>>,
+    progress_watermark: &progress::ProgressWatermark,
+    account_filter: &database::account_filter::AccountFilterArgs,
 ) -> anyhow::Result<()> {
     let block_height = streamer_message.block.header.height;
     let block_hash = streamer_message.block.header.hash;
@@ -33,6 +38,17 @@ async fn handle_streamer_message(
 
     stats.write().await.block_heights_processing.insert(block_height);
 
+    // Dropping the changes of the accounts which are not indexed before anything is stored
+    if !account_filter.is_empty() {
+        for shard in streamer_message.shards.iter_mut() {
+            let changes_count = shard.state_changes.len();
+            shard
+                .state_changes
+                .retain(|state_change| account_filter.is_indexed(state_change_account_id(&state_change.value)));
+            metrics::SKIPPED_STATE_CHANGES_TOTAL.inc_by((changes_count - shard.state_changes.len()) as u64);
+        }
+    }
+
     let handle_block_future = handle_block(
         block_height,
         block_hash,
@@ -42,13 +58,26 @@ async fn handle_streamer_message(
             .iter()
             .map(|chunk| (chunk.chunk_hash.to_string(), chunk.shard_id, chunk.height_included))
             .collect(),
-        scylla_storage,
+        db_manager,
     );
-    let handle_state_change_future = handle_state_changes(streamer_message, scylla_storage, block_height, block_hash);
+    // Serializing the changes before the `streamer_message` is moved into `handle_state_changes`,
+    // the deployed code is referenced by its hash since it is stored in the `contract_code` table
+    let block_state_changes = streamer_message
+        .shards
+        .iter()
+        .flat_map(|shard| shard.state_changes.iter())
+        .map(|state_change| serde_json::to_vec(&readnode_primitives::StoredStateChange::new(state_change)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let handle_block_state_changes_future =
+        db_manager.add_block_state_changes(block_height, block_hash, block_state_changes);
+    let handle_state_change_future = handle_state_changes(streamer_message, db_manager, block_height, block_hash);
 
-    let update_meta_future = scylla_storage.update_meta(indexer_id, num_bigint::BigInt::from(block_height));
+    futures::try_join!(handle_block_future, handle_block_state_changes_future, handle_state_change_future,)?;
 
-    futures::try_join!(handle_block_future, handle_state_change_future, update_meta_future)?;
+    // The block is fully stored, the progress is persisted only if all the lower blocks are stored as well
+    progress_watermark
+        .finish_and_persist(block_height, db_manager, indexer_id)
+        .await?;
 
     metrics::BLOCK_PROCESSED_TOTAL.inc();
     // Prometheus Gauge Metric type do not support u64
@@ -62,15 +91,15 @@ async fn handle_streamer_message(
     Ok(())
 }
 
-#[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(scylla_storage)))]
+#[cfg_attr(feature = "tracing-instrumentation", tracing::instrument(skip(db_manager)))]
 async fn handle_block(
     block_height: u64,
     block_hash: CryptoHash,
     chunks: Vec<(configs::ChunkHash, configs::ShardId, configs::HeightIncluded)>,
-    scylla_storage: &configs::ScyllaDBManager,
+    db_manager: &dyn storage::StateIndexerStorage,
 ) -> anyhow::Result<()> {
-    let add_block_future = scylla_storage.add_block(num_bigint::BigInt::from(block_height), block_hash);
-    let add_chunks_future = scylla_storage.add_chunks(num_bigint::BigInt::from(block_height), chunks);
+    let add_block_future = db_manager.add_block(block_height, block_hash);
+    let add_chunks_future = db_manager.add_chunks(block_height, chunks);
 
     futures::try_join!(add_block_future, add_chunks_future)?;
     Ok(())
@@ -85,14 +114,14 @@ async fn handle_block(
 /// in any order) so it's easier for us to skip all the changes except the latest one.
 #[cfg_attr(
     feature = "tracing-instrumentation",
-    tracing::instrument(skip(streamer_message, scylla_storage))
+    tracing::instrument(skip(streamer_message, db_manager))
 )]
 async fn handle_state_changes(
     streamer_message: near_indexer_primitives::StreamerMessage,
-    scylla_storage: &configs::ScyllaDBManager,
+    db_manager: &dyn storage::StateIndexerStorage,
     block_height: u64,
     block_hash: CryptoHash,
-) -> anyhow::Result<Vec<()>> {
+) -> anyhow::Result<()> {
     let mut state_changes_to_store =
         std::collections::HashMap::<String, near_indexer_primitives::views::StateChangeWithCauseView>::new();
 
@@ -133,105 +162,148 @@ async fn handle_state_changes(
         state_changes_to_store.insert(key, state_change);
     }
 
-    // Asynchronous storing of StateChangeWithCauseView into the storage.
-    let futures = state_changes_to_store.into_values().map(|state_change_with_cause| {
-        store_state_change(state_change_with_cause, scylla_storage, block_height, block_hash)
-    });
+    let state_changes: Vec<storage::StateChangeRecord> = state_changes_to_store
+        .into_values()
+        .map(|state_change_with_cause| state_change_record(state_change_with_cause.value))
+        .collect();
 
-    futures::future::try_join_all(futures).await
+    // The list of the account access keys is updated with a read-modify-write of the previous list,
+    // so it is stored separately from the rest of the changes
+    #[cfg(feature = "account_access_keys")]
+    {
+        let access_key_changes: Vec<storage::StateChangeRecord> = state_changes
+            .iter()
+            .filter(|state_change| matches!(state_change, storage::StateChangeRecord::AccessKey { .. }))
+            .cloned()
+            .collect();
+        let add_account_access_keys_futures = access_key_changes.iter().filter_map(|state_change| match state_change {
+            storage::StateChangeRecord::AccessKey {
+                account_id,
+                public_key,
+                access_key,
+            } => Some(db_manager.add_account_access_keys(
+                account_id.clone(),
+                block_height,
+                public_key,
+                access_key.as_deref(),
+            )),
+            _ => None,
+        });
+        futures::try_join!(
+            db_manager.store_state_changes(block_height, block_hash, state_changes),
+            futures::future::try_join_all(add_account_access_keys_futures)
+        )?;
+    }
+    #[cfg(not(feature = "account_access_keys"))]
+    db_manager
+        .store_state_changes(block_height, block_hash, state_changes)
+        .await?;
+    Ok(())
 }
 
-#[cfg_attr(
-    feature = "tracing-instrumentation",
-    tracing::instrument(skip(state_change, scylla_storage))
-)]
-async fn store_state_change(
-    state_change: near_indexer_primitives::views::StateChangeWithCauseView,
-    scylla_storage: &configs::ScyllaDBManager,
-    block_height: u64,
-    block_hash: CryptoHash,
-) -> anyhow::Result<()> {
-    let block_height = num_bigint::BigInt::from(block_height);
-    match state_change.value {
-        StateChangeValueView::DataUpdate { account_id, key, value } => {
-            scylla_storage
-                .add_state_changes(account_id, block_height, block_hash, key.as_ref(), value.as_ref())
-                .await?
-        }
-        StateChangeValueView::DataDeletion { account_id, key } => {
-            scylla_storage
-                .delete_state_changes(account_id, block_height, block_hash, key.as_ref())
-                .await?
-        }
+fn state_change_account_id(state_change: &StateChangeValueView) -> &str {
+    match state_change {
+        StateChangeValueView::DataUpdate { account_id, .. }
+        | StateChangeValueView::DataDeletion { account_id, .. }
+        | StateChangeValueView::AccessKeyUpdate { account_id, .. }
+        | StateChangeValueView::AccessKeyDeletion { account_id, .. }
+        | StateChangeValueView::ContractCodeUpdate { account_id, .. }
+        | StateChangeValueView::ContractCodeDeletion { account_id }
+        | StateChangeValueView::AccountUpdate { account_id, .. }
+        | StateChangeValueView::AccountDeletion { account_id } => account_id.as_ref(),
+    }
+}
+
+fn state_change_record(state_change: StateChangeValueView) -> storage::StateChangeRecord {
+    match state_change {
+        StateChangeValueView::DataUpdate { account_id, key, value } => storage::StateChangeRecord::Data {
+            account_id,
+            key: <[u8]>::to_vec(key.as_ref()),
+            value: Some(<[u8]>::to_vec(value.as_ref())),
+        },
+        StateChangeValueView::DataDeletion { account_id, key } => storage::StateChangeRecord::Data {
+            account_id,
+            key: <[u8]>::to_vec(key.as_ref()),
+            value: None,
+        },
         StateChangeValueView::AccessKeyUpdate {
             account_id,
             public_key,
             access_key,
-        } => {
-            let data_key = public_key
-                .try_to_vec()
-                .expect("Failed to borsh-serialize the PublicKey");
-            let data_value = access_key
+        } => storage::StateChangeRecord::AccessKey {
+            account_id,
+            public_key: public_key
                 .try_to_vec()
-                .expect("Failed to borsh-serialize the AccessKey");
-            let add_access_key_future = scylla_storage.add_access_key(
-                account_id.clone(),
-                block_height.clone(),
-                block_hash,
-                &data_key,
-                &data_value,
-            );
-
-            #[cfg(feature = "account_access_keys")]
-            {
-                let add_account_access_keys_future =
-                    scylla_storage.add_account_access_keys(account_id, block_height, &data_key, Some(&data_value));
-                futures::try_join!(add_access_key_future, add_account_access_keys_future)?;
-            }
-            #[cfg(not(feature = "account_access_keys"))]
-            add_access_key_future.await?;
-        }
-        StateChangeValueView::AccessKeyDeletion { account_id, public_key } => {
-            let data_key = public_key
+                .expect("Failed to borsh-serialize the PublicKey"),
+            access_key: Some(
+                access_key
+                    .try_to_vec()
+                    .expect("Failed to borsh-serialize the AccessKey"),
+            ),
+        },
+        StateChangeValueView::AccessKeyDeletion { account_id, public_key } => storage::StateChangeRecord::AccessKey {
+            account_id,
+            public_key: public_key
                 .try_to_vec()
-                .expect("Failed to borsh-serialize the PublicKey");
-            let delete_access_key_future =
-                scylla_storage.delete_access_key(account_id.clone(), block_height.clone(), block_hash, &data_key);
-
-            #[cfg(feature = "account_access_keys")]
-            {
-                let delete_account_access_keys_future =
-                    scylla_storage.add_account_access_keys(account_id, block_height, &data_key, None);
-                futures::try_join!(delete_access_key_future, delete_account_access_keys_future)?;
-            }
-            #[cfg(not(feature = "account_access_keys"))]
-            delete_access_key_future.await?;
-        }
-        StateChangeValueView::ContractCodeUpdate { account_id, code } => {
-            scylla_storage
-                .add_contract_code(account_id, block_height, block_hash, code.as_ref())
-                .await?
-        }
+                .expect("Failed to borsh-serialize the PublicKey"),
+            access_key: None,
+        },
+        StateChangeValueView::ContractCodeUpdate { account_id, code } => storage::StateChangeRecord::ContractCode {
+            account_id,
+            code: Some(<[u8]>::to_vec(code.as_ref())),
+        },
         StateChangeValueView::ContractCodeDeletion { account_id } => {
-            scylla_storage
-                .delete_contract_code(account_id, block_height, block_hash)
-                .await?
-        }
-        StateChangeValueView::AccountUpdate { account_id, account } => {
-            let value = Account::from(account)
-                .try_to_vec()
-                .expect("Failed to borsh-serialize the Account");
-            scylla_storage
-                .add_account(account_id, block_height, block_hash, value)
-                .await?
-        }
-        StateChangeValueView::AccountDeletion { account_id } => {
-            scylla_storage
-                .delete_account(account_id, block_height, block_hash)
-                .await?
+            storage::StateChangeRecord::ContractCode { account_id, code: None }
         }
+        StateChangeValueView::AccountUpdate { account_id, account } => storage::StateChangeRecord::Account {
+            account_id,
+            account: Some(
+                Account::from(account)
+                    .try_to_vec()
+                    .expect("Failed to borsh-serialize the Account"),
+            ),
+        },
+        StateChangeValueView::AccountDeletion { account_id } => storage::StateChangeRecord::Account {
+            account_id,
+            account: None,
+        },
     }
-    Ok(())
+}
+
+async fn init_db_manager(opts: &Opts) -> anyhow::Result<Box<dyn storage::StateIndexerStorage>> {
+    #[cfg(feature = "rocksdb")]
+    if let Some(rocksdb_path) = &opts.rocksdb_path {
+        tracing::info!(target: INDEXER, "Using the embedded RocksDB storage at {:?}", rocksdb_path);
+        return Ok(Box::new(database::embedded::RocksDBManager::open(
+            &rocksdb_path.join(database::embedded::STATE_INDEXER_DIR),
+            &database::embedded::STATE_INDEXER_COLUMN_FAMILIES,
+        )?));
+    }
+
+    #[cfg(feature = "postgres")]
+    if let Some(postgres_url) = &opts.postgres_url {
+        tracing::info!(target: INDEXER, "Connecting to the PostgreSQL database...");
+        return Ok(Box::new(
+            database::postgres::PostgresDBManager::connect(
+                postgres_url,
+                opts.postgres_max_connections,
+                opts.scylla_migrations,
+            )
+            .await?,
+        ));
+    }
+
+    let mut scylla_db_manager = configs::ScyllaDBManager::new(
+        &opts.to_scylla_session_options(),
+        opts.scylla_migrations,
+        &opts.scylla_keyspaces,
+    )
+    .await?;
+    scylla_db_manager.set_batch_size(opts.scylla_batch_size);
+    scylla_db_manager
+        .backfill_state_keys(opts.scylla_backfill_state_keys)
+        .await?;
+    Ok(scylla_db_manager)
 }
 
 #[tokio::main]
@@ -246,19 +318,34 @@ async fn main() -> anyhow::Result<()> {
 
     let opts: Opts = Opts::parse();
 
-    let scylla_storage = configs::ScyllaDBManager::new(
-        &opts.scylla_url,
-        opts.scylla_user.as_deref(),
-        opts.scylla_password.as_deref(),
-        opts.scylla_preferred_dc.as_deref(),
-        None,
-        opts.max_retry,
-        opts.strict_mode,
-    )
-    .await?;
-    let scylla_session = scylla_storage.scylla_session().await;
-    let config: near_lake_framework::LakeConfig = opts.to_lake_config(&scylla_session).await?;
-    let (sender, stream) = near_lake_framework::streamer(config);
+    let db_manager = init_db_manager(&opts).await?;
+    match opts.start_options() {
+        configs::StartOptions::ImportGenesis { genesis_file } => {
+            return state_records::import_genesis(
+                genesis_file,
+                db_manager.as_ref(),
+                &opts.account_filter,
+                opts.rpc_url(),
+                &opts.rpc_api_key,
+            )
+            .await;
+        }
+        configs::StartOptions::ImportStateDump { records_file, height } => {
+            return state_records::import_state_dump(
+                records_file,
+                *height,
+                db_manager.as_ref(),
+                &opts.account_filter,
+                &opts.indexer_id,
+                opts.rpc_url(),
+                &opts.rpc_api_key,
+            )
+            .await;
+        }
+        _ => {}
+    }
+    let config: block_source::BlockSourceConfig = opts.to_lake_config(db_manager.as_ref()).await?;
+    let (sender, stream) = block_source::streamer(config);
 
     // Initiate metrics http server
     tokio::spawn(metrics::init_server(opts.port).expect("Failed to start metrics server"));
@@ -266,19 +353,62 @@ async fn main() -> anyhow::Result<()> {
     let stats = std::sync::Arc::new(tokio::sync::RwLock::new(metrics::Stats::new()));
     tokio::spawn(metrics::state_logger(std::sync::Arc::clone(&stats), opts.rpc_url().to_string(), opts.rpc_api_key));
 
+    // In the `Range` mode the blocks above the range are not handled and the indexer exits
+    let range_end = opts.start_options().range().map(|(_, to)| to);
+    if let Some((from, to)) = opts.start_options().range() {
+        tracing::info!(target: INDEXER, "Indexing the range {}..={}", from, to);
+    }
+
+    let progress_watermark = progress::ProgressWatermark::default();
     let mut handlers = tokio_stream::wrappers::ReceiverStream::new(stream)
+        .take_while(|streamer_message| {
+            futures::future::ready(range_end.map_or(true, |to| streamer_message.block.header.height <= to))
+        })
         .map(|streamer_message| {
-            handle_streamer_message(streamer_message, &scylla_storage, &opts.indexer_id, std::sync::Arc::clone(&stats))
+            // Registering the block as in flight in the order of the stream,
+            // the futures of the blocks might start in any order
+            progress_watermark.start(streamer_message.block.header.height);
+            handle_streamer_message(
+                streamer_message,
+                db_manager.as_ref(),
+                &opts.indexer_id,
+                std::sync::Arc::clone(&stats),
+                &progress_watermark,
+                &opts.account_filter,
+            )
         })
         .buffer_unordered(opts.concurrency);
 
+    let mut failed_blocks_count = 0;
     while let Some(_handle_message) = handlers.next().await {
         if let Err(err) = _handle_message {
+            // The failed block holds the progress watermark back, so following the chain past it
+            // would only pile up the stored blocks which are never persisted as the progress
+            if range_end.is_none() {
+                sender.abort();
+                return Err(err.context(
+                    "Failed to store the block, restart the indexer to resume from the last persisted block",
+                ));
+            }
+            failed_blocks_count += 1;
             tracing::warn!(target: INDEXER, "{:?}", err);
         }
     }
     drop(handlers); // close the channel so the sender will stop
 
+    if range_end.is_some() && !sender.is_finished() {
+        // The sender keeps following the chain after the range is streamed
+        sender.abort();
+        if failed_blocks_count > 0 {
+            anyhow::bail!(
+                "{} blocks of the range failed to be stored, run the range again to resume from them",
+                failed_blocks_count
+            );
+        }
+        tracing::info!(target: INDEXER, "The range is indexed");
+        return Ok(());
+    }
+
     // propagate errors from the sender
     match sender.await {
         Ok(Ok(())) => Ok(()),
@@ -1,5 +1,5 @@
 use actix_web::{get, App, HttpServer, Responder};
-use prometheus::{Encoder, IntCounter, IntGauge, Opts};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts};
 
 type Result<T, E> = std::result::Result<T, E>;
 
@@ -17,6 +17,20 @@ fn try_create_int_gauge(name: &str, help: &str) -> Result<IntGauge, prometheus::
     Ok(gauge)
 }
 
+fn try_create_int_counter_vec(name: &str, help: &str, labels: &[&str]) -> Result<IntCounterVec, prometheus::Error> {
+    let opts = Opts::new(name, help);
+    let counter = IntCounterVec::new(opts, labels)?;
+    prometheus::register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+fn try_create_histogram_vec(name: &str, help: &str, labels: &[&str]) -> Result<HistogramVec, prometheus::Error> {
+    let opts = HistogramOpts::new(name, help);
+    let histogram = HistogramVec::new(opts, labels)?;
+    prometheus::register(Box::new(histogram.clone()))?;
+    Ok(histogram)
+}
+
 lazy_static! {
     pub(crate) static ref BLOCK_PROCESSED_TOTAL: IntCounter = try_create_int_counter(
         "total_blocks_processed",
@@ -28,6 +42,30 @@ lazy_static! {
         "Last seen block height by indexer"
     )
     .unwrap();
+    pub(crate) static ref PROGRESS_WATERMARK_BLOCK_HEIGHT: IntGauge = try_create_int_gauge(
+        "progress_watermark_block_height",
+        "The height below which every block is stored, persisted to resume from after the interruption"
+    )
+    .unwrap();
+    // `mode` is `batched` for the writes grouped into the batches and `single` for a query per change,
+    // compare the rates of the modes to see the effect of `scylla_batch_size`
+    pub(crate) static ref STORED_STATE_CHANGES_TOTAL: IntCounterVec = try_create_int_counter_vec(
+        "total_stored_state_changes",
+        "Total number of the stored state changes by the write mode",
+        &["mode"]
+    )
+    .unwrap();
+    pub(crate) static ref STORE_STATE_CHANGES_DURATION_SECONDS: HistogramVec = try_create_histogram_vec(
+        "store_state_changes_duration_seconds",
+        "Time to store the state changes of the block by the write mode",
+        &["mode"]
+    )
+    .unwrap();
+    pub(crate) static ref SKIPPED_STATE_CHANGES_TOTAL: IntCounter = try_create_int_counter(
+        "total_skipped_state_changes",
+        "Total number of the state changes of the accounts not indexed according to the account filter"
+    )
+    .unwrap();
 }
 
 #[get("/metrics")]
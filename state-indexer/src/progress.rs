@@ -0,0 +1,117 @@
+// The contiguous progress of the indexer.
+//
+// With `--concurrency > 1` the blocks are stored in any order, so the height of the block stored last
+// can't be persisted as the progress: the lower blocks might still be in flight and would be skipped
+// by `FromInterruption` after a crash. `ProgressWatermark` tracks the in-flight heights and persists
+// the highest height below which every received block is fully stored.
+// A block that failed to be stored stays in flight, so the watermark doesn't move past it
+// and the block is processed again after the restart. Following the chain the indexer exits
+// on the first failed block, a range is streamed to the end before the failed blocks are reported.
+
+#[derive(Debug, Default)]
+struct Heights {
+    /// Received but not yet stored
+    in_flight: std::collections::BTreeSet<u64>,
+    /// Stored but above some in-flight height
+    stored: std::collections::BTreeSet<u64>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ProgressWatermark {
+    heights: std::sync::Mutex<Heights>,
+    /// The last persisted watermark, the lock serializes the writes so the persisted value never goes back
+    persisted: tokio::sync::Mutex<Option<u64>>,
+}
+
+impl ProgressWatermark {
+    /// Has to be called in the order the blocks are received, before the block is handled
+    pub(crate) fn start(&self, block_height: u64) {
+        self.heights
+            .lock()
+            .expect("Progress watermark lock is poisoned")
+            .in_flight
+            .insert(block_height);
+    }
+
+    /// Marks the block as stored and returns the new watermark if it has moved
+    fn finish(&self, block_height: u64) -> Option<u64> {
+        let mut heights = self.heights.lock().expect("Progress watermark lock is poisoned");
+        heights.in_flight.remove(&block_height);
+        heights.stored.insert(block_height);
+
+        let watermark = match heights.in_flight.first() {
+            Some(lowest_in_flight) => heights.stored.range(..*lowest_in_flight).next_back().copied(),
+            None => heights.stored.last().copied(),
+        }?;
+        heights.stored = heights.stored.split_off(&(watermark + 1));
+        Some(watermark)
+    }
+
+    /// Marks the block as stored and persists the watermark if it has moved
+    pub(crate) async fn finish_and_persist(
+        &self,
+        block_height: u64,
+        db_manager: &dyn crate::storage::StateIndexerStorage,
+        indexer_id: &str,
+    ) -> anyhow::Result<()> {
+        let watermark = match self.finish(block_height) {
+            Some(watermark) => watermark,
+            None => return Ok(()),
+        };
+        let mut persisted = self.persisted.lock().await;
+        if persisted.map_or(true, |persisted| watermark > persisted) {
+            db_manager.update_meta(indexer_id, watermark).await?;
+            *persisted = Some(watermark);
+            crate::metrics::PROGRESS_WATERMARK_BLOCK_HEIGHT.set(i64::try_from(watermark)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finish_moves_the_watermark_in_order() {
+        let progress_watermark = ProgressWatermark::default();
+        progress_watermark.start(1);
+        progress_watermark.start(2);
+        assert_eq!(progress_watermark.finish(1), Some(1));
+        assert_eq!(progress_watermark.finish(2), Some(2));
+    }
+
+    #[test]
+    fn finish_holds_the_watermark_below_the_lowest_in_flight_block() {
+        let progress_watermark = ProgressWatermark::default();
+        for block_height in 1..=4 {
+            progress_watermark.start(block_height);
+        }
+        assert_eq!(progress_watermark.finish(2), None);
+        assert_eq!(progress_watermark.finish(4), None);
+        assert_eq!(progress_watermark.finish(1), Some(2));
+        assert_eq!(progress_watermark.finish(3), Some(4));
+    }
+
+    #[test]
+    fn finish_skips_the_heights_without_blocks() {
+        let progress_watermark = ProgressWatermark::default();
+        progress_watermark.start(10);
+        progress_watermark.start(15);
+        assert_eq!(progress_watermark.finish(15), None);
+        assert_eq!(progress_watermark.finish(10), Some(15));
+    }
+
+    #[test]
+    fn finish_forgets_the_stored_blocks_below_the_watermark() {
+        let progress_watermark = ProgressWatermark::default();
+        for block_height in 1..=3 {
+            progress_watermark.start(block_height);
+        }
+        progress_watermark.finish(2);
+        progress_watermark.finish(1);
+        let heights = progress_watermark.heights.lock().unwrap();
+        assert_eq!(heights.in_flight.iter().copied().collect::<Vec<_>>(), vec![3]);
+        assert!(heights.stored.is_empty());
+    }
+}
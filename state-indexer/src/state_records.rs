@@ -0,0 +1,527 @@
+// Import of the state from the state records of the genesis or the state dump.
+//
+// The state-indexer stores the state changes of the blocks, so the state which has not changed
+// since the block the indexer started from is missing from the state tables.
+// The genesis records are imported at the genesis height, so the state set in genesis and
+// never changed afterwards is served. The records of the state dump made by the nearcore
+// `neard view-state dump-state` at some height are imported at that height, so the indexer
+// can start from it instead of the genesis.
+// The accounts, access keys, contract code and contract data are imported, the receipts are skipped.
+
+use borsh::BorshSerialize;
+use near_indexer_primitives::near_primitives::state_record::StateRecord;
+
+use crate::storage::StateChangeRecord;
+
+/// The number of the records stored at a time
+const RECORDS_CHUNK_SIZE: usize = 1000;
+
+pub(crate) async fn import_genesis(
+    genesis_file: &std::path::Path,
+    db_manager: &dyn crate::storage::StateIndexerStorage,
+    account_filter: &database::account_filter::AccountFilterArgs,
+    rpc_url: &str,
+    rpc_api_key: &Option<String>,
+) -> anyhow::Result<()> {
+    let (genesis_height, genesis_hash) = crate::configs::genesis_block(rpc_url, rpc_api_key).await?;
+    tracing::info!(
+        target: crate::INDEXER,
+        "Importing the genesis records from {:?} at the genesis block {} ({})",
+        genesis_file,
+        genesis_height,
+        genesis_hash,
+    );
+    import_records(genesis_file, genesis_height, genesis_hash, db_manager, account_filter).await
+}
+
+/// Imports the state dump at its height and records the height as the progress of the `indexer_id`,
+/// so the indexer started `from-interruption` continues from it
+pub(crate) async fn import_state_dump(
+    records_file: &std::path::Path,
+    block_height: u64,
+    db_manager: &dyn crate::storage::StateIndexerStorage,
+    account_filter: &database::account_filter::AccountFilterArgs,
+    indexer_id: &str,
+    rpc_url: &str,
+    rpc_api_key: &Option<String>,
+) -> anyhow::Result<()> {
+    let block_hash = crate::configs::block_hash(block_height, rpc_url, rpc_api_key).await?;
+    tracing::info!(
+        target: crate::INDEXER,
+        "Importing the state dump from {:?} at the block {} ({})",
+        records_file,
+        block_height,
+        block_hash,
+    );
+    import_records(records_file, block_height, block_hash, db_manager, account_filter).await?;
+    db_manager.update_meta(indexer_id, block_height).await?;
+    tracing::info!(
+        target: crate::INDEXER,
+        "The state at the block {} is imported, start the indexer `from-interruption` with the indexer id {}",
+        block_height,
+        indexer_id,
+    );
+    Ok(())
+}
+
+/// Streams the records from the genesis file or the file with the array of the records
+/// and stores them at the given block
+async fn import_records(
+    records_file: &std::path::Path,
+    block_height: u64,
+    block_hash: near_indexer_primitives::CryptoHash,
+    db_manager: &dyn crate::storage::StateIndexerStorage,
+    account_filter: &database::account_filter::AccountFilterArgs,
+) -> anyhow::Result<()> {
+    // The file is too big to be read at once, so the records are streamed from the file on the blocking thread
+    let file = CancellableReader {
+        inner: std::fs::File::open(records_file)?,
+        cancelled: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+    };
+    let cancelled = file.cancelled.clone();
+    let (sender, receiver) = tokio::sync::mpsc::channel::<StateRecord>(RECORDS_CHUNK_SIZE * 4);
+    let reader = tokio::task::spawn_blocking(move || {
+        near_chain_configs::stream_records_from_file(std::io::BufReader::new(file), |record| {
+            // The receiver is dropped only if the import has failed, the reading is stopped then
+            if sender.blocking_send(record).is_err() {
+                cancelled.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+        })
+    });
+
+    // The receiver is dropped when the records are stored or failed to be stored
+    let imported_records = match store_records(receiver, block_height, block_hash, db_manager, account_filter).await {
+        Ok(imported_records) => imported_records,
+        Err(err) => {
+            // The reader fails on the next read after the receiver is dropped, its error is the consequence
+            if let Err(reader_err) = reader.await {
+                tracing::warn!(target: crate::INDEXER, "The records reader failed: {:?}", reader_err);
+            }
+            return Err(err);
+        }
+    };
+    reader.await??;
+
+    tracing::info!(target: crate::INDEXER, "Imported {} state records", imported_records);
+    Ok(())
+}
+
+/// Stores the received records in chunks, returns the number of the stored records
+async fn store_records(
+    mut receiver: tokio::sync::mpsc::Receiver<StateRecord>,
+    block_height: u64,
+    block_hash: near_indexer_primitives::CryptoHash,
+    db_manager: &dyn crate::storage::StateIndexerStorage,
+    account_filter: &database::account_filter::AccountFilterArgs,
+) -> anyhow::Result<usize> {
+    let mut imported_records = 0;
+    let mut state_changes = Vec::with_capacity(RECORDS_CHUNK_SIZE);
+    while let Some(record) = receiver.recv().await {
+        if let Some(state_change) = state_change_record(record) {
+            if account_filter.is_indexed(state_change.account_id().as_ref()) {
+                state_changes.push(state_change);
+            }
+        }
+        if state_changes.len() >= RECORDS_CHUNK_SIZE {
+            imported_records += state_changes.len();
+            store_state_changes(db_manager, block_height, block_hash, std::mem::take(&mut state_changes)).await?;
+            tracing::info!(target: crate::INDEXER, "Imported {} state records", imported_records);
+        }
+    }
+    imported_records += state_changes.len();
+    store_state_changes(db_manager, block_height, block_hash, state_changes).await?;
+    Ok(imported_records)
+}
+
+/// Fails the reads once cancelled, so the records stop being parsed after the import has failed
+struct CancellableReader<R> {
+    inner: R,
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl<R: std::io::Read> std::io::Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancelled.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "The import of the records is cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+async fn store_state_changes(
+    db_manager: &dyn crate::storage::StateIndexerStorage,
+    block_height: u64,
+    block_hash: near_indexer_primitives::CryptoHash,
+    state_changes: Vec<StateChangeRecord>,
+) -> anyhow::Result<()> {
+    // The list of the account access keys is updated with a read-modify-write of the list
+    // at the same height, so the keys of the account are grouped and its list is written once per chunk.
+    // The keys of the account can span any number of chunks, the list written for the previous chunk
+    // at the same height is merged with the keys of the next one by `add_account_access_keys_list`
+    #[cfg(feature = "account_access_keys")]
+    {
+        let mut account_access_keys = std::collections::HashMap::<_, Vec<_>>::new();
+        for state_change in state_changes.iter() {
+            if let StateChangeRecord::AccessKey {
+                account_id,
+                public_key,
+                access_key,
+            } = state_change
+            {
+                account_access_keys
+                    .entry(account_id.clone())
+                    .or_default()
+                    .push((public_key.clone(), access_key.clone()));
+            }
+        }
+        futures::future::try_join_all(account_access_keys.into_iter().map(|(account_id, access_keys)| {
+            db_manager.add_account_access_keys_list(account_id, block_height, access_keys)
+        }))
+        .await?;
+    }
+    db_manager
+        .store_state_changes(block_height, block_hash, state_changes)
+        .await
+}
+
+fn state_change_record(record: StateRecord) -> Option<StateChangeRecord> {
+    match record {
+        StateRecord::Account { account_id, account } => Some(StateChangeRecord::Account {
+            account_id,
+            account: Some(account.try_to_vec().expect("Failed to borsh-serialize the Account")),
+        }),
+        StateRecord::Data {
+            account_id,
+            data_key,
+            value,
+        } => Some(StateChangeRecord::Data {
+            account_id,
+            key: <[u8]>::to_vec(data_key.as_ref()),
+            value: Some(<[u8]>::to_vec(value.as_ref())),
+        }),
+        StateRecord::Contract { account_id, code } => Some(StateChangeRecord::ContractCode {
+            account_id,
+            code: Some(code),
+        }),
+        StateRecord::AccessKey {
+            account_id,
+            public_key,
+            access_key,
+        } => Some(StateChangeRecord::AccessKey {
+            account_id,
+            public_key: public_key
+                .try_to_vec()
+                .expect("Failed to borsh-serialize the PublicKey"),
+            access_key: Some(
+                access_key
+                    .try_to_vec()
+                    .expect("Failed to borsh-serialize the AccessKey"),
+            ),
+        }),
+        StateRecord::PostponedReceipt(_) | StateRecord::ReceivedData { .. } | StateRecord::DelayedReceipt(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_indexer_primitives::near_primitives;
+    use near_indexer_primitives::types::AccountId;
+
+    /// Records what the import stores, the other writes are not expected from the import
+    #[derive(Default)]
+    struct RecordingStorage {
+        stored_chunks: std::sync::Mutex<Vec<Vec<StateChangeRecord>>>,
+        #[cfg(feature = "account_access_keys")]
+        access_keys_lists: std::sync::Mutex<Vec<(AccountId, Vec<(Vec<u8>, Option<Vec<u8>>)>)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::storage::StateIndexerStorage for RecordingStorage {
+        async fn add_state_changes(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            _key: &[u8],
+            _value: &[u8],
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn delete_state_changes(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            _key: &[u8],
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn add_access_key(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            _public_key: &[u8],
+            _access_key: &[u8],
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn delete_access_key(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            _public_key: &[u8],
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        #[cfg(feature = "account_access_keys")]
+        async fn add_account_access_keys(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _public_key: &[u8],
+            _access_key: Option<&[u8]>,
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        #[cfg(feature = "account_access_keys")]
+        async fn add_account_access_keys_list(
+            &self,
+            account_id: AccountId,
+            _block_height: u64,
+            access_keys: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+        ) -> anyhow::Result<()> {
+            self.access_keys_lists.lock().unwrap().push((account_id, access_keys));
+            Ok(())
+        }
+
+        async fn add_contract_code(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            _code: &[u8],
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn delete_contract_code(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn add_account(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            _account: Vec<u8>,
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn delete_account(
+            &self,
+            _account_id: AccountId,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn store_state_changes(
+            &self,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            state_changes: Vec<StateChangeRecord>,
+        ) -> anyhow::Result<()> {
+            self.stored_chunks.lock().unwrap().push(state_changes);
+            Ok(())
+        }
+
+        async fn add_block(
+            &self,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn add_chunks(
+            &self,
+            _block_height: u64,
+            _chunks: Vec<(crate::configs::ChunkHash, crate::configs::ShardId, crate::configs::HeightIncluded)>,
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn add_block_state_changes(
+            &self,
+            _block_height: u64,
+            _block_hash: near_indexer_primitives::CryptoHash,
+            _state_changes: Vec<Vec<u8>>,
+        ) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn update_meta(&self, _indexer_id: &str, _block_height: u64) -> anyhow::Result<()> {
+            unreachable!()
+        }
+
+        async fn get_last_processed_block_height(&self, _indexer_id: &str) -> anyhow::Result<Option<u64>> {
+            unreachable!()
+        }
+    }
+
+    fn account_id(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    fn data_record(account_id: &str, index: usize) -> StateRecord {
+        StateRecord::Data {
+            account_id: self::account_id(account_id),
+            data_key: index.to_be_bytes().to_vec().into(),
+            value: b"value".to_vec().into(),
+        }
+    }
+
+    fn public_key(index: usize) -> near_crypto::PublicKey {
+        near_crypto::SecretKey::from_seed(near_crypto::KeyType::ED25519, &index.to_string()).public_key()
+    }
+
+    fn access_key_record(account_id: &str, index: usize) -> StateRecord {
+        StateRecord::AccessKey {
+            account_id: self::account_id(account_id),
+            public_key: public_key(index),
+            access_key: near_primitives::account::AccessKey::full_access(),
+        }
+    }
+
+    async fn import(
+        records: Vec<StateRecord>,
+        account_filter: &database::account_filter::AccountFilterArgs,
+    ) -> (usize, RecordingStorage) {
+        let storage = RecordingStorage::default();
+        let (sender, receiver) = tokio::sync::mpsc::channel(records.len().max(1));
+        for record in records {
+            sender.send(record).await.unwrap();
+        }
+        drop(sender);
+        let imported_records =
+            store_records(receiver, 10, near_indexer_primitives::CryptoHash::default(), &storage, account_filter)
+                .await
+                .unwrap();
+        (imported_records, storage)
+    }
+
+    fn no_filter() -> database::account_filter::AccountFilterArgs {
+        database::account_filter::AccountFilterArgs {
+            index_accounts: vec![],
+            skip_accounts: vec![],
+        }
+    }
+
+    #[test]
+    fn state_records_are_converted_to_state_changes() {
+        let account = near_primitives::account::Account::new(100, 0, Default::default(), 200);
+        assert!(matches!(
+            state_change_record(StateRecord::Account {
+                account_id: account_id("alice.near"),
+                account: account.clone(),
+            }),
+            Some(StateChangeRecord::Account { account_id, account: Some(stored) })
+                if account_id.as_ref() == "alice.near" && stored == account.try_to_vec().unwrap()
+        ));
+        assert!(matches!(
+            state_change_record(data_record("alice.near", 1)),
+            Some(StateChangeRecord::Data { key, value: Some(value), .. })
+                if key == 1usize.to_be_bytes() && value == b"value"
+        ));
+        assert!(matches!(
+            state_change_record(StateRecord::Contract {
+                account_id: account_id("alice.near"),
+                code: b"code".to_vec(),
+            }),
+            Some(StateChangeRecord::ContractCode { code: Some(code), .. }) if code == b"code"
+        ));
+        assert!(matches!(
+            state_change_record(access_key_record("alice.near", 1)),
+            Some(StateChangeRecord::AccessKey { public_key: stored_public_key, access_key: Some(access_key), .. })
+                if stored_public_key == public_key(1).try_to_vec().unwrap()
+                    && access_key == near_primitives::account::AccessKey::full_access().try_to_vec().unwrap()
+        ));
+        assert!(state_change_record(StateRecord::ReceivedData {
+            account_id: account_id("alice.near"),
+            data_id: Default::default(),
+            data: None,
+        })
+        .is_none());
+    }
+
+    #[tokio::test]
+    async fn records_are_stored_in_chunks() {
+        let records = (0..RECORDS_CHUNK_SIZE * 2 + 5)
+            .map(|index| data_record("alice.near", index))
+            .collect();
+        let (imported_records, storage) = import(records, &no_filter()).await;
+
+        assert_eq!(imported_records, RECORDS_CHUNK_SIZE * 2 + 5);
+        let chunk_sizes: Vec<_> = storage.stored_chunks.lock().unwrap().iter().map(Vec::len).collect();
+        assert_eq!(chunk_sizes, vec![RECORDS_CHUNK_SIZE, RECORDS_CHUNK_SIZE, 5]);
+    }
+
+    #[tokio::test]
+    async fn records_of_the_skipped_accounts_are_not_stored() {
+        let records = (0..10)
+            .map(|index| data_record(if index % 2 == 0 { "alice.near" } else { "skipped.near" }, index))
+            .collect();
+        let account_filter = database::account_filter::AccountFilterArgs {
+            index_accounts: vec![],
+            skip_accounts: vec!["skipped.near".parse().unwrap()],
+        };
+        let (imported_records, storage) = import(records, &account_filter).await;
+
+        assert_eq!(imported_records, 5);
+        let stored_chunks = storage.stored_chunks.lock().unwrap();
+        assert!(stored_chunks
+            .iter()
+            .flatten()
+            .all(|state_change| state_change.account_id().as_ref() == "alice.near"));
+    }
+
+    #[cfg(feature = "account_access_keys")]
+    #[tokio::test]
+    async fn access_keys_list_is_written_for_every_chunk_of_the_account() {
+        let records = (0..RECORDS_CHUNK_SIZE * 2 + 1)
+            .map(|index| access_key_record("alice.near", index))
+            .chain(std::iter::once(access_key_record("bob.near", 0)))
+            .collect();
+        let (_, storage) = import(records, &no_filter()).await;
+
+        let access_keys_lists = storage.access_keys_lists.lock().unwrap();
+        let alice_list_sizes: Vec<_> = access_keys_lists
+            .iter()
+            .filter(|(account_id, _)| account_id.as_ref() == "alice.near")
+            .map(|(_, access_keys)| access_keys.len())
+            .collect();
+        assert_eq!(alice_list_sizes, vec![RECORDS_CHUNK_SIZE, RECORDS_CHUNK_SIZE, 1]);
+        assert_eq!(
+            access_keys_lists
+                .iter()
+                .filter(|(account_id, _)| account_id.as_ref() == "bob.near")
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use crate::configs::{ChunkHash, HeightIncluded, ShardId};
+use near_indexer_primitives::types::AccountId;
+use near_indexer_primitives::CryptoHash;
+
+/// The latest change of the block to the account, access key, contract code or data key.
+/// `None` value means the deletion
+#[derive(Debug, Clone)]
+pub(crate) enum StateChangeRecord {
+    Data {
+        account_id: AccountId,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    },
+    AccessKey {
+        account_id: AccountId,
+        public_key: Vec<u8>,
+        access_key: Option<Vec<u8>>,
+    },
+    ContractCode {
+        account_id: AccountId,
+        code: Option<Vec<u8>>,
+    },
+    Account {
+        account_id: AccountId,
+        account: Option<Vec<u8>>,
+    },
+}
+
+impl StateChangeRecord {
+    pub(crate) fn account_id(&self) -> &AccountId {
+        match self {
+            Self::Data { account_id, .. }
+            | Self::AccessKey { account_id, .. }
+            | Self::ContractCode { account_id, .. }
+            | Self::Account { account_id, .. } => account_id,
+        }
+    }
+}
+
+/// Stores the changes concurrently with a query per change
+pub(crate) async fn store_state_changes_one_by_one(
+    storage: &(impl StateIndexerStorage + ?Sized),
+    block_height: u64,
+    block_hash: CryptoHash,
+    state_changes: Vec<StateChangeRecord>,
+) -> anyhow::Result<()> {
+    let timer = crate::metrics::STORE_STATE_CHANGES_DURATION_SECONDS
+        .with_label_values(&["single"])
+        .start_timer();
+    let state_changes_count = state_changes.len() as u64;
+    let futures = state_changes.into_iter().map(|state_change| async move {
+        match state_change {
+            StateChangeRecord::Data {
+                account_id,
+                key,
+                value: Some(value),
+            } => {
+                storage
+                    .add_state_changes(account_id, block_height, block_hash, &key, &value)
+                    .await
+            }
+            StateChangeRecord::Data {
+                account_id,
+                key,
+                value: None,
+            } => {
+                storage
+                    .delete_state_changes(account_id, block_height, block_hash, &key)
+                    .await
+            }
+            StateChangeRecord::AccessKey {
+                account_id,
+                public_key,
+                access_key: Some(access_key),
+            } => {
+                storage
+                    .add_access_key(account_id, block_height, block_hash, &public_key, &access_key)
+                    .await
+            }
+            StateChangeRecord::AccessKey {
+                account_id,
+                public_key,
+                access_key: None,
+            } => {
+                storage
+                    .delete_access_key(account_id, block_height, block_hash, &public_key)
+                    .await
+            }
+            StateChangeRecord::ContractCode {
+                account_id,
+                code: Some(code),
+            } => {
+                storage
+                    .add_contract_code(account_id, block_height, block_hash, &code)
+                    .await
+            }
+            StateChangeRecord::ContractCode { account_id, code: None } => {
+                storage.delete_contract_code(account_id, block_height, block_hash).await
+            }
+            StateChangeRecord::Account {
+                account_id,
+                account: Some(account),
+            } => storage.add_account(account_id, block_height, block_hash, account).await,
+            StateChangeRecord::Account {
+                account_id,
+                account: None,
+            } => storage.delete_account(account_id, block_height, block_hash).await,
+        }
+    });
+    futures::future::try_join_all(futures).await?;
+    timer.observe_duration();
+    crate::metrics::STORED_STATE_CHANGES_TOTAL
+        .with_label_values(&["single"])
+        .inc_by(state_changes_count);
+    Ok(())
+}
+
+/// The storage the indexer writes the state changes to.
+/// Implemented by the ScyllaDB manager, by the embedded RocksDB storage with the `rocksdb` feature
+/// and by the PostgreSQL storage with the `postgres` feature
+#[async_trait::async_trait]
+pub(crate) trait StateIndexerStorage: Send + Sync {
+    async fn add_state_changes(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        key: &[u8],
+        value: &[u8],
+    ) -> anyhow::Result<()>;
+
+    async fn delete_state_changes(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        key: &[u8],
+    ) -> anyhow::Result<()>;
+
+    async fn add_access_key(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        public_key: &[u8],
+        access_key: &[u8],
+    ) -> anyhow::Result<()>;
+
+    async fn delete_access_key(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        public_key: &[u8],
+    ) -> anyhow::Result<()>;
+
+    /// Updates the list of the account access keys, `None` removes the key from the list
+    #[cfg(feature = "account_access_keys")]
+    async fn add_account_access_keys(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        public_key: &[u8],
+        access_key: Option<&[u8]>,
+    ) -> anyhow::Result<()>;
+
+    /// Updates the list of the account access keys with all the given keys at once,
+    /// `None` removes the key from the list
+    #[cfg(feature = "account_access_keys")]
+    async fn add_account_access_keys_list(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        access_keys: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    ) -> anyhow::Result<()> {
+        for (public_key, access_key) in access_keys {
+            self.add_account_access_keys(account_id.clone(), block_height, &public_key, access_key.as_deref())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn add_contract_code(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        code: &[u8],
+    ) -> anyhow::Result<()>;
+
+    async fn delete_contract_code(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()>;
+
+    async fn add_account(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        account: Vec<u8>,
+    ) -> anyhow::Result<()>;
+
+    async fn delete_account(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()>;
+
+    /// Stores the latest changes of the block.
+    /// By default the changes are stored one by one, the storages able to group the writes override it
+    async fn store_state_changes(
+        &self,
+        block_height: u64,
+        block_hash: CryptoHash,
+        state_changes: Vec<StateChangeRecord>,
+    ) -> anyhow::Result<()> {
+        store_state_changes_one_by_one(self, block_height, block_hash, state_changes).await
+    }
+
+    async fn add_block(&self, block_height: u64, block_hash: CryptoHash) -> anyhow::Result<()>;
+
+    async fn add_chunks(
+        &self,
+        block_height: u64,
+        chunks: Vec<(ChunkHash, ShardId, HeightIncluded)>,
+    ) -> anyhow::Result<()>;
+
+    /// Stores all the serialized state changes of the block and marks the block as complete
+    /// once all of them are stored
+    async fn add_block_state_changes(
+        &self,
+        block_height: u64,
+        block_hash: CryptoHash,
+        state_changes: Vec<Vec<u8>>,
+    ) -> anyhow::Result<()>;
+
+    async fn update_meta(&self, indexer_id: &str, block_height: u64) -> anyhow::Result<()>;
+
+    async fn get_last_processed_block_height(&self, indexer_id: &str) -> anyhow::Result<Option<u64>>;
+}
+
+/// The embedded storage for the single-node deployments.
+/// The blocking RocksDB calls run on the blocking thread pool, so the borrowed arguments are copied
+#[cfg(feature = "rocksdb")]
+#[async_trait::async_trait]
+impl StateIndexerStorage for database::embedded::RocksDBManager {
+    async fn add_state_changes(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        key: &[u8],
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        let (key, value) = (key.to_vec(), value.to_vec());
+        self.run_blocking(move |db| {
+            db.put_state_value(account_id.as_ref(), block_height, &block_hash.to_string(), &key, Some(&value))
+        })
+        .await
+    }
+
+    async fn delete_state_changes(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        key: &[u8],
+    ) -> anyhow::Result<()> {
+        let key = key.to_vec();
+        self.run_blocking(move |db| {
+            db.put_state_value(account_id.as_ref(), block_height, &block_hash.to_string(), &key, None)
+        })
+        .await
+    }
+
+    async fn add_access_key(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        public_key: &[u8],
+        access_key: &[u8],
+    ) -> anyhow::Result<()> {
+        let (public_key, access_key) = (public_key.to_vec(), access_key.to_vec());
+        self.run_blocking(move |db| {
+            db.put_access_key(
+                account_id.as_ref(),
+                block_height,
+                &block_hash.to_string(),
+                &public_key,
+                Some(&access_key),
+            )
+        })
+        .await
+    }
+
+    async fn delete_access_key(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        public_key: &[u8],
+    ) -> anyhow::Result<()> {
+        let public_key = public_key.to_vec();
+        self.run_blocking(move |db| {
+            db.put_access_key(account_id.as_ref(), block_height, &block_hash.to_string(), &public_key, None)
+        })
+        .await
+    }
+
+    /// The embedded storage builds the list of the account access keys from the `access_key` records
+    /// on read, so there is nothing to store separately
+    #[cfg(feature = "account_access_keys")]
+    async fn add_account_access_keys(
+        &self,
+        _account_id: AccountId,
+        _block_height: u64,
+        _public_key: &[u8],
+        _access_key: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn add_contract_code(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        code: &[u8],
+    ) -> anyhow::Result<()> {
+        let code = code.to_vec();
+        self.run_blocking(move |db| {
+            db.put_contract_code(account_id.as_ref(), block_height, &block_hash.to_string(), Some(&code))
+        })
+        .await
+    }
+
+    async fn delete_contract_code(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()> {
+        self.run_blocking(move |db| {
+            db.put_contract_code(account_id.as_ref(), block_height, &block_hash.to_string(), None)
+        })
+        .await
+    }
+
+    async fn add_account(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        account: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        self.run_blocking(move |db| {
+            db.put_account(account_id.as_ref(), block_height, &block_hash.to_string(), Some(&account))
+        })
+        .await
+    }
+
+    async fn delete_account(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()> {
+        self.run_blocking(move |db| db.put_account(account_id.as_ref(), block_height, &block_hash.to_string(), None))
+            .await
+    }
+
+    async fn add_block(&self, block_height: u64, block_hash: CryptoHash) -> anyhow::Result<()> {
+        self.run_blocking(move |db| db.add_block(&block_hash.to_string(), block_height))
+            .await
+    }
+
+    async fn add_chunks(
+        &self,
+        block_height: u64,
+        chunks: Vec<(ChunkHash, ShardId, HeightIncluded)>,
+    ) -> anyhow::Result<()> {
+        self.run_blocking(move |db| db.add_chunks(block_height, &chunks)).await
+    }
+
+    async fn add_block_state_changes(
+        &self,
+        block_height: u64,
+        _block_hash: CryptoHash,
+        state_changes: Vec<Vec<u8>>,
+    ) -> anyhow::Result<()> {
+        self.run_blocking(move |db| db.add_block_state_changes(block_height, &state_changes))
+            .await
+    }
+
+    async fn update_meta(&self, indexer_id: &str, block_height: u64) -> anyhow::Result<()> {
+        let indexer_id = indexer_id.to_string();
+        self.run_blocking(move |db| db.update_meta(&indexer_id, block_height))
+            .await
+    }
+
+    async fn get_last_processed_block_height(&self, indexer_id: &str) -> anyhow::Result<Option<u64>> {
+        let indexer_id = indexer_id.to_string();
+        self.run_blocking(move |db| db.get_last_processed_block_height(&indexer_id))
+            .await
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait::async_trait]
+impl StateIndexerStorage for database::postgres::PostgresDBManager {
+    async fn add_state_changes(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        key: &[u8],
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        self.put_state_value(account_id.as_ref(), block_height, &block_hash.to_string(), key, Some(value))
+            .await
+    }
+
+    async fn delete_state_changes(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        key: &[u8],
+    ) -> anyhow::Result<()> {
+        self.put_state_value(account_id.as_ref(), block_height, &block_hash.to_string(), key, None)
+            .await
+    }
+
+    async fn add_access_key(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        public_key: &[u8],
+        access_key: &[u8],
+    ) -> anyhow::Result<()> {
+        self.put_access_key(account_id.as_ref(), block_height, &block_hash.to_string(), public_key, Some(access_key))
+            .await
+    }
+
+    async fn delete_access_key(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        public_key: &[u8],
+    ) -> anyhow::Result<()> {
+        self.put_access_key(account_id.as_ref(), block_height, &block_hash.to_string(), public_key, None)
+            .await
+    }
+
+    /// The list of the account access keys is selected from the `state_changes_access_key` table
+    /// on read, so there is nothing to store separately
+    #[cfg(feature = "account_access_keys")]
+    async fn add_account_access_keys(
+        &self,
+        _account_id: AccountId,
+        _block_height: u64,
+        _public_key: &[u8],
+        _access_key: Option<&[u8]>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn add_contract_code(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        code: &[u8],
+    ) -> anyhow::Result<()> {
+        self.put_contract_code(account_id.as_ref(), block_height, &block_hash.to_string(), Some(code))
+            .await
+    }
+
+    async fn delete_contract_code(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()> {
+        self.put_contract_code(account_id.as_ref(), block_height, &block_hash.to_string(), None)
+            .await
+    }
+
+    async fn add_account(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+        account: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        self.put_account(account_id.as_ref(), block_height, &block_hash.to_string(), Some(&account))
+            .await
+    }
+
+    async fn delete_account(
+        &self,
+        account_id: AccountId,
+        block_height: u64,
+        block_hash: CryptoHash,
+    ) -> anyhow::Result<()> {
+        self.put_account(account_id.as_ref(), block_height, &block_hash.to_string(), None)
+            .await
+    }
+
+    async fn add_block(&self, block_height: u64, block_hash: CryptoHash) -> anyhow::Result<()> {
+        database::postgres::PostgresDBManager::add_block(self, &block_hash.to_string(), block_height).await
+    }
+
+    async fn add_chunks(
+        &self,
+        block_height: u64,
+        chunks: Vec<(ChunkHash, ShardId, HeightIncluded)>,
+    ) -> anyhow::Result<()> {
+        database::postgres::PostgresDBManager::add_chunks(self, block_height, &chunks).await
+    }
+
+    async fn add_block_state_changes(
+        &self,
+        block_height: u64,
+        block_hash: CryptoHash,
+        state_changes: Vec<Vec<u8>>,
+    ) -> anyhow::Result<()> {
+        database::postgres::PostgresDBManager::add_block_state_changes(
+            self,
+            block_height,
+            &block_hash.to_string(),
+            &state_changes,
+        )
+        .await
+    }
+
+    async fn update_meta(&self, indexer_id: &str, block_height: u64) -> anyhow::Result<()> {
+        database::postgres::PostgresDBManager::update_meta(
+            self,
+            database::postgres::STATE_INDEXER_SCHEMA,
+            indexer_id,
+            block_height,
+        )
+        .await
+    }
+
+    async fn get_last_processed_block_height(&self, indexer_id: &str) -> anyhow::Result<Option<u64>> {
+        database::postgres::PostgresDBManager::get_last_processed_block_height(
+            self,
+            database::postgres::STATE_INDEXER_SCHEMA,
+            indexer_id,
+        )
+        .await
+    }
+}
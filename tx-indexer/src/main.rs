@@ -20,24 +20,53 @@ async fn main() -> anyhow::Result<()> {
 
     let opts: Opts = Opts::parse();
 
+    #[cfg(feature = "rocksdb")]
+    if let Some(rocksdb_path) = &opts.rocksdb_path {
+        tracing::info!(target: INDEXER, "Opening the embedded RocksDB storage...");
+        let db_manager: std::sync::Arc<dyn storage::base::TxIndexerStorage> =
+            std::sync::Arc::new(database::embedded::RocksDBManager::open(
+                &rocksdb_path.join(database::embedded::TX_INDEXER_DIR),
+                &database::embedded::TX_INDEXER_COLUMN_FAMILIES,
+            )?);
+        let start_block_height = config::get_start_block_height(&opts, db_manager.as_ref()).await?;
+
+        // There is no cache of the collecting transactions to restore from in the embedded storage
+        tracing::info!(target: INDEXER, "Creating hash storage...");
+        let tx_collecting_storage = std::sync::Arc::new(storage::memory::HashStorage::new());
+        return run_indexer(opts, start_block_height, db_manager, tx_collecting_storage).await;
+    }
+
+    #[cfg(feature = "postgres")]
+    if let Some(postgres_url) = &opts.postgres_url {
+        tracing::info!(target: INDEXER, "Connecting to the PostgreSQL database...");
+        let db_manager: std::sync::Arc<dyn storage::base::TxIndexerStorage> = std::sync::Arc::new(
+            database::postgres::PostgresDBManager::connect(
+                postgres_url,
+                opts.postgres_max_connections,
+                opts.scylla_migrations,
+            )
+            .await?,
+        );
+        let start_block_height = config::get_start_block_height(&opts, db_manager.as_ref()).await?;
+
+        // The collecting transactions are not cached in PostgreSQL, same as in the embedded storage
+        tracing::info!(target: INDEXER, "Creating hash storage...");
+        let tx_collecting_storage = std::sync::Arc::new(storage::memory::HashStorage::new());
+        return run_indexer(opts, start_block_height, db_manager, tx_collecting_storage).await;
+    }
+
     tracing::info!(target: INDEXER, "Connecting to scylla db...");
     let scylla_db_client: std::sync::Arc<config::ScyllaDBManager> = std::sync::Arc::new(
         *config::ScyllaDBManager::new(
-            &opts.scylla_url,
-            opts.scylla_user.as_deref(),
-            opts.scylla_password.as_deref(),
-            opts.scylla_preferred_dc.as_deref(),
-            None,
-            opts.max_retry,
-            opts.strict_mode,
+            &opts.to_scylla_session_options(),
+            opts.scylla_migrations,
+            &opts.scylla_keyspaces,
         )
         .await?,
     );
-    let scylla_session = scylla_db_client.scylla_session().await;
 
-    let start_block_height = config::get_start_block_height(&opts, &scylla_session).await?;
-    tracing::info!(target: INDEXER, "Generating LakeConfig...");
-    let config: near_lake_framework::LakeConfig = opts.to_lake_config(start_block_height).await?;
+    let start_block_height =
+        config::get_start_block_height(&opts, scylla_db_client.as_ref()).await?;
 
     tracing::info!(target: INDEXER, "Creating hash storage...");
     let tx_collecting_storage = std::sync::Arc::new(
@@ -50,8 +79,26 @@ async fn main() -> anyhow::Result<()> {
         .await?,
     );
 
+    run_indexer(
+        opts,
+        start_block_height,
+        scylla_db_client,
+        tx_collecting_storage,
+    )
+    .await
+}
+
+async fn run_indexer(
+    opts: Opts,
+    start_block_height: u64,
+    db_manager: std::sync::Arc<dyn storage::base::TxIndexerStorage>,
+    tx_collecting_storage: std::sync::Arc<impl storage::base::TxCollectingStorage>,
+) -> anyhow::Result<()> {
+    tracing::info!(target: INDEXER, "Generating LakeConfig...");
+    let config: block_source::BlockSourceConfig = opts.to_lake_config(start_block_height).await?;
+
     tracing::info!(target: INDEXER, "Instantiating the stream...",);
-    let (sender, stream) = near_lake_framework::streamer(config);
+    let (sender, stream) = block_source::streamer(config);
 
     // Initiate metrics http server
     tokio::spawn(metrics::init_server(opts.port).expect("Failed to start metrics server"));
@@ -69,7 +116,7 @@ async fn main() -> anyhow::Result<()> {
             handle_streamer_message(
                 opts.chain_id.clone(),
                 streamer_message,
-                &scylla_db_client,
+                &db_manager,
                 &tx_collecting_storage,
                 &opts.indexer_id,
                 std::sync::Arc::clone(&stats),
@@ -96,7 +143,7 @@ async fn main() -> anyhow::Result<()> {
 async fn handle_streamer_message(
     chain_id: config::ChainId,
     streamer_message: near_indexer_primitives::StreamerMessage,
-    scylla_db_client: &std::sync::Arc<config::ScyllaDBManager>,
+    db_manager: &std::sync::Arc<dyn storage::base::TxIndexerStorage>,
     tx_collecting_storage: &std::sync::Arc<impl storage::base::TxCollectingStorage>,
     indexer_id: &str,
     stats: std::sync::Arc<tokio::sync::RwLock<metrics::Stats>>,
@@ -113,12 +160,12 @@ async fn handle_streamer_message(
     let tx_future = collector::index_transactions(
         chain_id,
         &streamer_message,
-        scylla_db_client,
+        db_manager,
         tx_collecting_storage,
     );
 
     let update_meta_future =
-        scylla_db_client.update_meta(indexer_id, streamer_message.block.header.height);
+        db_manager.update_meta(indexer_id, streamer_message.block.header.height);
 
     match futures::try_join!(tx_future, update_meta_future) {
         Ok(_) => tracing::debug!(
@@ -58,3 +58,36 @@ pub trait TxCollectingStorage {
         indexer_execution_outcome_with_receipt: near_indexer_primitives::IndexerExecutionOutcomeWithReceipt,
     ) -> anyhow::Result<()>;
 }
+
+/// The storage the indexer writes the collected transactions and receipts to
+#[async_trait::async_trait]
+pub trait TxIndexerStorage: Send + Sync {
+    async fn add_transaction(
+        &self,
+        transaction: readnode_primitives::TransactionDetails,
+        block_height: u64,
+    ) -> anyhow::Result<()>;
+
+    async fn add_receipt(
+        &self,
+        receipt_id: &str,
+        parent_tx_hash: &str,
+        block_height: u64,
+        shard_id: u64,
+    ) -> anyhow::Result<()>;
+
+    async fn update_meta(&self, indexer_id: &str, block_height: u64) -> anyhow::Result<()>;
+
+    /// Removes the saved transaction from the cache used to restore the collecting transactions
+    /// after interruption
+    async fn cache_delete_transaction(
+        &self,
+        transaction_hash: &str,
+        block_height: u64,
+    ) -> anyhow::Result<()>;
+
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<u64>>;
+}
@@ -0,0 +1,62 @@
+use borsh::BorshSerialize;
+
+use crate::storage::base::TxIndexerStorage;
+
+/// The embedded storage for the single-node deployments.
+/// The blocking RocksDB calls run on the blocking thread pool.
+/// The transactions are collected in memory only, so the transactions that were
+/// in progress at the moment of the interruption are not restored
+#[async_trait::async_trait]
+impl TxIndexerStorage for database::embedded::RocksDBManager {
+    async fn add_transaction(
+        &self,
+        transaction: readnode_primitives::TransactionDetails,
+        block_height: u64,
+    ) -> anyhow::Result<()> {
+        let transaction_details = transaction
+            .try_to_vec()
+            .expect("Failed to borsh-serialize the Transaction");
+        let transaction_hash = transaction.transaction.hash.to_string();
+        self.run_blocking(move |db| {
+            db.add_transaction(&transaction_hash, block_height, &transaction_details)
+        })
+        .await
+    }
+
+    async fn add_receipt(
+        &self,
+        receipt_id: &str,
+        parent_tx_hash: &str,
+        block_height: u64,
+        shard_id: u64,
+    ) -> anyhow::Result<()> {
+        let (receipt_id, parent_tx_hash) = (receipt_id.to_string(), parent_tx_hash.to_string());
+        self.run_blocking(move |db| {
+            db.add_receipt(&receipt_id, &parent_tx_hash, block_height, shard_id)
+        })
+        .await
+    }
+
+    async fn update_meta(&self, indexer_id: &str, block_height: u64) -> anyhow::Result<()> {
+        let indexer_id = indexer_id.to_string();
+        self.run_blocking(move |db| db.update_meta(&indexer_id, block_height))
+            .await
+    }
+
+    async fn cache_delete_transaction(
+        &self,
+        _transaction_hash: &str,
+        _block_height: u64,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<u64>> {
+        let indexer_id = indexer_id.to_string();
+        self.run_blocking(move |db| db.get_last_processed_block_height(&indexer_id))
+            .await
+    }
+}
@@ -1,5 +1,9 @@
 pub mod base;
 pub mod database;
+#[cfg(feature = "rocksdb")]
+pub mod embedded;
 pub mod memory;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 
 pub const STORAGE: &str = "storage_tx";
@@ -0,0 +1,74 @@
+use borsh::BorshSerialize;
+
+use crate::storage::base::TxIndexerStorage;
+
+/// The PostgreSQL storage.
+/// The transactions are collected in memory only, so the transactions that were
+/// in progress at the moment of the interruption are not restored
+#[async_trait::async_trait]
+impl TxIndexerStorage for database::postgres::PostgresDBManager {
+    async fn add_transaction(
+        &self,
+        transaction: readnode_primitives::TransactionDetails,
+        block_height: u64,
+    ) -> anyhow::Result<()> {
+        let transaction_details = transaction
+            .try_to_vec()
+            .expect("Failed to borsh-serialize the Transaction");
+        database::postgres::PostgresDBManager::add_transaction(
+            self,
+            &transaction.transaction.hash.to_string(),
+            block_height,
+            transaction.transaction.signer_id.as_ref(),
+            &transaction_details,
+        )
+        .await
+    }
+
+    async fn add_receipt(
+        &self,
+        receipt_id: &str,
+        parent_tx_hash: &str,
+        block_height: u64,
+        shard_id: u64,
+    ) -> anyhow::Result<()> {
+        database::postgres::PostgresDBManager::add_receipt(
+            self,
+            receipt_id,
+            parent_tx_hash,
+            block_height,
+            shard_id,
+        )
+        .await
+    }
+
+    async fn update_meta(&self, indexer_id: &str, block_height: u64) -> anyhow::Result<()> {
+        database::postgres::PostgresDBManager::update_meta(
+            self,
+            database::postgres::TX_INDEXER_SCHEMA,
+            indexer_id,
+            block_height,
+        )
+        .await
+    }
+
+    async fn cache_delete_transaction(
+        &self,
+        _transaction_hash: &str,
+        _block_height: u64,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get_last_processed_block_height(
+        &self,
+        indexer_id: &str,
+    ) -> anyhow::Result<Option<u64>> {
+        database::postgres::PostgresDBManager::get_last_processed_block_height(
+            self,
+            database::postgres::TX_INDEXER_SCHEMA,
+            indexer_id,
+        )
+        .await
+    }
+}